@@ -0,0 +1,55 @@
+extern crate micro_http_server;
+use micro_http_server::MicroHTTP;
+use std::{
+	io::Write,
+	net::TcpStream,
+	thread,
+	time::{Duration,Instant}
+};
+
+// Compares how long it takes until a client is accepted when polling with
+// a fixed sleep (as in the echo example) versus using next_client_timeout.
+
+const ROUNDS: u32 = 20;
+
+// Connect to the server after a short random-ish delay and send a request.
+fn spawn_client(port: u16, round: u32) -> thread::JoinHandle<Instant> {
+	thread::spawn(move || {
+		thread::sleep(Duration::from_millis(10 + (round as u64 * 37) % 90));
+		let mut connection = TcpStream::connect(("127.0.0.1", port)).expect("Could not reach server");
+		let sent_at = Instant::now();
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		sent_at
+	})
+}
+
+fn measure(port: u16, accept: impl Fn(&MicroHTTP) -> bool) -> Duration {
+	let mut server = MicroHTTP::new(("127.0.0.1", port)).expect("Could not create server.");
+	server.set_nonblocking(true).unwrap();
+
+	let mut total = Duration::from_secs(0);
+	for round in 0..ROUNDS {
+		let client = spawn_client(port, round);
+		while !accept(&server) {}
+		let accepted_at = Instant::now();
+		total += accepted_at - client.join().unwrap();
+	}
+	total / ROUNDS
+}
+
+pub fn main() {
+	let sleep_loop = measure(3010, |server| match server.next_client().unwrap() {
+		Some(_) => true,
+		None => {
+			thread::sleep(Duration::from_millis(50));
+			false
+		}
+	});
+	println!("next_client + 50ms sleep: {:?} average latency", sleep_loop);
+
+	let timeout = measure(3011, |server| server
+		.next_client_timeout(Duration::from_millis(50))
+		.unwrap()
+		.is_some());
+	println!("next_client_timeout(50ms): {:?} average latency", timeout);
+}
@@ -0,0 +1,51 @@
+extern crate micro_http_server;
+use micro_http_server::MicroHTTP;
+use std::{
+	io::{self,Read,Write},
+	net::TcpStream,
+	thread,
+	time::Instant
+};
+
+// Measures how the buffer size set with MicroHTTP::set_buffer_size affects
+// the throughput of large uploads and downloads over the loopback interface.
+
+const SIZE: usize = 64 * 1024 * 1024;
+
+// Upload SIZE bytes, then download SIZE bytes; returns the throughput of both in MiB/s.
+fn measure(buffer_size: usize) -> (f64, f64) {
+	let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	server.set_buffer_size(buffer_size);
+	let addr = server.local_addr().unwrap();
+
+	let client = thread::spawn(move || {
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", SIZE).as_bytes()).unwrap();
+		io::copy(&mut io::repeat(b'x').take(SIZE as u64), &mut connection).unwrap();
+
+		let mut response = Vec::with_capacity(SIZE + 100);
+		connection.read_to_end(&mut response).unwrap();
+		Instant::now()
+	});
+
+	let start = Instant::now();
+	let mut client_conn = server.next_client().unwrap().unwrap();
+	let uploaded = start.elapsed();
+	assert_eq!(SIZE, client_conn.body().len());
+
+	let start = Instant::now();
+	client_conn.respond_ok_chunked(io::repeat(b'y').take(SIZE as u64), SIZE).unwrap();
+	drop(client_conn);
+	let downloaded = client.join().unwrap() - start;
+
+	let mib = (SIZE / (1024 * 1024)) as f64;
+	(mib / uploaded.as_secs_f64(), mib / downloaded.as_secs_f64())
+}
+
+pub fn main() {
+	for buffer_size in [1024, 4 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024].iter() {
+		let (upload, download) = measure(*buffer_size);
+		println!("buffer size {:>8} bytes: upload {:>8.1} MiB/s, download {:>8.1} MiB/s",
+			buffer_size, upload, download);
+	}
+}
@@ -0,0 +1,92 @@
+extern crate micro_http_server;
+use micro_http_server::{Client, MicroHTTP};
+use std::{
+	io::{self,BufRead,BufReader,Read,Write},
+	net::{SocketAddr,TcpStream},
+	thread
+};
+
+// A streaming reverse proxy: request bodies are passed to the upstream server
+// while they arrive, and the upstream response is passed back the same way,
+// so neither has to fit into memory.
+//
+// Try it with: curl -T some_large_file http://127.0.0.1:3000/upload
+
+// Headers which only concern a single connection and must not be forwarded
+const HOP_BY_HOP: [&str; 6] = ["Connection", "Keep-Alive", "Expect", "TE", "Transfer-Encoding", "Upgrade"];
+
+fn forward(client: &mut Client, upstream_addr: SocketAddr) -> io::Result<usize> {
+	let mut upstream = TcpStream::connect(upstream_addr)?;
+
+	// Pass the request head on...
+	let mut head = format!("{} {} HTTP/1.0\r\n",
+		client.method().unwrap_or("GET"),
+		client.request().as_deref().unwrap_or("/"));
+	for (name, value) in client.headers_raw() {
+		if !HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+			head += &format!("{}: {}\r\n", name, value);
+		}
+	}
+	head += &format!("X-Forwarded-For: {}\r\n\r\n", client.addr().ip());
+	upstream.write_all(head.as_bytes())?;
+
+	// ...followed by the body, while it arrives. This sends "100 Continue" first
+	// if the client waits for it. The whole body must be read before responding:
+	// the response can only be sent once the body reader is gone.
+	io::copy(&mut client.body_reader()?, &mut upstream)?;
+
+	// Now pass the response back, again without buffering its body.
+	let mut response = BufReader::new(upstream);
+	let mut status_line = String::new();
+	response.read_line(&mut status_line)?;
+	let status = match status_line.trim_end().split_once(' ') {
+		Some((_, status)) => status.to_string(),
+		None => "502 Bad Gateway".to_string()
+	};
+
+	let mut headers = Vec::new();
+	let mut content_length = None;
+	loop {
+		let mut line = String::new();
+		if response.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+			break;
+		}
+		match line.trim_end().split_once(':') {
+			Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") =>
+				content_length = value.trim().parse::<usize>().ok(),
+			Some((name, _)) if HOP_BY_HOP.iter().any(|h| h.eq_ignore_ascii_case(name)) => {},
+			Some(_) => headers.push(line.trim_end().to_string()),
+			None => {}
+		}
+	}
+
+	match content_length {
+		Some(len) => client.respond_chunked(&status, response.take(len as u64), len, &headers),
+		None => client.respond_unframed(&status, response, &headers)
+	}
+}
+
+pub fn main() {
+	// A small upstream server which reports the size of what it got.
+	let upstream = MicroHTTP::new("127.0.0.1:0").expect("Could not create upstream server.");
+	let upstream_addr = upstream.local_addr().unwrap();
+	thread::spawn(move || upstream.run(|mut client| {
+		let size = client.read_body_into(&mut io::sink())?;
+		let text = format!("Upstream received {} bytes for {}\n", size, client.request().as_deref().unwrap_or("-"));
+		client.respond_text("200 OK", &text).map(|_| ())
+	}));
+
+	let mut server = MicroHTTP::new("127.0.0.1:3000").expect("Could not create server.");
+	server.set_stream_bodies(true);
+	println!("Proxying http://127.0.0.1:3000 to {}", upstream_addr);
+
+	let result = server.run(|mut client| {
+		if let Err(e) = forward(&mut client, upstream_addr) {
+			println!("Could not forward the request from {}: {}", client.addr(), e);
+			client.respond_error("502 Bad Gateway")?;
+		}
+		Ok::<(), io::Error>(())
+	});
+
+	println!("Server failed: {:?}", result);
+}
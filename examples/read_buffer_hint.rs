@@ -0,0 +1,76 @@
+extern crate micro_http_server;
+use micro_http_server::MicroHTTP;
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	io::{self,Read,Write},
+	net::TcpStream,
+	sync::atomic::{AtomicUsize, Ordering},
+	thread,
+	time::Instant
+};
+
+// Counts how often the memory of a large request body is reallocated while it
+// is read, depending on the limit set with MicroHTTP::set_read_buffer_hint.
+
+// Passes everything on to the system allocator, counting reallocations.
+struct CountingAllocator;
+
+static REALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+
+	unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+		REALLOCS.fetch_add(1, Ordering::Relaxed);
+		System.realloc(ptr, layout, new_size)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const SIZE: usize = 64 * 1024 * 1024;
+
+// Upload SIZE bytes; returns the number of reallocations on the server side
+// and the throughput in MiB/s.
+fn measure(hint: usize) -> (usize, f64) {
+	let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	server.set_read_buffer_hint(hint);
+	let addr = server.local_addr().unwrap();
+
+	let uploader = thread::spawn(move || {
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", SIZE).as_bytes()).unwrap();
+		io::copy(&mut io::repeat(b'x').take(SIZE as u64), &mut connection).unwrap();
+		connection.read_to_end(&mut Vec::new()).unwrap();
+	});
+
+	// The uploader reallocates nothing, so all reallocations happen on this side.
+	let reallocs = REALLOCS.load(Ordering::Relaxed);
+	let start = Instant::now();
+	let mut client = server.next_client().unwrap().unwrap();
+	let uploaded = start.elapsed();
+	let reallocs = REALLOCS.load(Ordering::Relaxed) - reallocs;
+	assert_eq!(SIZE, client.body().len());
+
+	client.respond_ok(&[]).unwrap();
+	drop(client);
+	uploader.join().unwrap();
+
+	let mib = (SIZE / (1024 * 1024)) as f64;
+	(reallocs, mib / uploaded.as_secs_f64())
+}
+
+pub fn main() {
+	for hint in [0, 1024 * 1024, SIZE].iter() {
+		let (reallocs, upload) = measure(*hint);
+		println!("read buffer hint {:>8} bytes: {:>3} reallocations, upload {:>8.1} MiB/s",
+			hint, reallocs, upload);
+	}
+}
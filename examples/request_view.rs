@@ -0,0 +1,72 @@
+extern crate micro_http_server;
+use micro_http_server::{MicroHTTP, RequestView};
+use std::{
+	alloc::{GlobalAlloc, Layout, System},
+	io::Write,
+	net::TcpStream,
+	sync::atomic::{AtomicUsize, Ordering}
+};
+
+// Counts the allocations needed to parse a typical request, once by accepting
+// it as a Client and once with RequestView::parse, which borrows from the buffer.
+
+// Passes everything on to the system allocator, counting allocations.
+struct CountingAllocator;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+	unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+		ALLOCS.fetch_add(1, Ordering::Relaxed);
+		System.alloc(layout)
+	}
+
+	unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+		System.dealloc(ptr, layout)
+	}
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const REQUEST: &[u8] = b"GET /api/items?page=2 HTTP/1.1\r\n\
+	Host: localhost:8080\r\n\
+	User-Agent: Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101 Firefox/128.0\r\n\
+	Accept: application/json\r\n\
+	Accept-Language: en-US,en;q=0.5\r\n\
+	Accept-Encoding: gzip, deflate, br\r\n\
+	Connection: keep-alive\r\n\
+	Cache-Control: no-cache\r\n\
+	Authorization: Bearer secret\r\n\r\n";
+
+const ROUNDS: usize = 1000;
+
+// Return the average number of allocations of f.
+fn allocations(mut f: impl FnMut()) -> f64 {
+	let before = ALLOCS.load(Ordering::Relaxed);
+	for _ in 0..ROUNDS {
+		f();
+	}
+	(ALLOCS.load(Ordering::Relaxed) - before) as f64 / ROUNDS as f64
+}
+
+pub fn main() {
+	let server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	let addr = server.local_addr().unwrap();
+
+	// The count for Client includes connecting and accepting the connection.
+	let client = allocations(|| {
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all(REQUEST).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(Some("HTTP/1.1"), client.http_version());
+	});
+
+	let view = allocations(|| {
+		let view = RequestView::parse(REQUEST).unwrap();
+		assert_eq!(Some("HTTP/1.1"), view.http_version());
+	});
+
+	println!("Client:      {:>5.1} allocations per request", client);
+	println!("RequestView: {:>5.1} allocations per request", view);
+}
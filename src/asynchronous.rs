@@ -0,0 +1,497 @@
+use std::{io, net::SocketAddr, sync::Arc, time::Duration};
+use tokio::{
+	io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+	net::{TcpListener, TcpStream, ToSocketAddrs}
+};
+
+use crate::client::{build_head, response_protocol, MAX_HEAD_SIZE};
+use crate::microhttp::{ErrorPage, Settings};
+use crate::protocol_version::ProtocolVersion;
+use crate::request_head::{find_header_end, parse_request, ParseLimits};
+use crate::status::forbids_body;
+
+/// Asynchronous counterpart of ``MicroHTTP`` which is driven by Tokio.
+///
+/// This is only available with the ``tokio`` feature enabled. The settings
+/// work like those of ``MicroHTTP``; response middleware isn't supported, since
+/// it works on a ``Client``.
+///
+/// # Example
+///
+/// ```no_run
+/// use micro_http_server::AsyncMicroHTTP;
+///
+/// # async fn serve() -> std::io::Result<()> {
+/// let server = AsyncMicroHTTP::new("127.0.0.1:3000").await?;
+///
+/// loop {
+///     let mut client = server.accept().await?;
+///     // Read the request in a task of its own, so a slow client
+///     // doesn't hold up the next ones.
+///     tokio::spawn(async move {
+///         if client.read_request().await {
+///             let _ = client.respond_ok("Hello from Tokio!".as_bytes()).await;
+///         }
+///     });
+/// }
+/// # }
+/// ```
+pub struct AsyncMicroHTTP {
+	// Internal listener which is used for the server part
+	listener: TcpListener,
+	// Settings which are shared with every incoming client
+	settings: Arc<Settings>,
+}
+
+impl AsyncMicroHTTP {
+	/// Create a new asynchronous server on the given interface.
+	///
+	/// Internally, this just tries to create a Tokio ``TcpListener``.
+	pub async fn new(interface: impl ToSocketAddrs) -> Result<AsyncMicroHTTP, io::Error> {
+		let listener = TcpListener::bind(interface).await?;
+		Ok(AsyncMicroHTTP { listener, settings: Arc::new(Settings::default()) })
+	}
+
+	/// Set the maximum size of a request body in bytes; ``None`` means unlimited (the default).
+	///
+	/// Requests with a larger ``Content-Length`` are answered with ``413 Payload Too Large``
+	/// by ``AsyncClient::read_request`` without reading the body.
+	pub fn set_max_request_size(&mut self, size: Option<usize>) {
+		Arc::make_mut(&mut self.settings).max_request_size = size;
+	}
+
+	/// Set the maximum length of the request target in bytes, see ``MicroHTTP::set_max_uri_length``.
+	pub fn set_max_uri_length(&mut self, length: usize) {
+		Arc::make_mut(&mut self.settings).max_uri_length = length;
+	}
+
+	/// Set the maximum number of headers a request may have, see ``MicroHTTP::set_max_header_count``.
+	pub fn set_max_header_count(&mut self, count: usize) {
+		Arc::make_mut(&mut self.settings).max_header_count = count;
+	}
+
+	/// Limit the time for receiving the whole request, see ``MicroHTTP::set_request_timeout``.
+	///
+	/// The time starts when ``AsyncClient::read_request`` is called. If the request
+	/// hasn't arrived in time, it is answered with ``408 Request Timeout``.
+	pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+		Arc::make_mut(&mut self.settings).request_timeout = timeout;
+	}
+
+	/// Select the protocol version of responses, see ``MicroHTTP::set_protocol_version``.
+	pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+		Arc::make_mut(&mut self.settings).protocol_version = version;
+	}
+
+	/// Set the maximum number of headers a response may have, see ``MicroHTTP::set_max_response_headers``.
+	pub fn set_max_response_headers(&mut self, count: usize) {
+		Arc::make_mut(&mut self.settings).max_response_headers = count;
+	}
+
+	/// Set the maximum total size of the headers of a response in bytes,
+	/// see ``MicroHTTP::set_max_response_header_size``.
+	pub fn set_max_response_header_size(&mut self, size: usize) {
+		Arc::make_mut(&mut self.settings).max_response_header_size = size;
+	}
+
+	/// Set the body and content type used when ``AsyncClient::read_request`` answers
+	/// a request with the given status code on its own, see ``MicroHTTP::set_error_page``.
+	pub fn set_error_page(&mut self, status: u16, body: &[u8], content_type: &str) {
+		Arc::make_mut(&mut self.settings).error_pages.insert(status, ErrorPage {
+			body: body.to_vec(),
+			content_type: content_type.to_string()
+		});
+	}
+
+	/// Wait for the next client.
+	///
+	/// Only the connection is accepted; its request is read by
+	/// ``AsyncClient::read_request``, which should run in a task of its own.
+	/// Errors are those of accepting the connection; errors while reading a
+	/// request only concern that client, see ``AsyncClient::read_error``.
+	pub async fn accept(&self) -> Result<AsyncClient, io::Error> {
+		let (stream, addr) = self.listener.accept().await?;
+		Ok(AsyncClient {
+			stream,
+			addr,
+			request: None,
+			http_version: None,
+			headers: Vec::new(),
+			body: Vec::new(),
+			error: None,
+			settings: self.settings.clone()
+		})
+	}
+}
+
+/// Asynchronous counterpart of ``Client``, returned by ``AsyncMicroHTTP::accept``.
+///
+/// If an instance of this struct is dropped, the connection is closed.
+#[derive(Debug)]
+pub struct AsyncClient {
+	stream: TcpStream,
+	addr: SocketAddr,
+	request: Option<String>,
+	http_version: Option<String>,
+	headers: Vec<(String, String)>,
+	body: Vec<u8>,
+	// Error which occurred while reading the request, see read_error
+	error: Option<io::Error>,
+	settings: Arc<Settings>
+}
+
+impl AsyncClient {
+	/// Read the request of the client: the head and, if it has a ``Content-Length``,
+	/// the body.
+	///
+	/// Returns false if the server already answered the request on its own and the
+	/// client should be dropped, i.e. if the request exceeds one of the limits set on
+	/// ``AsyncMicroHTTP`` or didn't arrive within the request timeout. Errors while
+	/// reading don't prevent answering the request; they are available through
+	/// ``read_error``, along with whatever was received.
+	pub async fn read_request(&mut self) -> bool {
+		let result = match self.settings.request_timeout {
+			Some(timeout) => match tokio::time::timeout(timeout, self.receive_request()).await {
+				Ok(result) => result,
+				Err(_) => {
+					warn!("Client {} didn't send its request in time", self.addr);
+					Err("408 Request Timeout")
+				}
+			},
+			None => self.receive_request().await
+		};
+
+		match result {
+			Ok(()) => true,
+			Err(status_code) => {
+				self.reject(status_code).await;
+				false
+			}
+		}
+	}
+
+	// Read the head and the body of the request. Returns the status of the
+	// response if the request is rejected.
+	async fn receive_request(&mut self) -> Result<(), &'static str> {
+		// Read until the header section is complete, the client stops sending
+		// or the head gets too large.
+		let mut data = Vec::new();
+		let mut buf = [0u8; 4096];
+		while find_header_end(&data).is_none() && data.len() < MAX_HEAD_SIZE {
+			match self.stream.read(&mut buf).await {
+				Ok(0) => break,
+				Ok(bytes_read) => data.extend_from_slice(&buf[..bytes_read]),
+				Err(e) => {
+					warn!("Could not read the request from {}: {}", self.addr, e);
+					self.error = Some(e);
+					break;
+				}
+			}
+		}
+
+		let limits = ParseLimits {
+			max_target_len: self.settings.max_uri_length,
+			max_header_count: self.settings.max_header_count,
+			max_head_len: MAX_HEAD_SIZE
+		};
+		let head = match parse_request(&data, &limits) {
+			Ok(head) => head,
+			Err(e) => {
+				warn!("Rejecting request from {}: {}", self.addr, e);
+				return Err(e.status());
+			}
+		};
+		self.body = data.split_off(head.len);
+		self.request = head.target;
+		self.http_version = head.http_version;
+		self.headers = head.headers;
+
+		if let (Some(content_length), None) = (self.content_length(), &self.error) {
+			if self.settings.max_request_size.is_some_and(|max| content_length > max) {
+				return Err("413 Payload Too Large");
+			}
+
+			if self.body.len() < content_length {
+				if let Err(e) = self.read_body(content_length).await {
+					warn!("Could not read the body from {}: {}", self.addr, e);
+					self.error = Some(e);
+				}
+			}
+			self.body.truncate(content_length);
+
+			if self.error.is_none() && self.body.len() < content_length {
+				warn!("Client {} sent only {} of {} body bytes", self.addr, self.body.len(), content_length);
+				self.error = Some(io::Error::new(
+					io::ErrorKind::UnexpectedEof,
+					format!("Client sent only {} of {} body bytes", self.body.len(), content_length)));
+			}
+		}
+
+		Ok(())
+	}
+
+	// Read the rest of the body, after sending "100 Continue" if the client waits for it.
+	async fn read_body(&mut self, content_length: usize) -> io::Result<()> {
+		let expects_continue = self.header("Expect")
+			.is_some_and(|v| v.eq_ignore_ascii_case("100-continue"));
+		if expects_continue {
+			self.stream.write_all(b"HTTP/1.1 100 Continue\r\n\r\n").await?;
+		}
+
+		let remaining = (content_length - self.body.len()) as u64;
+		(&mut self.stream).take(remaining).read_to_end(&mut self.body).await?;
+		Ok(())
+	}
+
+	// Answer a request which is not handed to the application, with the error
+	// page for its status if there is one.
+	async fn reject(&mut self, status_code: &str) {
+		let settings = self.settings.clone();
+		let status = status_code.split(' ').next()
+			.and_then(|code| code.parse::<u16>().ok());
+		let result = match status.and_then(|code| settings.error_pages.get(&code)) {
+			Some(page) => {
+				let headers = vec!(format!("Content-Type: {}", page.content_type));
+				self.respond(status_code, &page.body, &headers).await
+			},
+			None => self.respond(status_code, &[], &[]).await
+		};
+		if let Err(e) = result {
+			warn!("Could not send the rejection to {}: {}", self.addr, e);
+		}
+	}
+
+	/// Return the address of the requesting client, for example "1.2.3.4:9435".
+	pub fn addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	/// Return the request the client made or None if the client
+	/// didn't make any or an invalid one.
+	///
+	/// See ``Client::request`` for details.
+	pub fn request(&self) -> &Option<String> {
+		&self.request
+	}
+
+	/// Return the protocol version from the request line, e.g. ``HTTP/1.1``,
+	/// or None if the client didn't send one.
+	pub fn http_version(&self) -> Option<&str> {
+		self.http_version.as_deref()
+	}
+
+	/// Return all headers the client sent as ``(name, value)`` pairs,
+	/// in the exact order and casing they were received.
+	pub fn headers_raw(&self) -> &[(String, String)] {
+		&self.headers
+	}
+
+	/// Return the value of the first header with the given name (compared
+	/// case-insensitively) or None if the client didn't send it.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	/// Return the value of the ``Content-Length`` header or None if the client
+	/// didn't send one or an invalid one.
+	pub fn content_length(&self) -> Option<usize> {
+		self.header("Content-Length")
+			.and_then(|v| v.parse().ok())
+	}
+
+	/// Return the error which occurred while reading the request, or None if it
+	/// was read without problems.
+	///
+	/// This is e.g. an ``UnexpectedEof`` error if the client closed the connection
+	/// before sending the whole body announced by ``Content-Length``; ``body``
+	/// then only returns the part which was received. See ``Client::read_error``.
+	pub fn read_error(&self) -> Option<&io::Error> {
+		self.error.as_ref()
+	}
+
+	/// Return the body the client sent along with its request.
+	pub fn body(&self) -> &[u8] {
+		&self.body
+	}
+
+	/// Send a HTTP 200 OK response to the client + the provided data.
+	pub async fn respond_ok(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.respond("200 OK", data, &[]).await
+	}
+
+	/// Send response data to the client, see ``Client::respond``.
+	pub async fn respond(
+		&mut self,
+		status_code: &str,
+		data: &[u8],
+		headers: &[String]) -> io::Result<usize>
+	{
+		self.respond_chunked(status_code, data, data.len(), headers).await
+	}
+
+	/// Send response data read from ``data`` to the client, see ``Client::respond_chunked``.
+	///
+	/// At most ``content_size`` bytes are sent. If ``data`` ends before, the connection
+	/// is shut down and an ``UnexpectedEof`` error is returned. Statuses which don't
+	/// allow a body, like ``204 No Content``, are sent without ``Content-Length``
+	/// and fail with ``InvalidInput`` if ``content_size`` isn't 0.
+	pub async fn respond_chunked(
+		&mut self,
+		status_code: &str,
+		mut data: impl AsyncRead + Unpin,
+		content_size: usize,
+		headers: &[String]) -> io::Result<usize>
+	{
+		if forbids_body(status_code) && content_size > 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("A {} response must not have a body", status_code)));
+		}
+
+		let protocol = response_protocol(&self.settings, self.http_version());
+		let head = build_head(&self.settings, protocol, status_code, Some(content_size), headers)?;
+		self.stream.write_all(head.as_bytes()).await?;
+
+		// Never send more than announced; if data ends early, the client would
+		// wait for the missing bytes forever.
+		let body_len = tokio::io::copy(&mut (&mut data).take(content_size as u64), &mut self.stream).await?;
+		if (body_len as usize) < content_size {
+			self.stream.shutdown().await?;
+			return Err(io::Error::new(
+				io::ErrorKind::UnexpectedEof,
+				format!("Body ended early, {} of {} bytes are missing", content_size - body_len as usize, content_size)));
+		}
+		Ok(head.len() + body_len as usize)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::AsyncMicroHTTP;
+	use crate::ProtocolVersion;
+	use std::{io, time::Duration};
+	use tokio::{io::{AsyncReadExt, AsyncWriteExt}, net::TcpStream};
+
+	#[tokio::test]
+	async fn body_shorter_than_declared() {
+		let server = AsyncMicroHTTP::new("127.0.0.1:0").await.unwrap();
+		let addr = server.listener.local_addr().unwrap();
+
+		let mut connection = TcpStream::connect(addr).await.unwrap();
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nSHORT".as_bytes()).await.unwrap();
+		connection.shutdown().await.unwrap();
+
+		let mut client = server.accept().await.unwrap();
+		assert!(client.read_request().await);
+		assert_eq!(io::ErrorKind::UnexpectedEof, client.read_error().unwrap().kind());
+		assert_eq!(b"SHORT", client.body());
+		client.respond("400 Bad Request", &[], &[]).await.unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).await.unwrap();
+		assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[tokio::test]
+	async fn slow_client() {
+		let server = AsyncMicroHTTP::new("127.0.0.1:0").await.unwrap();
+		let addr = server.listener.local_addr().unwrap();
+
+		// The first client doesn't finish its request, which must not keep
+		// the second one from being accepted and answered.
+		let mut slow = TcpStream::connect(addr).await.unwrap();
+		slow.write_all("GET /slow HTTP/1.1\r\n".as_bytes()).await.unwrap();
+		let mut slow_client = server.accept().await.unwrap();
+
+		let mut fast = TcpStream::connect(addr).await.unwrap();
+		fast.write_all("GET /fast HTTP/1.1\r\n\r\n".as_bytes()).await.unwrap();
+		let mut client = server.accept().await.unwrap();
+		assert!(client.read_request().await);
+		assert_eq!(&Some("/fast".to_string()), client.request());
+		client.respond_ok(b"fast").await.unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		fast.read_to_string(&mut buf).await.unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\nfast", buf);
+
+		slow.write_all("\r\n".as_bytes()).await.unwrap();
+		assert!(slow_client.read_request().await);
+		assert_eq!(&Some("/slow".to_string()), slow_client.request());
+	}
+
+	#[tokio::test]
+	async fn limits() {
+		let mut server = AsyncMicroHTTP::new("127.0.0.1:0").await.unwrap();
+		server.set_max_request_size(Some(10));
+		server.set_max_uri_length(8);
+		server.set_max_header_count(2);
+		let addr = server.listener.local_addr().unwrap();
+
+		// A head which never ends; the server reads exactly up to its limit, so
+		// no data is left unread when the connection is closed.
+		let mut huge_header = "GET / HTTP/1.1\r\nX-Big: ".to_string();
+		huge_header += &"a".repeat(64 * 1024 - huge_header.len());
+		for (request, expected) in [
+			(huge_header.as_str(), "431 Request Header Fields Too Large"),
+			("POST / HTTP/1.1\r\nContent-Length: 100\r\n\r\n", "413 Payload Too Large"),
+			("GET /a/b/c/d/e HTTP/1.1\r\n\r\n", "414 URI Too Long"),
+			("GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\nC: 3\r\n\r\n", "431 Request Header Fields Too Large"),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).await.unwrap();
+			let mut client = server.accept().await.unwrap();
+			let (written, rejected) = tokio::join!(connection.write_all(request.as_bytes()), client.read_request());
+			written.unwrap();
+			assert!(!rejected);
+			drop(client);
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).await.unwrap();
+			assert_eq!(format!("HTTP/1.0 {}\r\nContent-Length: 0\r\n\r\n", expected), buf);
+		}
+	}
+
+	#[tokio::test]
+	async fn request_timeout() {
+		let mut server = AsyncMicroHTTP::new("127.0.0.1:0").await.unwrap();
+		server.set_request_timeout(Some(Duration::from_millis(100)));
+		server.set_error_page(408, b"Too slow", "text/plain");
+		let addr = server.listener.local_addr().unwrap();
+
+		let mut connection = TcpStream::connect(addr).await.unwrap();
+		connection.write_all("GET / HTTP/1.1\r\nHost: a\r\n".as_bytes()).await.unwrap();
+		let mut client = server.accept().await.unwrap();
+		assert!(!client.read_request().await);
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).await.unwrap();
+		assert_eq!("HTTP/1.0 408 Request Timeout\r\nContent-Length: 8\r\nContent-Type: text/plain\r\n\r\nToo slow", buf);
+	}
+
+	#[tokio::test]
+	async fn response_head() {
+		let mut server = AsyncMicroHTTP::new("127.0.0.1:0").await.unwrap();
+		server.set_protocol_version(ProtocolVersion::Http11);
+		server.set_max_response_headers(1);
+		let addr = server.listener.local_addr().unwrap();
+
+		let mut connection = TcpStream::connect(addr).await.unwrap();
+		connection.write_all("DELETE /item HTTP/1.1\r\n\r\n".as_bytes()).await.unwrap();
+		let mut client = server.accept().await.unwrap();
+		assert!(client.read_request().await);
+
+		let too_many = vec!("A: 1".to_string(), "B: 2".to_string());
+		assert_eq!(io::ErrorKind::InvalidInput, client.respond("200 OK", &[], &too_many).await.unwrap_err().kind());
+		assert_eq!(io::ErrorKind::InvalidInput, client.respond("204 No Content", b"body", &[]).await.unwrap_err().kind());
+		client.respond("204 No Content", &[], &[]).await.unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).await.unwrap();
+		assert_eq!("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n", buf);
+	}
+}
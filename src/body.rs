@@ -0,0 +1,65 @@
+use std::{
+	io,
+	io::Read,
+	net::TcpStream
+};
+
+/// Reader for the body of a request, returned by ``Client::body_reader``.
+///
+/// It first yields the part of the body which has already been received and
+/// then reads the rest from the connection, stopping at the ``Content-Length``
+/// the client announced. If the client closes the connection before sending
+/// the whole body, reading fails with ``UnexpectedEof``.
+#[derive(Debug)]
+pub struct BodyReader<'a> {
+	stream: &'a mut TcpStream,
+	buffered: io::Cursor<Vec<u8>>,
+	remaining: &'a mut usize,
+	expected_len: Option<usize>,
+}
+
+impl<'a> BodyReader<'a> {
+	// remaining is the number of body bytes which are still on the connection;
+	// it is kept up to date while reading.
+	pub(crate) fn new(
+		stream: &'a mut TcpStream,
+		buffered: Vec<u8>,
+		remaining: &'a mut usize,
+		expected_len: Option<usize>) -> BodyReader<'a>
+	{
+		BodyReader {
+			stream,
+			buffered: io::Cursor::new(buffered),
+			remaining,
+			expected_len,
+		}
+	}
+
+	/// Return the total size of the body in bytes, as announced by the client
+	/// in the ``Content-Length`` header, or None if it didn't announce one.
+	///
+	/// This is useful to preallocate memory or to show the progress of an upload.
+	pub fn expected_len(&self) -> Option<usize> {
+		self.expected_len
+	}
+}
+
+impl Read for BodyReader<'_> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let bytes_read = self.buffered.read(buf)?;
+		if bytes_read > 0 || *self.remaining == 0 || buf.is_empty() {
+			return Ok(bytes_read);
+		}
+
+		let max = buf.len().min(*self.remaining);
+		match self.stream.read(&mut buf[..max])? {
+			0 => Err(io::Error::new(
+				io::ErrorKind::UnexpectedEof,
+				format!("Client closed the connection with {} body bytes missing", self.remaining))),
+			bytes_read => {
+				*self.remaining -= bytes_read;
+				Ok(bytes_read)
+			}
+		}
+	}
+}
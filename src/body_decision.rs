@@ -0,0 +1,31 @@
+// Decisions about request bodies before they are read, see
+// MicroHTTP::set_pre_body_handler.
+
+use std::{fmt, sync::Arc};
+
+use crate::client::Client;
+
+/// What to do with the body of a request, returned by the handler set with
+/// ``MicroHTTP::set_pre_body_handler``.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BodyDecision {
+	/// Read the body as usual, sending ``100 Continue`` if the client waits for it.
+	Accept,
+	/// Answer with the given status code, e.g. ``401`` or ``415``, without reading the body.
+	Reject(u16),
+	/// Answer with ``307 Temporary Redirect`` to the given location, without
+	/// reading the body. The client repeats the upload there.
+	Redirect(String),
+}
+
+type Handler = Arc<dyn Fn(&Client) -> BodyDecision + Send + Sync>;
+
+// The handler in the settings, which need to implement Debug.
+#[derive(Clone)]
+pub(crate) struct PreBodyHandler(pub(crate) Handler);
+
+impl fmt::Debug for PreBodyHandler {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("PreBodyHandler")
+	}
+}
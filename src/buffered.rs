@@ -0,0 +1,67 @@
+use std::{io, io::Write};
+
+use crate::client::Client;
+
+/// Builder for a response whose body is collected in memory first, returned by
+/// ``Client::begin_buffered``.
+///
+/// Append to the body with the methods of ``Write``, e.g. ``write!``. Nothing is
+/// sent until ``finish`` is called, which sends the response with the correct
+/// ``Content-Length``. If the builder is dropped without it, nothing is sent at all.
+///
+/// The body may not grow beyond the limit passed to ``Client::begin_buffered``;
+/// writes which would exceed it fail with ``InvalidInput``.
+#[derive(Debug)]
+pub struct BufferedResponse<'a> {
+	client: &'a mut Client,
+	status_code: String,
+	headers: Vec<String>,
+	body: Vec<u8>,
+	max_size: usize,
+}
+
+impl<'a> BufferedResponse<'a> {
+	pub(crate) fn new(client: &'a mut Client, status_code: &str, headers: &[&str], max_size: usize) -> BufferedResponse<'a> {
+		BufferedResponse {
+			client,
+			status_code: status_code.to_string(),
+			headers: headers.iter().map(|h| h.to_string()).collect(),
+			body: Vec::new(),
+			max_size,
+		}
+	}
+
+	/// Return the size of the body collected so far in bytes.
+	pub fn len(&self) -> usize {
+		self.body.len()
+	}
+
+	/// Return whether nothing has been added to the body yet.
+	pub fn is_empty(&self) -> bool {
+		self.body.is_empty()
+	}
+
+	/// Send the response, with a ``Content-Length`` matching the collected body.
+	///
+	/// Returns the number of bytes written, like ``Client::respond``.
+	pub fn finish(self) -> io::Result<usize> {
+		self.client.respond(&self.status_code, &self.body, &self.headers)
+	}
+}
+
+impl Write for BufferedResponse<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.body.len() + buf.len() > self.max_size {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("Response body would exceed the limit of {} bytes", self.max_size)));
+		}
+
+		self.body.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,147 @@
+use std::{
+	io,
+	io::Write,
+	net::Shutdown,
+	time::{Duration, Instant}
+};
+
+use crate::client::{abort_response, check_header_line};
+use crate::output::Output;
+
+/// Writer for a response whose body is sent in chunks, returned by ``Client::begin_chunked``.
+///
+/// Each call to ``write_chunk`` is sent as a separate HTTP chunk and flushed
+/// right away, so you decide where the chunk boundaries are, e.g. one chunk
+/// per log line. Call ``finish`` to end the response; if the writer is dropped
+/// without it or ``abort`` is called, the client can tell that the response is
+/// incomplete.
+///
+/// Chunked transfer coding needs an ``HTTP/1.1`` response, see ``Client::begin_chunked``.
+/// Otherwise, the data is sent as it is and the end of the body is signalled by
+/// closing the connection.
+#[derive(Debug)]
+pub struct ChunkedResponse<'a> {
+	output: Output<'a>,
+	chunked: bool,
+	trailers: bool,
+	shutdown: bool,
+	finished: bool,
+	bytes_written: usize,
+	received_at: Instant,
+	handling_time: &'a mut Option<Duration>,
+}
+
+impl<'a> ChunkedResponse<'a> {
+	// The response head has already been written to the stream; bytes_written
+	// is its size. trailers tells whether the client accepts trailer fields,
+	// shutdown whether the sending side is shut down after the last chunk.
+	// handling_time is set once the response is finished.
+	pub(crate) fn new(
+		output: Output<'a>,
+		chunked: bool,
+		trailers: bool,
+		shutdown: bool,
+		bytes_written: usize,
+		received_at: Instant,
+		handling_time: &'a mut Option<Duration>) -> ChunkedResponse<'a>
+	{
+		ChunkedResponse {
+			output,
+			chunked,
+			trailers,
+			shutdown,
+			finished: false,
+			bytes_written,
+			received_at,
+			handling_time,
+		}
+	}
+
+	/// Send the given data as one chunk and flush it.
+	///
+	/// Empty data is skipped, since an empty chunk would end the response.
+	/// Returns the number of bytes written, including the chunk framing.
+	pub fn write_chunk(&mut self, data: &[u8]) -> io::Result<usize> {
+		if data.is_empty() {
+			return Ok(0);
+		}
+
+		let mut chunk = Vec::with_capacity(data.len() + 12);
+		match self.chunked {
+			true => {
+				chunk.extend(format!("{:x}\r\n", data.len()).as_bytes());
+				chunk.extend(data);
+				chunk.extend(b"\r\n");
+			},
+			false => chunk.extend(data)
+		}
+
+		self.output.write_all(&chunk)?;
+		self.output.flush()?;
+		self.bytes_written += chunk.len();
+		Ok(chunk.len())
+	}
+
+	/// End the response with the terminating zero-size chunk, followed by the
+	/// given trailer fields, e.g. ``&["Expires: 0"]``. May be empty.
+	///
+	/// Trailers are only sent if the client accepts them, see ``Client::accepts_trailers``;
+	/// otherwise they are dropped. For HTTP/1.0 clients, trailers can't be sent at all;
+	/// the sending side of the connection is shut down instead.
+	///
+	/// Returns the number of bytes written for the whole response.
+	pub fn finish(mut self, trailers: &[&str]) -> io::Result<usize> {
+		match self.chunked {
+			true => {
+				for t in trailers {
+					check_header_line(t)?;
+				}
+
+				let mut end = String::from("0\r\n");
+				match self.trailers {
+					true => for t in trailers {
+						end += &format!("{}\r\n", t);
+					},
+					false if !trailers.is_empty() =>
+						debug!("Dropping trailers, the client doesn't accept them: {:?}", trailers),
+					false => {}
+				}
+				end += "\r\n";
+				self.output.write_all(end.as_bytes())?;
+				self.output.flush()?;
+				self.bytes_written += end.len();
+				if self.shutdown {
+					self.output.stream.shutdown(Shutdown::Write)?;
+				}
+			},
+			false => {
+				if !trailers.is_empty() {
+					debug!("Dropping trailers for an HTTP/1.0 client: {:?}", trailers);
+				}
+				self.output.stream.shutdown(Shutdown::Write)?;
+			}
+		}
+
+		self.finished = true;
+		*self.handling_time = Some(self.received_at.elapsed());
+		Ok(self.bytes_written)
+	}
+
+	/// Abort the response, e.g. because the source of the data failed halfway through.
+	///
+	/// The client is told that the response is incomplete: the connection is closed
+	/// without the terminating zero-size chunk, or, for HTTP/1.0 clients, reset once
+	/// the ``Client`` is dropped, since a closed connection would look like the end of
+	/// the body to them. The same happens if the writer is dropped without ``finish``.
+	pub fn abort(self) {
+		// Dropping does all the work.
+	}
+}
+
+impl Drop for ChunkedResponse<'_> {
+	fn drop(&mut self) {
+		if !self.finished {
+			abort_response(self.output.stream, self.chunked);
+		}
+	}
+}
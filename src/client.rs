@@ -1,9 +1,33 @@
 use std::{
-	io,io::Read,io::Write,
-	net::{SocketAddr,TcpStream},
-	str
+	collections::HashMap,
+	io,io::Read,io::Seek,io::Write,
+	fs::{self,File},
+	net::{IpAddr,Shutdown,SocketAddr,TcpStream},
+	path::Path,
+	str,
+	sync::Arc,
+	time::{Duration,Instant,SystemTime}
 };
 use super::os_windows;
+use crate::body::BodyReader;
+use crate::body_decision::BodyDecision;
+use crate::buffered::BufferedResponse;
+use crate::chunked::ChunkedResponse;
+use crate::compression::{self, Coding, DecompressError};
+use crate::content_type::ContentType;
+use crate::httpdate::parse_http_date;
+use crate::microhttp::Settings;
+use crate::multipart::{self, MultipartError, UploadedPart};
+use crate::output::{Output, OutputFilter};
+use crate::protocol_version::ProtocolVersion;
+use crate::range::{parse_range, ByteRange};
+use crate::response_parts::ResponseParts;
+use crate::request_head::{find_header_end, parse_request, ParseLimits, ParseWarning, RequestHead};
+use crate::request_url::RequestUrl;
+use crate::retry_after::RetryAfter;
+use crate::static_files::{self, Lookup};
+use crate::status::{forbids_body, reason_phrase};
+use crate::urlencoded::{parse_urlencoded, percent_decode, percent_encode};
 
 /// This struct represents a client which has connected to the µHTTP server.microhttp
 ///
@@ -12,20 +36,44 @@ use super::os_windows;
 pub struct Client {
 	stream: TcpStream,
 	addr: SocketAddr,
-	request: Option<String>
+	local_addr: SocketAddr,
+	method: Option<String>,
+	request_line: Option<String>,
+	request: Option<String>,
+	fragment: Option<String>,
+	headers: Vec<(String, String)>,
+	warnings: Vec<ParseWarning>,
+	body: Vec<u8>,
+	// The body as it was sent, if it has been decompressed
+	raw_body: Option<Vec<u8>>,
+	body_remaining: usize,
+	// Size of the header section and number of bytes read from the connection
+	// by Client::new; body_reader reads the remaining body later on.
+	header_bytes: usize,
+	bytes_read: usize,
+	body_remaining_at_start: usize,
+	continue_sent: bool,
+	output_filter: Option<OutputFilter>,
+	// Values of the Set-Cookie headers added to the response
+	cookies: Vec<String>,
+	http_version: Option<String>,
+	received_at: Instant,
+	handling_time: Option<Duration>,
+	// Error which occurred while reading the request, see read_error
+	error: Option<io::Error>,
+	settings: Arc<Settings>
 }
 
 // Read all data from an incoming stream
-fn read_all(stream: &mut TcpStream) -> Result<Vec<u8>,io::ErrorKind> {
+fn read_all(stream: &mut TcpStream, buffer_size: usize) -> Result<Vec<u8>,io::ErrorKind> {
 	let mut result = Vec::new();
+	let mut buf = vec![0u8; buffer_size];
 
 	loop {
-		const BUF_SIZE: usize = 4096;
-		let mut buf: [u8; BUF_SIZE] = [0u8; BUF_SIZE];
 		match stream.read(&mut buf) {
 			Ok(val) => if val > 0 {
-				result.append(&mut Vec::from(&buf[0..val]));
-				if val < BUF_SIZE {
+				result.extend_from_slice(&buf[0..val]);
+				if val < buffer_size {
 					return Ok(result);
 				}
 			} else {
@@ -45,170 +93,2449 @@ fn read_all(stream: &mut TcpStream) -> Result<Vec<u8>,io::ErrorKind> {
 	}
 }
 
-fn extract_request_url(buf: &[u8]) -> Option<String> {
-	let s = str::from_utf8(buf).unwrap();
+// Read the request head, i.e. everything up to the end of the header section,
+// even if it arrives in several packets. Any data read beyond that is returned
+// as well. Stops early if the client closes the connection, if the request is
+// an HTTP/0.9 style request without headers or if the head gets too large.
+fn read_head(stream: &mut TcpStream, buffer_size: usize, deadline: Option<Instant>) -> Result<Vec<u8>,io::ErrorKind> {
+	set_read_deadline(stream, deadline).map_err(|e| e.kind())?;
+	let mut data = read_all(stream, buffer_size)?;
 
-	for line in s.split("\r\n") {
-		if line.starts_with("GET ") {
-			let components = line.split(" ").collect::<Vec<&str>>();
-			if components.len() < 2 {
-				warn!("Invalid GET line: {}", line);
-				continue;
+	while find_header_end(&data).is_none() && data.len() < MAX_HEAD_SIZE {
+		let line_end = data.windows(2).position(|w| w == b"\r\n");
+		if !data.starts_with(b"PROXY ") && line_end.is_some_and(|end| data[..end].split(|&c| c == b' ').count() < 3) {
+			break;
+		}
+
+		set_read_deadline(stream, deadline).map_err(|e| e.kind())?;
+		let more = read_all(stream, buffer_size)?;
+		if more.is_empty() {
+			// read_all returns nothing if a read timed out.
+			if deadline.is_some_and(|d| Instant::now() >= d) {
+				return Err(io::ErrorKind::TimedOut);
+			}
+			break;
+		}
+		data.extend(more);
+	}
+
+	Ok(data)
+}
+
+// Limit the next read from the stream to the time left until the deadline, if any.
+// Fails with TimedOut if the deadline has passed already.
+fn set_read_deadline(stream: &TcpStream, deadline: Option<Instant>) -> io::Result<()> {
+	let deadline = match deadline {
+		Some(deadline) => deadline,
+		None => return Ok(())
+	};
+
+	let left = deadline.saturating_duration_since(Instant::now());
+	match left.is_zero() {
+		true => Err(io::Error::new(io::ErrorKind::TimedOut, "Request not received in time")),
+		false => stream.set_read_timeout(Some(left))
+	}
+}
+
+// Stop waiting for the rest of a request head larger than this.
+pub(crate) const MAX_HEAD_SIZE: usize = 64 * 1024;
+
+// Limit for decompressed request bodies if there is no max_request_size.
+const MAX_DECODED_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+// Set SO_LINGER on the stream, since TcpStream::set_linger isn't stable yet.
+#[cfg(unix)]
+fn set_linger(stream: &TcpStream, linger: Duration) -> io::Result<()> {
+	use std::os::unix::io::AsRawFd;
+
+	let value = libc::linger {
+		l_onoff: 1,
+		l_linger: linger.as_secs().min(libc::c_int::MAX as u64) as libc::c_int
+	};
+	let result = unsafe {
+		libc::setsockopt(
+			stream.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_LINGER,
+			&value as *const libc::linger as *const libc::c_void,
+			std::mem::size_of::<libc::linger>() as libc::socklen_t)
+	};
+
+	match result {
+		0 => Ok(()),
+		_ => Err(io::Error::last_os_error())
+	}
+}
+
+// Send up to count bytes from the file's current position straight to the
+// socket with sendfile(2), without copying them through user space.
+// Returns the number of bytes sent, which is less than count if the file
+// ended early, or 0 if the file can't be sent this way (e.g. for files on
+// some special file systems), so the rest is left to the caller.
+#[cfg(target_os = "linux")]
+fn sendfile(stream: &TcpStream, file: &File, count: usize) -> io::Result<usize> {
+	use std::os::unix::io::AsRawFd;
+
+	let mut sent = 0;
+	while sent < count {
+		// Larger counts are capped by the kernel anyway.
+		let chunk = (count - sent).min(0x7fff_f000);
+		let result = unsafe {
+			libc::sendfile(stream.as_raw_fd(), file.as_raw_fd(), std::ptr::null_mut(), chunk)
+		};
+		match result {
+			0 => break,
+			n if n > 0 => sent += n as usize,
+			_ => {
+				let e = io::Error::last_os_error();
+				match e.raw_os_error() {
+					Some(libc::EINTR) => continue,
+					Some(libc::EINVAL) | Some(libc::ENOSYS) if sent == 0 => break,
+					_ => return Err(e)
+				}
 			}
-			return Some(String::from(*components.get(1).unwrap()));
 		}
 	}
+	Ok(sent)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn sendfile(_stream: &TcpStream, _file: &File, _count: usize) -> io::Result<usize> {
+	Ok(0)
+}
+
+#[cfg(not(unix))]
+fn set_linger(_stream: &TcpStream, _linger: Duration) -> io::Result<()> {
+	Ok(())
+}
+
+// Make sure the client notices that a response is incomplete, e.g. because
+// the source of the body failed. With framing (Content-Length or chunked coding),
+// closing the connection early is enough. Without it, the end of the connection
+// would look like the end of the body, so the connection is reset instead once
+// it is closed.
+pub(crate) fn abort_response(stream: &TcpStream, framed: bool) {
+	let result = match framed {
+		true => stream.shutdown(Shutdown::Both),
+		false => set_linger(stream, Duration::from_secs(0))
+	};
+	if let Err(e) = result {
+		debug!("Could not abort the response: {}", e);
+	}
+}
+
+// Parse a PROXY protocol v1 header at the start of the buffer, e.g.
+// "PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n".
+//
+// Returns the length of the header line and the original client address,
+// which is None for "PROXY UNKNOWN". Returns None if the header is invalid.
+fn parse_proxy_header(buf: &[u8]) -> Option<(usize, Option<SocketAddr>)> {
+	// The header is at most 107 bytes long, including the CRLF.
+	let end = buf[..buf.len().min(107)]
+		.windows(2)
+		.position(|w| w == b"\r\n")?;
+	let line = str::from_utf8(&buf[..end]).ok()?;
+	let components = line.split(' ').collect::<Vec<&str>>();
+
+	match components.as_slice() {
+		["PROXY", "UNKNOWN", ..] => Some((end + 2, None)),
+		["PROXY", "TCP4", src, _, sport, _] | ["PROXY", "TCP6", src, _, sport, _] => {
+			let ip = src.parse::<IpAddr>().ok()?;
+			let port = sport.parse::<u16>().ok()?;
+			Some((end + 2, Some(SocketAddr::new(ip, port))))
+		},
+		_ => None
+	}
+}
 
+// Log a failure to send the response to a request the server rejected on its
+// own; there is no client to hand out for such requests.
+fn rejected(addr: SocketAddr, result: io::Result<usize>) -> Option<Client> {
+	if let Err(e) = result {
+		warn!("Could not send the rejection to {}: {}", addr, e);
+	}
 	None
 }
 
+// Read from the stream until the body has reached the given length.
+// Fails with UnexpectedEof if the client closes the connection before, or
+// with TimedOut if the body isn't complete at the deadline.
+// Up to max_reserve bytes are reserved for the body beforehand.
+fn read_body(
+	stream: &mut TcpStream,
+	body: &mut Vec<u8>,
+	content_length: usize,
+	max_reserve: usize,
+	deadline: Option<Instant>) -> io::Result<()>
+{
+	if body.len() < content_length {
+		let remaining = content_length - body.len();
+		body.reserve_exact(remaining.min(max_reserve));
+		match deadline {
+			None => {
+				stream.take(remaining as u64).read_to_end(body)?;
+			},
+			// Read piece by piece, so each read only waits as long as there is
+			// time left; with read_to_end, every single read could take that long.
+			Some(_) => {
+				let mut buf = [0u8; 16 * 1024];
+				while body.len() < content_length {
+					set_read_deadline(stream, deadline)?;
+					let wanted = (content_length - body.len()).min(buf.len());
+					match stream.read(&mut buf[..wanted]) {
+						Ok(0) => break,
+						Ok(len) => body.extend_from_slice(&buf[..len]),
+						Err(e) => match e.kind() {
+							io::ErrorKind::Interrupted => {},
+							io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => return Err(io::Error::new(
+								io::ErrorKind::TimedOut,
+								format!("Client sent only {} of {} body bytes in time", body.len(), content_length))),
+							_ => return Err(e)
+						}
+					}
+				}
+			}
+		}
+	}
+
+	match body.len() < content_length {
+		true => Err(io::Error::new(
+			io::ErrorKind::UnexpectedEof,
+			format!("Client sent only {} of {} body bytes", body.len(), content_length))),
+		false => Ok(())
+	}
+}
+
+// Headers whose values must not end up in logs
+const SENSITIVE_HEADERS: [&str; 4] = ["Authorization", "Proxy-Authorization", "Cookie", "Set-Cookie"];
+
+// Decide whether the connection should be kept open after the response,
+// based on the protocol version and the value of the Connection header.
+//
+// HTTP/1.1 keeps connections alive unless the client sends "close",
+// older versions close them unless the client sends "keep-alive".
+pub(crate) fn keep_alive(http_version: Option<&str>, connection: Option<&str>) -> bool {
+	match http_version {
+		None | Some("HTTP/0.9") | Some("HTTP/1.0") => has_token(connection, "keep-alive"),
+		Some(_) => !has_token(connection, "close")
+	}
+}
+
+// Return whether a comma-separated header value like "keep-alive, Upgrade"
+// contains the given token, compared case-insensitively.
+fn has_token(value: Option<&str>, token: &str) -> bool {
+	value.is_some_and(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case(token)))
+}
+
+// Return the first protocol of the Upgrade header, e.g. "websocket", if the
+// client requested an upgrade; this requires "upgrade" in the Connection header.
+// Upgrades don't exist before HTTP/1.1, so they are ignored for older versions.
+pub(crate) fn upgrade_protocol<'a>(
+	http_version: Option<&str>,
+	connection: Option<&str>,
+	upgrade: Option<&'a str>) -> Option<&'a str>
+{
+	match http_version {
+		None | Some("HTTP/0.9") | Some("HTTP/1.0") => None,
+		Some(_) if has_token(connection, "upgrade") => upgrade?
+			.split(',')
+			.map(str::trim)
+			.find(|p| !p.is_empty()),
+		Some(_) => None
+	}
+}
+
+// Decide whether the client accepts the given content coding, based on the
+// value of its Accept-Encoding header, e.g. "gzip, deflate;q=0.5, *;q=0".
+// A coding listed with q=0 is refused; unlisted codings fall back to "*".
+pub(crate) fn accepts_coding(accept_encoding: &str, coding: &str) -> bool {
+	coding_quality(accept_encoding, coding).is_some_and(|q| q > 0.0)
+}
+
+// Return the q value the Accept-Encoding header assigns to the given coding,
+// or to "*" if the coding isn't listed; None if neither is listed.
+pub(crate) fn coding_quality(accept_encoding: &str, coding: &str) -> Option<f32> {
+	let mut wildcard = None;
+
+	for entry in accept_encoding.split(',') {
+		let mut params = entry.split(';');
+		let name = params.next().unwrap_or("").trim();
+		let q = params
+			.filter_map(|p| p.trim().strip_prefix("q="))
+			.next()
+			.map_or(1.0, |q| q.trim().parse::<f32>().unwrap_or(0.0));
+
+		if name.eq_ignore_ascii_case(coding) {
+			return Some(q);
+		}
+		if name == "*" {
+			wildcard = Some(q);
+		}
+	}
+
+	wildcard
+}
+
+// Return whether an If-None-Match header value matches the given entity tag,
+// using the weak comparison (RFC 7232, section 3.2), i.e. "W/" is ignored.
+pub(crate) fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+	let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+	let etag = opaque(etag);
+
+	if_none_match.trim() == "*" || if_none_match.split(',').any(|t| opaque(t) == etag)
+}
+
+// Return whether an If-Match header value matches the given entity tag, using
+// the strong comparison (RFC 9110, section 13.1.1): weak tags never match.
+// "*" matches any current tag; without one (None), nothing matches.
+fn if_match_matches(if_match: &str, etag: Option<&str>) -> bool {
+	let etag = match etag {
+		Some(etag) if !etag.trim().starts_with("W/") => quote_etag(etag.trim()),
+		Some(_) => return if_match.trim() == "*",
+		None => return false
+	};
+
+	if_match.trim() == "*" || if_match.split(',').any(|t| t.trim() == etag)
+}
+
+// Add quotes around an entity tag unless it is quoted already, e.g. "v1"
+// for v1, so both forms can be passed.
+fn quote_etag(etag: &str) -> String {
+	match etag.ends_with('"') {
+		true => etag.to_string(),
+		false => format!("\"{}\"", etag)
+	}
+}
+
+// Quote the given text as a JSON string, escaping quotes, backslashes and
+// control characters (RFC 8259, section 7).
+fn json_string(text: &str) -> String {
+	let mut json = String::with_capacity(text.len() + 2);
+	json.push('"');
+	for c in text.chars() {
+		match c {
+			'"' => json.push_str("\\\""),
+			'\\' => json.push_str("\\\\"),
+			'\n' => json.push_str("\\n"),
+			'\r' => json.push_str("\\r"),
+			'\t' => json.push_str("\\t"),
+			c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+			c => json.push(c)
+		}
+	}
+	json.push('"');
+	json
+}
+
+// Build a Content-Disposition value which makes browsers save the response
+// under the given filename. Browsers supporting RFC 6266 use the exact name
+// from filename* (percent-encoded UTF-8, RFC 5987); older ones fall back to
+// the quoted filename, in which anything but printable ASCII is replaced.
+fn content_disposition(filename: &str) -> String {
+	let fallback = filename.chars()
+		.map(|c| match c {
+			'"' => "\\\"".to_string(),
+			'\\' => "\\\\".to_string(),
+			' '..='~' => c.to_string(),
+			_ => "_".to_string()
+		})
+		.collect::<String>();
+	format!("attachment; filename=\"{}\"; filename*=UTF-8''{}", fallback, percent_encode(filename))
+}
+
+// Make sure a line of the response head can't break out of its line, e.g.
+// a header value taken from the request which contains "\r\nSet-Cookie: ...".
+// NUL is rejected as well, since some clients treat it as the end of the line.
+pub(crate) fn check_header_line(line: &str) -> io::Result<()> {
+	match line.contains(['\r', '\n', '\0']) {
+		true => Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Response line contains CR, LF or NUL: {:?}", line))),
+		false => Ok(())
+	}
+}
+
+// Build the status line and the headers, followed by the empty line, after
+// checking them against the limits in settings. Content-Length is only added
+// if content_size is given and the status allows a body.
+pub(crate) fn build_head(
+	settings: &Settings,
+	protocol: &str,
+	status_code: &str,
+	content_size: Option<usize>,
+	headers: &[String]) -> io::Result<String>
+{
+	// Validate everything before anything is sent.
+	check_header_line(status_code)?;
+	if headers.len() > settings.max_response_headers {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Response has {} headers, at most {} are allowed", headers.len(), settings.max_response_headers)));
+	}
+	let header_size = headers.iter().map(|h| h.len() + 2).sum::<usize>();
+	if header_size > settings.max_response_header_size {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Response headers have {} bytes, at most {} are allowed", header_size, settings.max_response_header_size)));
+	}
+	for h in headers {
+		check_header_line(h)?;
+	}
+
+	let mut head = format!("{} {}\r\n", protocol, status_code);
+	if let Some(size) = content_size.filter(|_| !forbids_body(status_code)) {
+		head += &format!("Content-Length: {}\r\n", size);
+	}
+	for h in headers {
+		head += &format!("{}\r\n", h);
+	}
+	// HTTP/1.1 clients would expect the connection to stay open.
+	if protocol == "HTTP/1.1" && !headers.iter().any(|h| h.to_ascii_lowercase().starts_with("connection:")) {
+		head += "Connection: close\r\n";
+	}
+	head += "\r\n";
+	Ok(head)
+}
+
+// The protocol version of responses to a client which sent the given version,
+// see MicroHTTP::set_protocol_version. Only HTTP/1.1 or later gets HTTP/1.1.
+pub(crate) fn response_protocol(settings: &Settings, http_version: Option<&str>) -> &'static str {
+	match (settings.protocol_version, http_version) {
+		(_, None) | (_, Some("HTTP/0.9")) | (_, Some("HTTP/1.0")) => "HTTP/1.0",
+		(ProtocolVersion::Http11, _) => "HTTP/1.1",
+		_ => "HTTP/1.0"
+	}
+}
+
 impl Client {
-	pub(crate) fn new(mut stream : TcpStream, addr : SocketAddr) -> Result<Client,::std::io::Error> {
-		// Read all data now, since we only expect simple requests like "HTTP 1.0 GET /"
-		let data = read_all(&mut stream)?;
+	// Returns None if the server already answered the request on its own. Errors
+	// while reading the request don't prevent creating the client, so the handler
+	// can still answer it; they are available through read_error.
+	pub(crate) fn new(mut stream : TcpStream, mut addr : SocketAddr, settings: Arc<Settings>) -> Option<Client> {
+		let received_at = Instant::now();
+		let local_addr = stream.local_addr().unwrap_or_else(|e| {
+			warn!("Could not get the local address of the connection from {}: {}", addr, e);
+			SocketAddr::from(([0, 0, 0, 0], 0))
+		});
+
+		if let Some(linger) = settings.linger {
+			if let Err(e) = set_linger(&stream, linger) {
+				warn!("Could not set the linger timeout for {}: {}", addr, e);
+			}
+		}
+
+		// Read the head now; the body is read below or, when streaming, later on.
+		let deadline = settings.request_timeout.map(|timeout| received_at + timeout);
+		let (mut data, error) = match read_head(&mut stream, settings.buffer_size, deadline) {
+			Ok(data) => (data, None),
+			Err(kind) => {
+				warn!("Could not read the request from {}: {:?}", addr, kind);
+				(Vec::new(), Some(io::Error::from(kind)))
+			}
+		};
+		let bytes_read = data.len();
+
+		// Behind a load balancer, replace the peer address with the one of the real client.
+		if settings.proxy_protocol {
+			match parse_proxy_header(&data) {
+				Some((len, client_addr)) => {
+					data.drain(..len);
+					addr = client_addr.unwrap_or(addr);
+				},
+				None => {
+					warn!("Missing or invalid PROXY protocol header from {}", addr);
+					settings.connection_closed(addr);
+					return None;
+				}
+			}
+		}
+
+		trace!("Received from {}: {:?}", addr, String::from_utf8_lossy(&data));
+
+		let limits = ParseLimits {
+			max_target_len: settings.max_uri_length,
+			max_header_count: settings.max_header_count,
+			max_head_len: MAX_HEAD_SIZE
+		};
+		let (head, parse_error) = match parse_request(&data, &limits) {
+			Ok(head) => (head, None),
+			Err(e) => (RequestHead::default(), Some(e))
+		};
+
+		// Everything after the header section belongs to the body.
+		let body = data.split_off(head.len);
+
+		let mut client = Client {
+			stream,
+			addr,
+			local_addr,
+			method: head.method,
+			request_line: head.request_line,
+			request: head.target,
+			fragment: head.fragment,
+			headers: head.headers,
+			warnings: head.warnings,
+			body,
+			raw_body: None,
+			body_remaining: 0,
+			header_bytes: data.len(),
+			bytes_read,
+			body_remaining_at_start: 0,
+			continue_sent: false,
+			output_filter: None,
+			cookies: Vec::new(),
+			http_version: head.http_version,
+			received_at,
+			handling_time: None,
+			error,
+			settings
+		};
+		if client.timed_out() {
+			return rejected(client.addr, client.respond_error("408 Request Timeout"));
+		}
+		if let Some(e) = parse_error {
+			warn!("Rejecting request from {}: {}", client.addr, e);
+			return rejected(client.addr, client.respond_error(e.status()));
+		}
+		client.log_request();
+
+		if client.is_health_check() {
+			return rejected(client.addr, client.respond("200 OK", &[], &vec!()));
+		}
+
+		if client.settings.method_override && client.method() == Some("POST") {
+			client.apply_method_override();
+		}
+
+		let settings = client.settings.clone();
+		if let (Some(allowed), Some(method)) = (&settings.allowed_methods, client.method()) {
+			if !allowed.iter().any(|m| m == method) {
+				warn!("Rejecting {} request from {}", method, client.addr);
+				let allow = vec!(format!("Allow: {}", allowed.join(", ")));
+				return rejected(client.addr, client.respond("405 Method Not Allowed", &[], &allow));
+			}
+		}
+
+		// Several Host headers could make proxies and this server disagree on
+		// which site is requested (RFC 7230, section 5.4).
+		if client.headers.iter().filter(|(n, _)| n.eq_ignore_ascii_case("Host")).count() > 1 {
+			warn!("Rejecting request from {} with several Host headers", client.addr);
+			return rejected(client.addr, client.respond_error("400 Bad Request"));
+		}
+
+		// The same goes for the length of the body: a proxy might frame it
+		// differently and pass on a smuggled request (RFC 9112, section 6.3).
+		let ambiguous_length = client.warnings.iter().any(|w| matches!(w,
+			ParseWarning::ConflictingContentLength | ParseWarning::ContentLengthWithTransferEncoding));
+		if ambiguous_length {
+			warn!("Rejecting request from {} with an ambiguous body length", client.addr);
+			return rejected(client.addr, client.respond_error("400 Bad Request"));
+		}
+
+		if let Some(content_length) = head.content_length {
+			// Reject uploads which are too large before reading them.
+			if client.settings.max_request_size.is_some_and(|max| content_length > max) {
+				return rejected(client.addr, client.respond_error("413 Payload Too Large"));
+			}
+
+			// Let the application turn the upload down before it is transmitted.
+			if let (Some(handler), true) = (&settings.pre_body_handler, content_length > 0) {
+				match (handler.0)(&client) {
+					BodyDecision::Accept => {},
+					BodyDecision::Reject(status) => {
+						let status_line = format!("{} {}", status, reason_phrase(status));
+						return rejected(client.addr, client.respond_error(&status_line));
+					},
+					BodyDecision::Redirect(location) => {
+						let headers = vec!(format!("Location: {}", location));
+						return rejected(client.addr, client.respond("307 Temporary Redirect", &[], &headers));
+					}
+				}
+			}
+
+			// In streaming mode, the body is read later by body_reader.
+			if !client.settings.stream_bodies && client.error.is_none() {
+				// Read exactly the declared body; anything beyond belongs to
+				// a further request, which is not supported.
+				let buffered = client.body.len();
+				let max_reserve = client.settings.read_buffer_hint;
+				let result = client.send_continue()
+					.and_then(|_| read_body(&mut client.stream, &mut client.body, content_length, max_reserve, deadline));
+				client.bytes_read += client.body.len() - buffered;
+
+				if let Err(e) = result {
+					warn!("Could not read the body from {}: {}", client.addr, e);
+					client.error = Some(e);
+				}
+				if client.timed_out() {
+					return rejected(client.addr, client.respond_error("408 Request Timeout"));
+				}
+			}
+
+			client.body_remaining = content_length.saturating_sub(client.body.len());
+			client.body_remaining_at_start = client.body_remaining;
+		}
+
+		// The deadline only applies to receiving the request, not to streaming the body.
+		if deadline.is_some() {
+			if let Err(e) = client.stream.set_read_timeout(None) {
+				warn!("Could not reset the read timeout for {}: {}", client.addr, e);
+			}
+		}
+
+		// Anything after the end of the request belongs to a further request,
+		// which is not supported.
+		let request_len = client.content_length().unwrap_or(0);
+		if client.body.len() > request_len {
+			let excess = client.body.len() - request_len;
+			if client.settings.strict_framing {
+				warn!("Rejecting request from {} with {} bytes after its end", client.addr, excess);
+				return rejected(client.addr, client.respond_error("400 Bad Request"));
+			}
+			// Without Content-Length, whatever followed the headers is
+			// kept as the body, as it always was.
+			if client.content_length().is_some() {
+				warn!("Ignoring {} bytes after the body from {}", excess, client.addr);
+				client.body.truncate(request_len);
+			}
+		}
+
+		if !client.settings.stream_bodies && client.error.is_none() {
+			if let Err(e) = client.decode_body() {
+				let status = match e {
+					DecompressError::TooLarge => "413 Payload Too Large",
+					DecompressError::Invalid(_) => "400 Bad Request"
+				};
+				warn!("Could not decode the body from {}: {}", client.addr, e);
+				return rejected(client.addr, client.respond_error(status));
+			}
+		}
+
+		Some(client)
+	}
+
+	// Decompress the body if it was sent with a Content-Encoding this build
+	// can decode; other codings are left to the application.
+	fn decode_body(&mut self) -> Result<(), DecompressError> {
+		let coding = match self.header("Content-Encoding") {
+			Some(coding) => coding.trim().to_ascii_lowercase(),
+			None => return Ok(())
+		};
+		if !compression::DECODABLE.contains(&coding.as_str()) {
+			return Ok(());
+		}
+
+		let max_size = self.settings.max_request_size.unwrap_or(MAX_DECODED_BODY_SIZE);
+		let decoded = compression::decompress(&coding, &self.body, max_size)?;
+		self.raw_body = Some(std::mem::replace(&mut self.body, decoded));
+		Ok(())
+	}
+
+	// Return whether the request wasn't received before the deadline set with
+	// set_request_timeout.
+	fn timed_out(&self) -> bool {
+		self.settings.request_timeout.is_some()
+			&& self.error.as_ref().is_some_and(|e| e.kind() == io::ErrorKind::TimedOut)
+	}
+
+	// Return whether this is a request for the path set with set_health_check_path.
+	fn is_health_check(&self) -> bool {
+		let path = match self.settings.health_check_path {
+			Some(ref path) => path,
+			None => return false
+		};
+		let target = self.request.as_deref().unwrap_or("");
+		let target_path = target.split_once('?').map_or(target, |(path, _)| path);
+
+		matches!(self.method(), Some("GET") | Some("HEAD")) && target_path == path
+	}
+
+	// Send "100 Continue" if the client waits for it before sending the body.
+	fn send_continue(&mut self) -> io::Result<()> {
+		let expects_continue = self.header("Expect")
+			.is_some_and(|v| v.eq_ignore_ascii_case("100-continue"));
+		let body_missing = self.content_length().is_some_and(|len| self.body.len() < len);
+
+		if expects_continue && body_missing && !self.continue_sent {
+			self.output().write_all(b"HTTP/1.1 100 Continue\r\n\r\n")?;
+			self.continue_sent = true;
+		}
+		Ok(())
+	}
+
+	// Replace the method with the one from X-HTTP-Method-Override, if it is
+	// one of the methods which HTML forms can't send.
+	fn apply_method_override(&mut self) {
+		let method = match self.header("X-HTTP-Method-Override") {
+			Some(method) => method.to_ascii_uppercase(),
+			None => return
+		};
+
+		match method.as_str() {
+			"PUT" | "PATCH" | "DELETE" => {
+				debug!("{} overrides the method POST with {}", self.addr, method);
+				self.method = Some(method);
+			},
+			_ => warn!("Ignoring method override {:?} from {}", method, self.addr)
+		}
+	}
+
+	// Log the parsed request and warn about anything suspicious.
+	fn log_request(&self) {
+		debug!("{} requested {} {:?} ({})", self.addr, self.method().unwrap_or("-"),
+			self.request, self.http_version().unwrap_or("no version"));
+
+		for (name, value) in &self.headers {
+			match SENSITIVE_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+				true => debug!("  {}: <redacted>", name),
+				false => debug!("  {}: {}", name, value)
+			}
+		}
+
+		for warning in &self.warnings {
+			warn!("Unusual request from {}: {}", self.addr, warning.redacted());
+		}
+	}
+
+	/// Return the address of the requesting client, for example "1.2.3.4:9435".
+	pub fn addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	/// Return the local address the connection was accepted on, e.g. to build
+	/// absolute links to this server when it was bound to port 0. For a server
+	/// listening on several interfaces (see ``MicroHTTP::new_multi``), this tells
+	/// which one served the request.
+	///
+	/// Behind a proxy, this is the address the proxy connected to.
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+
+	/// Return the point in time at which the server started receiving the request.
+	pub fn received_at(&self) -> Instant {
+		self.received_at
+	}
+
+	/// Return the time between receiving the request and finishing the
+	/// response, or None if no response has been sent yet.
+	///
+	/// This is useful for latency metrics, e.g. in access logs.
+	pub fn handling_time(&self) -> Option<Duration> {
+		self.handling_time
+	}
+
+	/// Return the request the client made or None if the client
+	/// didn't make any or an invalid one.
+	///
+	/// This is the request target, e.g. ``/index.html?lang=en``;
+	/// use ``method`` to find out whether it was a GET, POST, etc.
+	/// A ``#fragment`` the client sent along is cut off, see ``request_url``.
+	pub fn request(&self) -> &Option<String> {
+		&self.request
+	}
+
+	/// Return the request target split into path, query and fragment, or None
+	/// if the client didn't make a valid request.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let client = server.next_client().unwrap().unwrap();
+	/// if let Some(url) = client.request_url() {
+	///     println!("Path: {}, query: {:?}", url.path(), url.query());
+	/// }
+	/// ```
+	pub fn request_url(&self) -> Option<RequestUrl> {
+		let target = self.request.as_deref()?;
+		Some(RequestUrl::new(target, self.fragment.as_deref()))
+	}
+
+	/// Return the parameters of the query string, e.g. ``q`` and ``page`` for
+	/// ``/search?q=cats&page=2``. The map is empty if there is no query string.
+	///
+	/// Keys and values are percent-decoded and ``+`` is decoded as a space, as in
+	/// ``form_params``. If a key occurs more than once, the last value wins; use
+	/// ``query_params_multi`` to get all of them.
+	pub fn query_params(&self) -> HashMap<String, String> {
+		self.query_pairs().into_iter().collect()
+	}
+
+	/// Return all values of each query parameter, in the order they were sent,
+	/// e.g. ``["a", "b"]`` for ``tag`` in ``/posts?tag=a&tag=b``.
+	///
+	/// This is needed for repeated keys, like the values of a multi-select form
+	/// field submitted with ``GET``.
+	pub fn query_params_multi(&self) -> HashMap<String, Vec<String>> {
+		let mut params = HashMap::new();
+		for (key, value) in self.query_pairs() {
+			params.entry(key).or_insert_with(Vec::new).push(value);
+		}
+		params
+	}
+
+	// The decoded key/value pairs of the query string, in their original order.
+	fn query_pairs(&self) -> Vec<(String, String)> {
+		let target = self.request.as_deref().unwrap_or("");
+		match target.split_once('?') {
+			Some((_, query)) => parse_urlencoded(query),
+			None => Vec::new()
+		}
+	}
+
+	/// Return the request method, e.g. ``GET`` or ``POST``, or None if the
+	/// client didn't send a valid request line.
+	///
+	/// The method is parsed on its own, so it is available even if the rest of
+	/// the request line is broken and ``request`` returns None.
+	pub fn method(&self) -> Option<&str> {
+		self.method.as_deref()
+	}
+
+	/// Return the first line of the request exactly as the client sent it,
+	/// e.g. ``GET /index.html HTTP/1.1``, or None if the client sent nothing at all.
+	///
+	/// Together with ``method`` and ``request``, this tells apart the cases in which
+	/// ``request`` returns None:
+	/// * ``request_line`` is None: the client connected, but didn't send anything.
+	/// * ``method`` is Some: a well-formed method without a usable target, e.g. a
+	///   method you don't support, which could be answered with ``405 Method Not Allowed``.
+	/// * ``method`` is None: garbled input, which could be answered with ``400 Bad Request``.
+	///
+	/// Invalid UTF-8 is replaced with ``U+FFFD``.
+	pub fn request_line(&self) -> Option<&str> {
+		self.request_line.as_deref()
+	}
+
+	/// Return all headers the client sent as ``(name, value)`` pairs,
+	/// in the exact order and casing they were received.
+	///
+	/// This is useful for logging or relaying a request faithfully;
+	/// for simple lookups, use ``header`` instead.
+	pub fn headers_raw(&self) -> &[(String, String)] {
+		&self.headers
+	}
+
+	/// Return the unusual things found while parsing the request head which
+	/// µHTTP tolerated, e.g. whitespace before a header colon or a line ending
+	/// with a bare line feed. The list is empty for well-formed requests.
+	///
+	/// Each of them is also logged once as a warning, leaving out the content of
+	/// header lines; use this to reject ambiguous requests in security-sensitive
+	/// deployments, or to count them.
+	pub fn warnings(&self) -> &[ParseWarning] {
+		&self.warnings
+	}
+
+	/// Return the value of the first header with the given name or None
+	/// if the client didn't send it.
+	///
+	/// The name is compared case-insensitively, so ``header("content-type")``
+	/// will also find a ``Content-Type`` header.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	/// Return the value of the given header as an integer, e.g. for ``Max-Forwards``,
+	/// or None if the header is missing or not a valid number.
+	pub fn header_u64(&self, name: &str) -> Option<u64> {
+		self.header(name)?.parse().ok()
+	}
+
+	/// Return the value of the given header as a point in time, e.g. for
+	/// ``If-Modified-Since``, or None if the header is missing or not a valid date.
+	///
+	/// All three date formats allowed by HTTP are understood, e.g.
+	/// ``Sun, 06 Nov 1994 08:49:37 GMT``.
+	pub fn header_date(&self, name: &str) -> Option<SystemTime> {
+		parse_http_date(self.header(name)?)
+	}
+
+	/// Return the token of an ``Authorization: Bearer <token>`` header, e.g. an API
+	/// key or a JWT, or None if the header is missing, uses another scheme or the
+	/// token is malformed.
+	///
+	/// The scheme is matched case-insensitively and the token is returned without
+	/// surrounding whitespace; it is only checked for valid characters (RFC 6750,
+	/// section 2.1), validating it is up to the application.
+	pub fn bearer_token(&self) -> Option<&str> {
+		let (scheme, token) = self.header("Authorization")?.split_once(' ')?;
+		let token = token.trim();
+		let valid = !token.is_empty() && token.trim_end_matches('=').bytes()
+			.all(|c| c.is_ascii_alphanumeric() || b"-._~+/".contains(&c));
+
+		match scheme.eq_ignore_ascii_case("Bearer") && valid {
+			true => Some(token),
+			false => None
+		}
+	}
+
+	/// Return the protocol version from the request line, e.g. ``HTTP/1.1``,
+	/// or None if the client didn't specify one.
+	pub fn http_version(&self) -> Option<&str> {
+		self.http_version.as_deref()
+	}
+
+	/// Return the scheme of the request, i.e. ``https`` or ``http``, e.g. to build
+	/// absolute URLs for redirects.
+	///
+	/// Connections to this server are always plain ``http``. Behind a reverse proxy
+	/// which terminates TLS, the scheme is taken from the ``X-Forwarded-Proto`` header
+	/// if the server is configured to trust it (see ``MicroHTTP::set_trust_forwarded_proto``).
+	pub fn scheme(&self) -> &'static str {
+		let forwarded = match self.settings.trust_forwarded_proto {
+			true => self.header("X-Forwarded-Proto"),
+			false => None
+		};
+
+		// Proxies in a chain may append their own value; the first one is the client's.
+		match forwarded.and_then(|v| v.split(',').next()).map(str::trim) {
+			Some(proto) if proto.eq_ignore_ascii_case("https") => "https",
+			_ => "http"
+		}
+	}
+
+	/// Return the ``Referer`` header, i.e. the address of the page which linked to
+	/// the requested resource, or None if the client didn't send one.
+	///
+	/// Note that the header name is misspelled in the HTTP standard; the correctly
+	/// spelled ``Referrer`` is not a header clients send. Clients may leave it out
+	/// or shorten it for privacy, so only use it for statistics, not for security checks.
+	pub fn referer(&self) -> Option<&str> {
+		self.header("Referer")
+	}
+
+	/// Return the ``Origin`` header, e.g. ``https://example.com:8080``, or None
+	/// if the client didn't send one.
+	///
+	/// Browsers send it with cross-origin and ``POST`` requests, which makes it
+	/// suitable for CSRF protection: compare it against the origins you trust.
+	/// Only scheme, host and port are returned, even if a client wrongly includes
+	/// a path. Privacy-sensitive contexts send the literal ``null``, which should
+	/// never be trusted.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// if client.method() == Some("POST") && client.origin() != Some("https://example.com") {
+	///     client.respond_error("403 Forbidden");
+	/// }
+	/// ```
+	pub fn origin(&self) -> Option<&str> {
+		let origin = self.header("Origin")?;
+		let path_start = origin.find("://")
+			.and_then(|pos| origin[pos + 3..].find('/').map(|p| pos + 3 + p));
+
+		match path_start {
+			Some(end) => Some(&origin[..end]),
+			None => Some(origin)
+		}
+	}
+
+	/// Return whether the client asked to keep the connection open after the response.
+	///
+	/// HTTP/1.1 clients keep connections alive unless they send ``Connection: close``,
+	/// while HTTP/1.0 clients close them unless they send ``Connection: keep-alive``.
+	pub fn wants_keep_alive(&self) -> bool {
+		keep_alive(self.http_version(), self.header("Connection"))
+	}
+
+	/// Return whether the client accepts trailer fields after a chunked body,
+	/// which it signals with ``TE: trailers``.
+	///
+	/// ``ChunkedResponse::finish`` only sends trailers to clients which accept them.
+	pub fn accepts_trailers(&self) -> bool {
+		has_token(self.header("TE"), "trailers")
+	}
+
+	/// Return the protocol the client asked to switch to, e.g. ``websocket`` or
+	/// ``h2c``, or None if it didn't ask for an upgrade.
+	///
+	/// An upgrade is requested with an ``Upgrade`` header naming the protocol along
+	/// with ``Connection: Upgrade``; if the client lists several protocols, the first
+	/// one is returned. Upgrades only exist since HTTP/1.1, so they are ignored for
+	/// HTTP/1.0 requests.
+	pub fn wants_upgrade(&self) -> Option<&str> {
+		upgrade_protocol(self.http_version(), self.header("Connection"), self.header("Upgrade"))
+	}
+
+	/// Return whether the client accepts responses with the given content coding,
+	/// e.g. ``gzip``, according to its ``Accept-Encoding`` header.
+	///
+	/// Codings the client lists with ``q=0`` are not accepted.
+	pub fn accepts_encoding(&self, coding: &str) -> bool {
+		self.header("Accept-Encoding")
+			.is_some_and(|v| accepts_coding(v, coding))
+	}
+
+	/// Return the value of the ``Content-Length`` header or None if the client
+	/// didn't send one or an invalid one.
+	pub fn content_length(&self) -> Option<usize> {
+		self.header("Content-Length")
+			.and_then(|v| v.parse().ok())
+	}
+
+	/// Return the parsed ``Content-Type`` header, or None if the client didn't
+	/// send one or an invalid one.
+	///
+	/// Use ``ContentType::mime`` to check the type and ``ContentType::charset``,
+	/// ``ContentType::boundary`` or ``ContentType::param`` for its parameters.
+	pub fn content_type(&self) -> Option<ContentType> {
+		ContentType::parse(self.header("Content-Type")?)
+	}
+
+	/// Return the body the client sent along with its request.
+	///
+	/// The body may be empty, e.g. for a simple GET request.
+	/// If bodies are streamed (see ``MicroHTTP::set_stream_bodies``),
+	/// use ``body_reader`` instead. Compressed bodies are returned decoded
+	/// where possible, see ``raw_body``.
+	pub fn body(&self) -> &[u8] {
+		&self.body
+	}
+
+	/// Return the body exactly as the client sent it.
+	///
+	/// This differs from ``body`` only for compressed bodies: with the ``flate2``
+	/// feature, bodies sent with ``Content-Encoding: gzip`` or ``deflate`` are
+	/// decompressed (``br`` with the ``brotli`` feature), and ``body`` returns the
+	/// decoded data. The decoded body may be at most as large as allowed by
+	/// ``MicroHTTP::set_max_request_size``, or 16 MiB if no limit is set; larger
+	/// bodies are rejected with ``413 Payload Too Large``, invalid ones with
+	/// ``400 Bad Request``. Streamed bodies (see ``body_reader``) are never decoded.
+	pub fn raw_body(&self) -> &[u8] {
+		self.raw_body.as_deref().unwrap_or(&self.body)
+	}
+
+	/// Return the number of bytes the client has sent for this request so far,
+	/// i.e. the header section, the body and anything else received along with them
+	/// (e.g. a PROXY protocol header).
+	///
+	/// In streaming mode (see ``MicroHTTP::set_stream_bodies``), body bytes are
+	/// counted as they are read with ``body_reader``. Together with the return value
+	/// of the respond methods, this accounts for all data transferred for a request.
+	pub fn request_bytes(&self) -> usize {
+		self.bytes_read + (self.body_remaining_at_start - self.body_remaining)
+	}
+
+	/// Return the size of the header section of the request in bytes, i.e. the
+	/// request line and all headers including the empty line which ends them.
+	pub fn request_header_bytes(&self) -> usize {
+		self.header_bytes
+	}
+
+	/// Return the error which occurred while reading the request, or None if it
+	/// was read without problems.
+	///
+	/// A client is handed out even if its request couldn't be read completely,
+	/// e.g. because the connection was reset or the client sent less body data
+	/// than announced before closing its side. Whatever was received is available
+	/// as usual, so the request can still be answered, e.g. with ``400 Bad Request``.
+	pub fn read_error(&self) -> Option<&io::Error> {
+		self.error.as_ref()
+	}
+
+	/// Return whether the whole body announced by ``Content-Length`` has been received.
+	///
+	/// When bodies are read up front (the default), this is true unless reading
+	/// the body failed (see ``read_error``). In
+	/// streaming mode (see ``MicroHTTP::set_stream_bodies``), it only becomes true
+	/// once the body has been read to the end with ``body_reader``; if the client
+	/// disconnects early, it stays false. Handlers should reject truncated bodies,
+	/// e.g. with ``400 Bad Request``, rather than process them.
+	pub fn body_complete(&self) -> bool {
+		self.body_remaining == 0
+	}
+
+	/// Return a reader for the body the client sent along with its request.
+	///
+	/// This is meant for streaming mode (see ``MicroHTTP::set_stream_bodies``),
+	/// where the body is read from the connection only while the reader is used;
+	/// if the client waits for ``100 Continue``, it is sent now. Otherwise the
+	/// reader just yields the body which has already been read.
+	///
+	/// The body can only be read once.
+	///
+	/// # Reading the body and responding
+	///
+	/// The reader borrows the client, so no response can be sent while it is in
+	/// use; read the body first, then respond. This is all a streaming proxy
+	/// needs: pass the body on while it arrives, then pass the upstream response
+	/// back (see ``examples/proxy.rs``).
+	///
+	/// Responding before the whole body has been read, e.g. to reject an upload
+	/// early, works as well, but the client may still be sending: since the
+	/// connection is closed after the response, the client may then see the
+	/// connection reset instead of the response. Clients which sent
+	/// ``Expect: 100-continue`` are safe, since they wait for ``100 Continue``,
+	/// which is only sent once ``body_reader`` is called.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let mut server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// server.set_stream_bodies(true);
+	/// let mut client = server.next_client().unwrap().unwrap();
+	///
+	/// let mut file = std::fs::File::create("/tmp/upload").unwrap();
+	/// std::io::copy(&mut client.body_reader().unwrap(), &mut file).unwrap();
+	/// client.respond_ok(b"Stored.").unwrap();
+	/// ```
+	pub fn body_reader(&mut self) -> io::Result<BodyReader<'_>> {
+		self.send_continue()?;
+		let buffered = std::mem::take(&mut self.body);
+		let expected_len = self.content_length();
+		Ok(BodyReader::new(&mut self.stream, buffered, &mut self.body_remaining, expected_len))
+	}
+
+	/// Save the parts of a ``multipart/form-data`` upload, e.g. from an HTML form with
+	/// file fields, to files of their own in ``dir``.
+	///
+	/// Each part is written to its file while it is read, so together with
+	/// ``MicroHTTP::set_stream_bodies``, uploads never need to fit into memory. The
+	/// files get names of their own (the file name sent by the client is only
+	/// available through ``UploadedPart::filename``) and belong to the caller
+	/// afterwards: move them to their final place or delete them.
+	///
+	/// If a part is larger than ``max_part_size`` bytes or all parts together are larger
+	/// than ``max_total_size`` bytes, reading stops and the request is answered with
+	/// ``413 Payload Too Large``. A body which isn't valid ``multipart/form-data`` is
+	/// answered with ``400 Bad Request``, one with a different ``Content-Type`` with
+	/// ``415 Unsupported Media Type``. In all these cases an ``InvalidData`` error is
+	/// returned, and the files saved so far, including the one of the part being read,
+	/// are deleted again. The same happens if reading the body or writing a file fails,
+	/// but then nothing is sent, so the handler may respond itself.
+	///
+	/// As with ``body_reader``, the body can only be read once, and a client which
+	/// is still sending when the upload is rejected may see a reset connection
+	/// instead of the response.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let mut server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// server.set_stream_bodies(true);
+	/// let mut client = server.next_client().unwrap().unwrap();
+	///
+	/// // At most 10 MB per file and 50 MB per upload.
+	/// if let Ok(parts) = client.save_multipart("/var/uploads", 10_000_000, 50_000_000) {
+	///     for part in &parts {
+	///         println!("{} ({:?}): {} bytes", part.name(), part.filename(), part.size());
+	///     }
+	///     client.respond_ok(b"Thanks!").unwrap();
+	/// }
+	/// ```
+	pub fn save_multipart(
+		&mut self,
+		dir: impl AsRef<Path>,
+		max_part_size: u64,
+		max_total_size: u64) -> io::Result<Vec<UploadedPart>>
+	{
+		let boundary = match self.header("Content-Type").and_then(multipart::boundary) {
+			Some(boundary) => boundary,
+			None => {
+				self.respond_error("415 Unsupported Media Type")?;
+				return Err(io::Error::new(io::ErrorKind::InvalidData, "Body is not multipart/form-data"));
+			}
+		};
+
+		let result = multipart::save_parts(self.body_reader()?, &boundary, dir.as_ref(), max_part_size, max_total_size);
+		let (status, message) = match result {
+			Ok(parts) => return Ok(parts),
+			Err(MultipartError::Io(e)) => return Err(e),
+			Err(MultipartError::TooLarge(message)) => ("413 Payload Too Large", message),
+			Err(MultipartError::Malformed(message)) => ("400 Bad Request", message)
+		};
+
+		warn!("Rejecting the upload from {}: {}", self.addr, message);
+		self.respond_error(status)?;
+		Err(io::Error::new(io::ErrorKind::InvalidData, message))
+	}
+
+	/// Write the body the client sent along with its request into ``sink``,
+	/// e.g. a file or a hasher, and return its size in bytes.
+	///
+	/// This reads the body through ``body_reader``, so in streaming mode (see
+	/// ``MicroHTTP::set_stream_bodies``) the body never needs to fit into memory.
+	/// Reading stops at the ``Content-Length`` announced by the client; bodies
+	/// larger than ``MicroHTTP::set_max_request_size`` have already been rejected.
+	/// As with ``body_reader``, the body can only be read once.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let mut server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// server.set_stream_bodies(true);
+	/// let mut client = server.next_client().unwrap().unwrap();
+	///
+	/// let mut file = std::fs::File::create("/tmp/upload").unwrap();
+	/// let size = client.read_body_into(&mut file).unwrap();
+	/// ```
+	pub fn read_body_into(&mut self, sink: &mut impl Write) -> io::Result<usize> {
+		let mut reader = self.body_reader()?;
+		io::copy(&mut reader, sink).map(|len| len as usize)
+	}
+
+	/// Return the fields of a submitted HTML form, or None if the body is not
+	/// of type ``application/x-www-form-urlencoded``.
+	///
+	/// Keys and values are percent-decoded and ``+`` is decoded as a space.
+	/// Fields without a value (e.g. ``flag`` in ``a=1&flag``) have an empty value.
+	/// If a key occurs more than once, the last value wins.
+	pub fn form_params(&self) -> Option<HashMap<String, String>> {
+		if self.content_type()?.mime() != "application/x-www-form-urlencoded" {
+			return None;
+		}
+
+		let body = String::from_utf8_lossy(&self.body);
+		Some(parse_urlencoded(&body).into_iter().collect())
+	}
+
+	/// Send a ``204 No Content`` response, e.g. to confirm a ``DELETE`` request.
+	///
+	/// Such a response consists of the status line only: it has neither a body
+	/// nor a ``Content-Length`` header.
+	pub fn respond_no_content(&mut self) -> io::Result<usize> {
+		self.respond("204 No Content", &[], &vec!())
+	}
+
+	/// Send a HTTP 200 OK response to the client + the provided data.
+	/// The data may be an empty array, for example the following
+	/// implementation echos all requests except "/hello":
+	///
+	/// Consider using ``respond_ok_chunked`` for sending file-backed data.
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	/// use std::io::*;
+	/// let server = MicroHTTP::new("127.0.0.1:4000").expect("Could not create server.");
+	/// # let mut connection = ::std::net::TcpStream::connect("127.0.0.1:4000").unwrap();
+	/// # connection.write("GET /\r\n\r\n".as_bytes());
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let request_str: String = client.request().as_ref().unwrap().clone();
+	///
+	/// match request_str.as_ref() {
+	///     "/hello" => client.respond_ok(&[]),
+	///     _ => client.respond_ok(request_str.as_bytes())  // Echo request
+	/// };
+	/// ```
+	pub fn respond_ok(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.respond_ok_chunked(data, data.len())
+	}
+
+	// The test in this doc comment is no_run because it refers to an arbitrary
+	// file that may not exist on the current system.
+
+	/// Send a HTTP 200 OK response to the client + the provided data.
+	/// The data may be any type implementing [Read](std::io::Read) and
+	/// will be read in chunks. This is useful for serving file-backed
+	/// data that should not be loaded into memory all at once.
+	///
+	/// Exactly ``content_size`` bytes are read from ``data``, from wherever it is
+	/// positioned; anything beyond is left unread. This means you can also pass
+	/// a partially consumed reader, e.g. a ``BufReader`` after reading a header
+	/// line from a file: the bytes it has already buffered are sent first, followed
+	/// by the rest of the file.
+	///
+	/// ```no_run
+	/// use micro_http_server::MicroHTTP;
+	/// use std::io::*;
+	/// use std::fs::*;
+	/// let server = MicroHTTP::new("127.0.0.1:4000").expect("Could not create server.");
+	/// # let mut connection = ::std::net::TcpStream::connect("127.0.0.1:4000").unwrap();
+	/// # connection.write("GET /\r\n\r\n".as_bytes());
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// client.request();
+	///
+	/// let mut file_handle = OpenOptions::new()
+	///        .read(true)
+	///        .write(false)
+	///        .open("/some/local/file")
+	///        .unwrap();
+	///    let file_len = file_handle.metadata().unwrap().len() as usize;
+	///
+	/// client.respond_ok_chunked(file_handle, file_len);
+	///
+	/// ```
+	pub fn respond_ok_chunked(&mut self, data: impl Read, content_size: usize) -> io::Result<usize> {
+		self.respond_chunked("200 OK", data, content_size, &vec!())
+	}
+
+	/// Send a response with the given numeric status code and its canonical
+	/// reason phrase, e.g. ``404`` is sent as ``404 Not Found``.
+	pub fn respond_status(&mut self, status: u16, data: &[u8]) -> io::Result<usize> {
+		self.respond_status_reason(status, reason_phrase(status), data)
+	}
+
+	/// Send a response with the given numeric status code and a custom reason phrase,
+	/// e.g. ``respond_status_reason(200, "Totally Fine", data)``.
+	///
+	/// Use ``respond_status`` to send the canonical reason phrase instead.
+	pub fn respond_status_reason(&mut self, status: u16, reason: &str, data: &[u8]) -> io::Result<usize> {
+		self.respond(&format!("{} {}", status, reason), data, &vec!())
+	}
+
+	/// Send a HTTP 200 OK response with the content of the given file.
+	///
+	/// This is a shortcut for ``respond_ok_chunked`` which determines the
+	/// ``Content-Length`` from the file's metadata.
+	///
+	/// If the file grows while it is being sent, only the announced number of bytes
+	/// is sent. If it shrinks, the response can't be completed: the connection is shut
+	/// down so the client notices, and an ``UnexpectedEof`` error is returned.
+	///
+	/// On Linux, the file is handed to the kernel with ``sendfile(2)``, which saves
+	/// copying large files through a buffer; this isn't possible if an output filter
+	/// is set (see ``set_output_filter``).
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let file = std::fs::File::open("/some/local/file").unwrap();
+	/// client.respond_file_handle(file);
+	/// ```
+	pub fn respond_file_handle(&mut self, file: File) -> io::Result<usize> {
+		self.send_file("200 OK", file, &vec!())
+	}
+
+	/// Send the content of a temporary file, e.g. a large generated response,
+	/// and delete the file afterwards.
+	///
+	/// The file is sent from its beginning, so it may be passed on right after
+	/// writing to it. The ``Content-Length`` is determined from the file's metadata,
+	/// so the data never needs to fit into memory. The file is closed once it has
+	/// been sent; if ``path`` is given, the file at this path is deleted then, no matter
+	/// whether sending succeeded. Failing to delete it is only logged.
+	///
+	/// As in ``respond_file_handle``, an ``UnexpectedEof`` error is returned if
+	/// the file shrinks while it is being sent.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # use std::{fs::File, io::Write, path::Path};
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let path = Path::new("/tmp/report.csv");
+	/// let mut file = File::options().read(true).write(true).create(true).open(path).unwrap();
+	/// for i in 0..1_000_000 {
+	///     writeln!(file, "{},{}", i, i * i).unwrap();
+	/// }
+	/// client.respond_temp_file("200 OK", file, Some(path), &["Content-Type: text/csv"]);
+	/// ```
+	pub fn respond_temp_file(
+		&mut self,
+		status_code: &str,
+		mut file: File,
+		path: Option<&Path>,
+		headers: &[&str]) -> io::Result<usize>
+	{
+		let headers = headers.iter().map(|h| h.to_string()).collect();
+		let result = file.rewind().and_then(|_| self.send_file(status_code, file, &headers));
+
+		if let Some(path) = path {
+			if let Err(e) = fs::remove_file(path) {
+				warn!("Could not delete temporary file {}: {}", path.display(), e);
+			}
+		}
+
+		result
+	}
+
+	// Send the file, with a Content-Length taken from its metadata.
+	// Unless the data passes an output filter, the body is handed to the
+	// kernel with sendfile(2) where possible (Linux only); whatever it didn't
+	// send is copied the usual way.
+	fn send_file(&mut self, status_code: &str, file: File, headers: &Vec<String>) -> io::Result<usize> {
+		let file_len = file.metadata()?.len() as usize;
+		if self.output_filter.is_some() {
+			return self.respond_chunked(status_code, file, file_len, headers);
+		}
+
+		let (head_len, body_allowed) = self.write_head(self.response_protocol(), status_code, Some(file_len), headers)?;
+		if !body_allowed {
+			return Ok(head_len + self.write_body(io::empty(), None, 0)?);
+		}
+		let sent = sendfile(&self.stream, &file, file_len)?;
+		let body_len = self.write_body(file, Some(file_len), sent)?;
+		Ok(head_len + body_len)
+	}
+
+	/// Send response data to the client.
+	///
+	/// This is similar to ``respond_ok``, but you may control the details yourself.
+	///
+	/// Consider using ``respond_chunked`` for sending file-backed data.
+	///
+	/// # Parameters
+	/// * ``status_code``: Select the status code of the response, e.g. ``200 OK``.
+	/// * ``data``: Data to transmit. May be empty.
+	/// * ``headers``: Additional headers to add to the response. May be empty.
+	///
+	/// Calling ``respond("200 OK", data, &vec!())`` is the same as calling ``respond_ok(data)``.
+	///
+	/// Responses are sent as HTTP/1.0 and the connection is closed afterwards, which
+	/// is what HTTP/1.0 clients expect by default, so no ``Connection`` header is added.
+	/// See ``MicroHTTP::set_protocol_version`` to answer HTTP/1.1 clients with HTTP/1.1.
+	///
+	/// Responses with a status which doesn't allow a body (``1xx``, ``204 No Content``
+	/// and ``304 Not Modified``) are sent without ``Content-Length``; passing data
+	/// along with such a status results in an ``InvalidInput`` error.
+	///
+	/// The headers are checked before anything is sent: if the status code or a header
+	/// contains CR, LF or NUL (which would allow response splitting), or if there are more or larger headers than allowed (see
+	/// ``MicroHTTP::set_max_response_headers``), an ``InvalidInput`` error is returned.
+	/// This applies to all respond methods.
+	pub fn respond(
+		&mut self,
+		status_code: &str,
+		data: &[u8],
+		headers: &Vec<String>) -> io::Result<usize>
+	{
+		self.respond_chunked(status_code, data, data.len(), headers)
+	}
+
+	/// Send repsonse data to the client.
+	/// 
+	/// This is similar to ``respond_ok_chunked``, but you may control the details
+	/// yourself.
+	///
+	/// # Parameters
+	/// * ``status_code``: Select the status code of the response, e.ge ``200 OK``.
+	/// * ``data``: Data to transmit. May be empty
+	/// * ``content_size``: Size of the data to transmit in bytes
+	/// * ``headers``: Additional headers to add to the response. May be empty.
+	///
+	/// Calling ``respond_chunked("200 OK", data, content_size, &vec!())`` is the same as calling
+	/// ``repsond_ok_chunked(data, content_size)``.
+	///
+	/// If ``data`` ends before ``content_size`` bytes were sent, the connection is shut
+	/// down and an ``UnexpectedEof`` error is returned; if it has more data, the rest is
+	/// left unread. See ``MicroHTTP::set_strict_content_length`` for details.
+	///
+	/// If reading ``data`` fails, e.g. because a file can't be read halfway through,
+	/// the error is returned and the connection is shut down as well, so the client
+	/// notices that the body is incomplete instead of waiting for the rest.
+	pub fn respond_chunked(
+		&mut self,
+		status_code: &str,
+		data: impl Read,
+		content_size: usize,
+		headers: &Vec<String>) -> io::Result<usize> 
+	{
+		self.write_response(status_code, Some(content_size), data, headers)
+	}
+
+	/// Send response data to the client without a ``Content-Length`` header.
+	///
+	/// The end of the body is signalled by closing the connection: after the
+	/// data has been sent, the sending side of the connection is shut down.
+	/// This is how HTTP/1.0 delimits bodies of unknown length, e.g. for a stream
+	/// whose size isn't known in advance. Note that the client cannot tell
+	/// a complete body from a truncated one this way.
+	///
+	/// If you frame the body yourself (e.g. already chunk-encoded data), pass
+	/// the matching ``Transfer-Encoding`` or ``Content-Length`` in ``headers``;
+	/// they are sent unchanged.
+	///
+	/// If reading ``data`` fails, the error is returned and the connection is reset
+	/// once the ``Client`` is dropped, so the client doesn't mistake the partial body
+	/// for a complete one.
+	///
+	/// # Parameters
+	/// * ``status_code``: Select the status code of the response, e.g. ``200 OK``.
+	/// * ``data``: Data to transmit. May be empty.
+	/// * ``headers``: Additional headers to add to the response. May be empty.
+	pub fn respond_unframed(
+		&mut self,
+		status_code: &str,
+		data: impl Read,
+		headers: &Vec<String>) -> io::Result<usize>
+	{
+		let bytes_written = self.write_response(status_code, None, data, headers)?;
+		self.stream.shutdown(Shutdown::Write)?;
+		Ok(bytes_written)
+	}
+
+	/// Start a response whose body is sent in chunks of your choice.
+	///
+	/// The status line and the headers are sent right away, along with
+	/// ``Transfer-Encoding: chunked``. The body is then sent with
+	/// ``ChunkedResponse::write_chunk`` and ended with ``ChunkedResponse::finish``,
+	/// which may add trailer fields if the client accepts them (see ``accepts_trailers``).
+	///
+	/// Chunked transfer coding is only used for ``HTTP/1.1`` responses, i.e. with
+	/// ``ProtocolVersion::Http11`` (see ``MicroHTTP::set_protocol_version``) and an
+	/// HTTP/1.1 client. Otherwise, the body is sent as it is and ends when the
+	/// connection is closed.
+	///
+	/// # Parameters
+	/// * ``status_code``: Select the status code of the response, e.g. ``200 OK``.
+	/// * ``headers``: Additional headers to add to the response. May be empty.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let mut response = client.begin_chunked("200 OK", &["Content-Type: text/plain"]).unwrap();
+	/// for line in ["first line\n", "second line\n"].iter() {
+	///     response.write_chunk(line.as_bytes()).unwrap();
+	/// }
+	/// response.finish(&[]).unwrap();
+	/// ```
+	pub fn begin_chunked(&mut self, status_code: &str, headers: &[&str]) -> io::Result<ChunkedResponse<'_>> {
+		// Chunked transfer coding requires HTTP/1.1 responses.
+		let protocol = self.response_protocol();
+		let chunked = protocol == "HTTP/1.1";
+		let mut all_headers = headers.iter().map(|h| h.to_string()).collect::<Vec<String>>();
+		if chunked {
+			all_headers.push("Transfer-Encoding: chunked".to_string());
+		}
+		let (bytes_written, _) = self.write_head(protocol, status_code, None, &all_headers)?;
+
+		let trailers = chunked && self.accepts_trailers();
+		let output = Output { stream: &mut self.stream, filter: self.output_filter.as_mut() };
+		let shutdown = self.settings.shutdown_after_response;
+		Ok(ChunkedResponse::new(output, chunked, trailers, shutdown, bytes_written, self.received_at, &mut self.handling_time))
+	}
+
+	/// Start a response whose body is collected in memory before it is sent.
+	///
+	/// This is useful if the body is built piece by piece, but should still be sent
+	/// with a ``Content-Length`` instead of in chunks. The body may be at most
+	/// ``max_size`` bytes large.
+	///
+	/// # Parameters
+	/// * ``status_code``: Select the status code of the response, e.g. ``200 OK``.
+	/// * ``headers``: Additional headers to add to the response. May be empty.
+	/// * ``max_size``: Maximum size of the body in bytes.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// use std::io::Write;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let mut response = client.begin_buffered("200 OK", &["Content-Type: text/csv"], 1024 * 1024);
+	/// for (name, count) in [("apples", 3), ("pears", 5)].iter() {
+	///     writeln!(response, "{},{}", name, count).unwrap();
+	/// }
+	/// response.finish().unwrap();
+	/// ```
+	pub fn begin_buffered(&mut self, status_code: &str, headers: &[&str], max_size: usize) -> BufferedResponse<'_> {
+		BufferedResponse::new(self, status_code, headers, max_size)
+	}
+
+	/// Add a cookie to the response, e.g. ``session=38afes7a8; HttpOnly; Path=/``.
+	///
+	/// This may be called several times; each cookie is sent in a ``Set-Cookie``
+	/// header line of its own, since cookies can't be combined into one line
+	/// like other headers (their ``Expires`` attribute contains a comma). The
+	/// cookies are added to whichever response is sent next, after its other headers.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// client.add_cookie("session=38afes7a8; HttpOnly; Path=/");
+	/// client.add_cookie("theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT");
+	/// client.respond_ok(b"Welcome!").unwrap();
+	/// ```
+	pub fn add_cookie(&mut self, cookie: &str) {
+		self.cookies.push(cookie.to_string());
+	}
+
+	/// Send all response data through the given filter instead of writing it
+	/// to the connection directly, e.g. to count or log the bytes sent.
+	///
+	/// The filter is called with every piece of data the respond methods send,
+	/// including the status line and the headers, along with the connection. It
+	/// is responsible for writing the data to the connection, possibly after
+	/// transforming it; the respond methods consider the data written once the
+	/// filter returns ``Ok``. An error returned by the filter is returned by the
+	/// respond method.
+	///
+	/// The filter is owned by the ``Client`` and dropped along with it; it doesn't
+	/// outlive the connection. To get data out of it, e.g. a byte count, share it
+	/// via an ``Arc`` as below. Setting a new filter replaces the previous one.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+	///
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let bytes_sent = Arc::new(AtomicUsize::new(0));
+	/// let counter = bytes_sent.clone();
+	/// client.set_output_filter(move |data, connection| {
+	///     counter.fetch_add(data.len(), Ordering::Relaxed);
+	///     connection.write_all(data)
+	/// });
+	///
+	/// client.respond_ok(b"Hello!").unwrap();
+	/// println!("Sent {} bytes", bytes_sent.load(Ordering::Relaxed));
+	/// ```
+	pub fn set_output_filter(&mut self, filter: impl FnMut(&[u8], &mut dyn Write) -> io::Result<()> + Send + 'static) {
+		self.output_filter = Some(OutputFilter(Box::new(filter)));
+	}
+
+	// The writer all response data goes through.
+	fn output(&mut self) -> Output<'_> {
+		Output {
+			stream: &mut self.stream,
+			filter: self.output_filter.as_mut()
+		}
+	}
+
+	// Write the status line and the headers, followed by the empty line.
+	// Content-Length is only added if content_size is given. Returns the length
+	// of the head and whether its final status, which middleware may have
+	// changed, allows a body.
+	fn write_head(
+		&mut self,
+		protocol: &str,
+		status_code: &str,
+		content_size: Option<usize>,
+		headers: &Vec<String>) -> io::Result<(usize, bool)>
+	{
+		let mut parts = ResponseParts::new(status_code, headers);
+		parts.headers.extend(self.cookies.iter().map(|c| format!("Set-Cookie: {}", c)));
+		for middleware in &self.settings.response_middleware {
+			(middleware.0)(self, &mut parts);
+		}
+
+		let head = build_head(&self.settings, protocol, &parts.status, content_size, &parts.headers)?;
+		self.output().write_all(head.as_bytes())?;
+		Ok((head.len(), !forbids_body(&parts.status)))
+	}
+
+	// The protocol version of responses, see MicroHTTP::set_protocol_version.
+	fn response_protocol(&self) -> &'static str {
+		response_protocol(&self.settings, self.http_version())
+	}
+
+	// Write the status line, the headers and the body read from data.
+	// Content-Length is only added if content_size is given, and never for
+	// statuses which don't allow a body. If middleware changed the status to
+	// one of those, the body is dropped.
+	fn write_response(
+		&mut self,
+		status_code: &str,
+		content_size: Option<usize>,
+		data: impl Read,
+		headers: &Vec<String>) -> io::Result<usize>
+	{
+		if forbids_body(status_code) && content_size.unwrap_or(0) > 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("A {} response must not have a body", status_code)));
+		}
+
+		let (head_len, body_allowed) = self.write_head(self.response_protocol(), status_code, content_size, headers)?;
+		let body_len = match body_allowed {
+			true => self.write_body(data, content_size, 0)?,
+			false => self.write_body(io::empty(), None, 0)?
+		};
+		Ok(head_len + body_len)
+	}
+
+	// Write the body read from data, of which already_sent bytes have been
+	// sent by other means. Returns the size of the whole body.
+	fn write_body(
+		&mut self,
+		data: impl Read,
+		content_size: Option<usize>,
+		already_sent: usize) -> io::Result<usize>
+	{
+		let mut bytes_written = already_sent;
+
+		// Never send more than announced, even if data has more to offer.
+		let mut data = data.take(content_size.map_or(u64::MAX, |size| (size - already_sent) as u64));
+
+		let mut buffer = vec![0; self.settings.buffer_size];
+		loop {
+			let bytes_read = match data.read(&mut buffer) {
+				Ok(bytes_read) => bytes_read,
+				Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+				Err(e) => {
+					// Part of the body may have been sent already.
+					abort_response(&self.stream, content_size.is_some());
+					return Err(e);
+				}
+			};
+			if bytes_read == 0 { break; }
+			self.output().write_all(&buffer[..bytes_read])?;
+			bytes_written += bytes_read;
+		}
+		self.handling_time = Some(self.received_at.elapsed());
 
-		// Extract the request
-		let request = extract_request_url(&data);
+		if let Some(content_size) = content_size {
+			// The client would wait for the missing bytes forever.
+			if data.limit() > 0 {
+				self.stream.shutdown(Shutdown::Both)?;
+				return Err(io::Error::new(
+					io::ErrorKind::UnexpectedEof,
+					format!("Body ended early, {} of {} bytes are missing", data.limit(), content_size)));
+			}
 
-		Ok(Client {
-			stream: stream,
-			addr: addr,
-			request: match request {
-				Some(s) => s.into(),
-				None => None
+			if self.settings.strict_content_length && data.into_inner().read(&mut [0u8])? > 0 {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("Body is larger than its Content-Length of {} bytes", content_size)));
 			}
-		})
+		}
+
+		if self.settings.shutdown_after_response {
+			self.shutdown_write()?;
+		}
+		Ok(bytes_written)
 	}
 
-	/// Return the address of the requesting client, for example "1.2.3.4:9435".
-	pub fn addr(&self) -> SocketAddr {
-		self.addr
+	/// Finish the request and close the connection right away.
+	///
+	/// Dropping a ``Client`` closes the connection as well, but this makes
+	/// the point explicit, e.g. to acknowledge a request quickly and then
+	/// continue with a long-running job:
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond("202 Accepted", &[], &vec!()).unwrap();
+	/// client.finish().unwrap();
+	///
+	/// // The client already got its response; now do the actual work.
+	/// ```
+	pub fn finish(mut self) -> io::Result<()> {
+		self.stream.flush()?;
+		match self.stream.shutdown(Shutdown::Both) {
+			// The client may have closed the connection already.
+			Err(ref e) if e.kind() == io::ErrorKind::NotConnected => Ok(()),
+			result => result
+		}
 	}
 
-	/// Return the request the client made or None if the client
-	/// didn't make any or an invalid one.
+	/// Shut down the sending side of the connection after the response.
 	///
-	/// **Note**: At the moment, only HTTP GET is supported.
-	/// Any other requests will not be collected.
-	pub fn request(&self) -> &Option<String> {
-		&self.request
+	/// The client then sees the end of the response right away, even if the
+	/// ``Client`` is kept around for a while, e.g. to do some more work after
+	/// responding. Nothing can be sent afterwards, but the request body can
+	/// still be read.
+	pub fn shutdown_write(&mut self) -> io::Result<()> {
+		self.stream.flush()?;
+		match self.stream.shutdown(Shutdown::Write) {
+			// The client may have closed the connection already.
+			Err(ref e) if e.kind() == io::ErrorKind::NotConnected => Ok(()),
+			result => result
+		}
 	}
 
-	/// Send a HTTP 200 OK response to the client + the provided data.
-	/// The data may be an empty array, for example the following
-	/// implementation echos all requests except "/hello":
+	/// Write a complete, pre-formed HTTP response to the client exactly as given.
 	///
-	/// Consider using ``respond_ok_chunked`` for sending file-backed data.
+	/// Unlike the other respond methods, nothing is added: no status line, no
+	/// ``Content-Length`` and no other headers. The caller is fully responsible
+	/// for producing a valid response, including the CRLF line endings, the empty
+	/// line after the headers and correct framing of the body.
 	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond_raw(b"HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nOK");
 	/// ```
-	/// use micro_http_server::MicroHTTP;
-	/// use std::io::*;
-	/// let server = MicroHTTP::new("127.0.0.1:4000").expect("Could not create server.");
-	/// # let mut connection = ::std::net::TcpStream::connect("127.0.0.1:4000").unwrap();
-	/// # connection.write("GET /\r\n\r\n".as_bytes());
-	/// let mut client = server.next_client().unwrap().unwrap();
-	/// let request_str: String = client.request().as_ref().unwrap().clone();
+	pub fn respond_raw(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.output().write_all(data)?;
+		self.handling_time = Some(self.received_at.elapsed());
+		Ok(data.len())
+	}
+
+	/// Send an error response with the given status code, e.g. ``404 Not Found``.
 	///
-	/// match request_str.as_ref() {
-	/// 	"/hello" => client.respond_ok(&[]),
-	///     _ => client.respond_ok(request_str.as_bytes())  // Echo request
-	/// };
+	/// The body is the error page configured with ``MicroHTTP::set_error_page``
+	/// for this status code; if there is none, the body is empty.
+	pub fn respond_error(&mut self, status_code: &str) -> io::Result<usize> {
+		self.respond_error_page(status_code, vec!())
+	}
+
+	/// Send an error response like ``respond_error``, telling the client when it may
+	/// try again with a ``Retry-After`` header.
+	///
+	/// This is meant for ``429 Too Many Requests`` and ``503 Service Unavailable``.
+	/// ``retry_after`` is either a number of seconds or a ``Duration``, sent as
+	/// delta-seconds, or a ``SystemTime``, sent as HTTP-date (see ``RetryAfter``).
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # use std::time::{Duration, SystemTime};
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// // Retry-After: 120
+	/// client.respond_retry_after("503 Service Unavailable", 120);
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// // Retry-After: the date in an hour, e.g. Fri, 16 Oct 2026 13:00:00 GMT
+	/// client.respond_retry_after("503 Service Unavailable", SystemTime::now() + Duration::from_secs(3600));
 	/// ```
-	pub fn respond_ok(&mut self, data: &[u8]) -> io::Result<usize> {
-		self.respond_ok_chunked(data, data.len())
+	pub fn respond_retry_after(&mut self, status_code: &str, retry_after: impl Into<RetryAfter>) -> io::Result<usize> {
+		self.respond_error_page(status_code, vec!(format!("Retry-After: {}", retry_after.into())))
 	}
 
-	// The test in this doc comment is no_run because it refers to an arbitrary
-	// file that may not exist on the current system.
+	// Send the error page for the given status along with the given headers.
+	fn respond_error_page(&mut self, status_code: &str, mut headers: Vec<String>) -> io::Result<usize> {
+		let status = status_code.split(' ').next()
+			.and_then(|code| code.parse::<u16>().ok());
+		let settings = self.settings.clone();
 
-	/// Send a HTTP 200 OK response to the client + the provided data.
-	/// The data may be any type implementing [Read](std::io::Read) and
-	/// will be read in chunks. This is useful for serving file-backed
-	/// data that should not be loaded into memory all at once.
+		match status.and_then(|code| settings.error_pages.get(&code)) {
+			Some(page) => {
+				headers.push(format!("Content-Type: {}", page.content_type));
+				self.respond(status_code, &page.body, &headers)
+			},
+			None => self.respond(status_code, &[], &headers)
+		}
+	}
+
+	/// Send an HTML page to the client, with ``Content-Type: text/html; charset=utf-8``.
 	///
 	/// ```no_run
-	/// use micro_http_server::MicroHTTP;
-	/// use std::io::*;
-	/// use std::fs::*;
-	/// let server = MicroHTTP::new("127.0.0.1:4000").expect("Could not create server.");
-	/// # let mut connection = ::std::net::TcpStream::connect("127.0.0.1:4000").unwrap();
-	/// # connection.write("GET /\r\n\r\n".as_bytes());
-	/// let mut client = server.next_client().unwrap().unwrap();
-	/// client.request();
-	///
-	/// let mut file_handle = OpenOptions::new()
-	///		.read(true)
-	///		.write(false)
-	///		.open("/some/local/file")
-	///		.unwrap();
-	///	let file_len = file_handle.metadata().unwrap().len() as usize;
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond_html("200 OK", "<h1>Grüße!</h1>");
+	/// ```
+	pub fn respond_html(&mut self, status_code: &str, html: &str) -> io::Result<usize> {
+		self.respond_file_like(status_code, "text/html; charset=utf-8", html.as_bytes(), html.len(), &[])
+	}
+
+	/// Send plain text to the client, with ``Content-Type: text/plain; charset=utf-8``.
+	pub fn respond_text(&mut self, status_code: &str, text: &str) -> io::Result<usize> {
+		self.respond_file_like(status_code, "text/plain; charset=utf-8", text.as_bytes(), text.len(), &[])
+	}
+
+	/// Send the request as the server understood it back to the client, as plain text.
 	///
-	/// client.respond_ok_chunked(file_handle, file_len);
+	/// The body lists the method, the request target and the protocol version,
+	/// followed by all headers in the order they were received, e.g.:
 	///
+	/// ```text
+	/// GET /debug HTTP/1.1
+	/// Host: example.com
+	/// X-Forwarded-For: 10.0.0.1
 	/// ```
-	pub fn respond_ok_chunked(&mut self, data: impl Read, content_size: usize) -> io::Result<usize> {
-		self.respond_chunked("200 OK", data, content_size, &vec!())
+	///
+	/// This is meant for debugging endpoints, e.g. to see which headers are left
+	/// after passing a proxy. Parts of the request line which are missing or
+	/// invalid are shown as ``-``. The values of credentials and cookies are
+	/// replaced with ``<redacted>``, like in the log.
+	pub fn respond_debug(&mut self) -> io::Result<usize> {
+		let mut text = format!("{} {} {}\n",
+			self.method().unwrap_or("-"),
+			self.request.as_deref().unwrap_or("-"),
+			self.http_version().unwrap_or("-"));
+
+		for (name, value) in &self.headers {
+			match SENSITIVE_HEADERS.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+				true => text += &format!("{}: <redacted>\n", name),
+				false => text += &format!("{}: {}\n", name, value)
+			}
+		}
+
+		self.respond_text("200 OK", &text)
 	}
 
-	/// Send response data to the client.
+	/// Send an already serialized JSON document to the client, with ``Content-Type: application/json``.
+	pub fn respond_json(&mut self, status_code: &str, json: &str) -> io::Result<usize> {
+		self.respond_file_like(status_code, "application/json", json.as_bytes(), json.len(), &[])
+	}
+
+	/// Send an error as a JSON object of the form
+	/// ``{"error": {"code": 404, "message": "Not Found"}}``, with
+	/// ``Content-Type: application/json``.
 	///
-	/// This is similar to ``respond_ok``, but you may control the details yourself.
+	/// The code is taken from ``status_code``; the message is escaped as needed, so it
+	/// may contain anything, e.g. quotes or line breaks from an error message. Returns
+	/// an ``InvalidInput`` error if ``status_code`` doesn't start with a numeric code.
 	///
-	/// Consider using ``respond_chunked`` for sending file-backed data.
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond_json_error("422 Unprocessable Entity", "Field \"name\" is required");
+	/// ```
+	pub fn respond_json_error(&mut self, status_code: &str, message: &str) -> io::Result<usize> {
+		let code = status_code.split(' ').next()
+			.and_then(|code| code.parse::<u16>().ok())
+			.ok_or_else(|| io::Error::new(
+				io::ErrorKind::InvalidInput,
+				format!("Status {:?} has no numeric code", status_code)))?;
+
+		let json = format!("{{\"error\":{{\"code\":{},\"message\":{}}}}}", code, json_string(message));
+		self.respond_json(status_code, &json)
+	}
+
+	/// Send a blob of data with the given content type to the client.
+	///
+	/// This is a shortcut for ``respond_chunked`` which adds the ``Content-Type``
+	/// header for you, so you don't have to assemble the header list yourself.
 	///
 	/// # Parameters
 	/// * ``status_code``: Select the status code of the response, e.g. ``200 OK``.
+	/// * ``content_type``: The MIME type of the data, e.g. ``image/png``.
 	/// * ``data``: Data to transmit. May be empty.
-	/// * ``headers``: Additional headers to add to the response. May be empty.
+	/// * ``content_size``: Size of the data to transmit in bytes.
+	/// * ``extra_headers``: Additional headers to add to the response. May be empty.
 	///
-	/// Calling ``respond("200 OK", data, &vec!())`` is the same as calling ``respond_ok(data)``.
-	pub fn respond(
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let file = std::fs::File::open("/some/image.png").unwrap();
+	/// let file_len = file.metadata().unwrap().len() as usize;
+	///
+	/// client.respond_file_like("200 OK", "image/png", file, file_len, &["Cache-Control: max-age=3600"]);
+	/// ```
+	pub fn respond_file_like(
 		&mut self,
 		status_code: &str,
-		data: &[u8],
-		headers: &Vec<String>) -> io::Result<usize>
+		content_type: &str,
+		data: impl Read,
+		content_size: usize,
+		extra_headers: &[&str]) -> io::Result<usize>
 	{
-		self.respond_chunked(status_code, data, data.len(), headers)
+		let mut headers = vec!(format!("Content-Type: {}", content_type));
+		headers.extend(extra_headers.iter().map(|h| h.to_string()));
+		self.respond_chunked(status_code, data, content_size, &headers)
 	}
 
-	/// Send repsonse data to the client.
-	/// 
-	/// This is similar to ``respond_ok_chunked``, but you may control the details
-	/// yourself.
+	/// Send data as a download, i.e. with a ``Content-Disposition`` header which makes
+	/// browsers save it under the given filename instead of displaying it.
 	///
-	/// # Parameters
-	/// * ``status_code``: Select the status code of the response, e.ge ``200 OK``.
-	/// * ``data``: Data to transmit. May be empty
-	/// * ``content_size``: Size of the data to transmit in bytes
-	/// * ``headers``: Additional headers to add to the response. May be empty.
+	/// The filename may contain any characters, including quotes, spaces and non-ASCII
+	/// characters; it is sent both as ``filename*`` (RFC 5987) and, with anything but
+	/// printable ASCII replaced by ``_``, as ``filename`` for older clients. The
+	/// ``Content-Type`` is guessed from the filename's extension.
 	///
-	/// Calling ``respond_chunked("200 OK", data, content_size, &vec!())`` is the same as calling
-	/// ``repsond_ok_chunked(data, content_size)``.
-	pub fn respond_chunked(
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let file = std::fs::File::open("/some/report.pdf").unwrap();
+	/// let file_len = file.metadata().unwrap().len() as usize;
+	///
+	/// client.respond_download(file, file_len, "Jahresübersicht 2024.pdf");
+	/// ```
+	pub fn respond_download(&mut self, data: impl Read, content_size: usize, filename: &str) -> io::Result<usize> {
+		let headers = vec!(
+			format!("Content-Type: {}", static_files::content_type(Path::new(filename))),
+			format!("Content-Disposition: {}", content_disposition(filename)));
+		self.respond_chunked("200 OK", data, content_size, &headers)
+	}
+
+	/// Send data with the given content type, along with a SHA-256 digest of it.
+	///
+	/// This works like ``respond_file_like``, but the data is read twice: once
+	/// to compute its size and digest, and once more to send it. The digest is
+	/// announced in a ``Repr-Digest`` header (RFC 9530) and, for older clients,
+	/// in a ``Digest`` header (RFC 3230), so the client can verify the download.
+	/// For data which can't be read twice, see ``respond_with_trailing_digest``.
+	///
+	/// Only available with the ``sha2`` feature.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let file = std::fs::File::open("/some/archive.tar.gz").unwrap();
+	/// client.respond_with_digest("200 OK", "application/gzip", file, &[]);
+	/// ```
+	#[cfg(feature = "sha2")]
+	pub fn respond_with_digest(
 		&mut self,
 		status_code: &str,
-		mut data: impl Read,
-		content_size: usize,
-		headers: &Vec<String>) -> io::Result<usize> 
+		content_type: &str,
+		mut data: impl Read + io::Seek,
+		extra_headers: &[&str]) -> io::Result<usize>
 	{
-		// Write status line
-		let mut bytes_written =
-			self.stream.write(format!("HTTP/1.0 {}\r\nContent-Length: {}\r\n", status_code, content_size).as_bytes())?;
+		let start = data.stream_position()?;
+		let (digest, content_size) = crate::digest::sha256(&mut data)?;
+		data.seek(io::SeekFrom::Start(start))?;
+
+		let mut headers = vec!(format!("Content-Type: {}", content_type));
+		headers.extend(crate::digest::digest_headers(&digest));
+		headers.extend(extra_headers.iter().map(|h| h.to_string()));
+		self.respond_chunked(status_code, data.take(content_size), content_size as usize, &headers)
+	}
 
-		for h in headers {
-			bytes_written += self.stream.write(format!("{}\r\n", h).as_ref())?;
+	/// Send data with the given content type, followed by its SHA-256 digest
+	/// in a ``Repr-Digest`` trailer (RFC 9530).
+	///
+	/// Unlike ``respond_with_digest``, the data is read only once and needn't be
+	/// seekable: it is sent in chunks while the digest is computed, see
+	/// ``begin_chunked``. The trailer is only sent, and announced with a ``Trailer``
+	/// header, if the response is chunked and the client accepts trailers
+	/// (see ``accepts_trailers``); otherwise, the data is sent without a digest.
+	///
+	/// Only available with the ``sha2`` feature.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// let output = std::process::Command::new("tar")
+	///     .args(&["-cz", "/some/dir"])
+	///     .stdout(std::process::Stdio::piped())
+	///     .spawn().unwrap()
+	///     .stdout.unwrap();
+	/// client.respond_with_trailing_digest("200 OK", "application/gzip", output, &[]);
+	/// ```
+	#[cfg(feature = "sha2")]
+	pub fn respond_with_trailing_digest(
+		&mut self,
+		status_code: &str,
+		content_type: &str,
+		data: impl Read,
+		extra_headers: &[&str]) -> io::Result<usize>
+	{
+		let content_type = format!("Content-Type: {}", content_type);
+		let mut headers = vec!(content_type.as_str());
+		let trailers = self.response_protocol() == "HTTP/1.1" && self.accepts_trailers();
+		if trailers {
+			headers.push("Trailer: Repr-Digest");
 		}
-		bytes_written += self.stream.write("\r\n".as_bytes())?;
+		headers.extend(extra_headers);
 
-		let mut buffer = [0; Self::CHUNK_SIZE];
+		let mut buffer = vec![0; self.settings.buffer_size];
+		let mut data = crate::digest::Sha256Reader::new(data);
+		let mut response = self.begin_chunked(status_code, &headers)?;
 		loop {
 			let bytes_read = data.read(&mut buffer)?;
 			if bytes_read == 0 { break; }
-			bytes_written += self.stream.write(&buffer[..bytes_read])?;
+			response.write_chunk(&buffer[..bytes_read])?;
 		}
 
-		Ok(bytes_written)
+		match trailers {
+			true => response.finish(&[&crate::digest::repr_digest(&data.finalize())]),
+			false => response.finish(&[])
+		}
+	}
+
+	/// Send a response produced on demand, unless the client already has it.
+	///
+	/// ``etag`` identifies the current version of the response, e.g. a version
+	/// number or a hash, and is sent in the ``ETag`` header. Quotes are added if
+	/// it isn't quoted yet. If the ``If-None-Match`` header of the request matches
+	/// it, ``304 Not Modified`` is sent without calling ``body`` at all. Otherwise,
+	/// ``body`` is called and its result is sent with ``200 OK``.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// # let revision = 42;
+	/// client.respond_cached(&format!("rev-{}", revision), || {
+	///     // Expensive rendering, only done if the client's copy is outdated.
+	///     format!("<h1>Revision {}</h1>", revision)
+	/// });
+	/// ```
+	pub fn respond_cached<B: AsRef<[u8]>>(&mut self, etag: &str, body: impl FnOnce() -> B) -> io::Result<usize> {
+		let etag = quote_etag(etag);
+		let headers = vec!(format!("ETag: {}", etag));
+
+		match self.header("If-None-Match").is_some_and(|v| etag_matches(v, &etag)) {
+			true => self.write_response("304 Not Modified", None, io::empty(), &headers),
+			false => self.respond("200 OK", body().as_ref(), &headers)
+		}
+	}
+
+	/// Return the entity tags listed in the ``If-Match`` header, e.g.
+	/// ``["\"v1\"", "\"v2\""]``, or ``["*"]`` if the client accepts any existing
+	/// version; None if the header is missing.
+	///
+	/// Use ``if_match_passes`` to evaluate the header.
+	pub fn if_match(&self) -> Option<Vec<&str>> {
+		let value = self.header("If-Match")?;
+		Some(value.split(',').map(str::trim).filter(|t| !t.is_empty()).collect())
+	}
+
+	/// Evaluate the ``If-Match`` precondition of the request against the current
+	/// entity tag of the resource, or None if the resource doesn't exist.
+	///
+	/// Returns true if the request may proceed: there is no ``If-Match`` header,
+	/// it lists ``current_etag``, or it is ``*`` and the resource exists. Otherwise,
+	/// the client's copy is outdated and the request should be answered with
+	/// ``412 Precondition Failed`` instead, so it doesn't overwrite someone else's
+	/// changes. Tags are compared strongly, so weak tags (``W/"..."``) never match.
+	/// As in ``respond_cached``, quotes are added to ``current_etag`` if needed.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// # let mut client = server.next_client().unwrap().unwrap();
+	/// # let revision = 42;
+	/// if !client.if_match_passes(Some(&format!("rev-{}", revision))) {
+	///     client.respond_error("412 Precondition Failed").unwrap();
+	///     return;
+	/// }
+	/// // Store the update, then respond.
+	/// ```
+	pub fn if_match_passes(&self, current_etag: Option<&str>) -> bool {
+		match self.header("If-Match") {
+			Some(if_match) => if_match_matches(if_match, current_etag),
+			None => true
+		}
+	}
+
+	/// Send the part of ``data`` the client asked for with a ``Range`` header,
+	/// or all of it if it didn't ask for a part.
+	///
+	/// Pass the value of the request's ``Range`` header, e.g. ``client.header("Range")``,
+	/// as ``range_header``. The data may be any seekable source, e.g. a ``File`` or a
+	/// ``Cursor`` over data in memory; it is sent from its beginning.
+	///
+	/// * A single range like ``bytes=0-499``, ``bytes=500-`` or ``bytes=-500`` (the last
+	///   500 bytes) is answered with ``206 Partial Content`` and a ``Content-Range`` header.
+	/// * A range which lies completely outside the data is answered with
+	///   ``416 Range Not Satisfiable``.
+	/// * Without a ``Range`` header, or with one which asks for several ranges or is
+	///   invalid, all of the data is sent with the given status.
+	///
+	/// Ranges are only applied to ``200 OK`` responses. The response always carries
+	/// ``Accept-Ranges: bytes``, plus the given headers.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let range = client.header("Range").map(String::from);
+	/// let video = std::fs::File::open("/srv/video.mp4").unwrap();
+	/// client.respond_range("200 OK", video, range.as_deref(), &["Content-Type: video/mp4"]);
+	/// ```
+	pub fn respond_range(
+		&mut self,
+		status_code: &str,
+		mut data: impl Read + Seek,
+		range_header: Option<&str>,
+		extra_headers: &[&str]) -> io::Result<usize>
+	{
+		let len = data.seek(io::SeekFrom::End(0))?;
+		data.rewind()?;
+
+		let mut headers = vec!("Accept-Ranges: bytes".to_string());
+		headers.extend(extra_headers.iter().map(|h| h.to_string()));
+
+		let range = match status_code.starts_with("200 ") {
+			true => parse_range(range_header, len),
+			false => ByteRange::Full
+		};
+		match range {
+			ByteRange::Full => self.respond_chunked(status_code, data, len as usize, &headers),
+			ByteRange::Partial(start, end) => {
+				data.seek(io::SeekFrom::Start(start))?;
+				headers.push(format!("Content-Range: bytes {}-{}/{}", start, end, len));
+				let part_len = end - start + 1;
+				self.respond_chunked("206 Partial Content", data.take(part_len), part_len as usize, &headers)
+			},
+			ByteRange::Unsatisfiable => {
+				headers.push(format!("Content-Range: bytes */{}", len));
+				self.respond("416 Range Not Satisfiable", &[], &headers)
+			}
+		}
+	}
+
+	/// Send the part of in-memory data the client asked for with a ``Range`` header,
+	/// or all of it if it didn't ask for a part.
+	///
+	/// This works like ``respond_range``, e.g. for generated data or embedded assets:
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let data = vec![0u8; 1024 * 1024];
+	/// let range = client.header("Range").map(String::from);
+	/// client.respond_bytes_range("200 OK", &data, range.as_deref());
+	/// ```
+	pub fn respond_bytes_range(&mut self, status_code: &str, data: impl AsRef<[u8]>, range_header: Option<&str>) -> io::Result<usize> {
+		self.respond_range(status_code, io::Cursor::new(data.as_ref()), range_header, &[])
+	}
+
+	/// Send the given file, or a gzip-compressed copy of it if there is one.
+	///
+	/// If a file with the same name plus ``.gz`` exists next to it (e.g. ``app.js.gz``
+	/// for ``app.js``) and the client accepts gzip, the compressed file is sent as it is,
+	/// with ``Content-Encoding: gzip``. Otherwise, the uncompressed file is sent. Either
+	/// way, the content type is guessed from the uncompressed file's extension.
+	///
+	/// This saves compressing the same files over and over again; compress them
+	/// once up front, e.g. with ``gzip -k app.js``.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond_precompressed("/var/www/app.js").expect("Could not send file.");
+	/// ```
+	pub fn respond_precompressed(&mut self, path: impl AsRef<Path>) -> io::Result<usize> {
+		let path = path.as_ref();
+		let content_type = static_files::content_type(path);
+		let compressed = static_files::gzip_sibling(path);
+
+		let (file, headers) = match compressed {
+			Some(ref gz) if self.accepts_encoding("gzip") =>
+				(File::open(gz)?, vec!("Content-Encoding: gzip", "Vary: Accept-Encoding")),
+			Some(_) => (File::open(path)?, vec!("Vary: Accept-Encoding")),
+			None => (File::open(path)?, vec!())
+		};
+		let file_len = file.metadata()?.len();
+
+		self.respond_file_like("200 OK", content_type, file.take(file_len), file_len as usize, &headers)
+	}
+
+	/// Send data compressed with the best content coding the client accepts.
+	///
+	/// The coding is chosen from the client's ``Accept-Encoding`` header, respecting
+	/// the ``q`` values given there: ``br`` (with the ``brotli`` feature), ``gzip`` or
+	/// ``deflate`` (both with the ``flate2`` feature). If the client prefers none of
+	/// them, or didn't send the header at all, the data is sent uncompressed. If it
+	/// refuses uncompressed data with ``identity;q=0`` and accepts none of the codings
+	/// either, ``406 Not Acceptable`` is sent instead.
+	///
+	/// The response carries ``Vary: Accept-Encoding``, plus a ``Content-Encoding``
+	/// header if the data was compressed.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let report = "Lots of text. ".repeat(1000);
+	/// client.respond_negotiated_compressed("200 OK", report.as_bytes()).expect("Could not send report.");
+	/// ```
+	pub fn respond_negotiated_compressed(&mut self, status_code: &str, body: &[u8]) -> io::Result<usize> {
+		let vary = String::from("Vary: Accept-Encoding");
+
+		match compression::negotiate(self.header("Accept-Encoding"), compression::SUPPORTED) {
+			Some(Coding::Compressed(coding)) => {
+				let compressed = compression::compress(coding, body)?;
+				self.respond(status_code, &compressed, &vec!(format!("Content-Encoding: {}", coding), vary))
+			},
+			Some(Coding::Identity) => self.respond(status_code, body, &vec!(vary)),
+			None => self.respond("406 Not Acceptable", &[], &vec!(vary))
+		}
+	}
+
+	/// Send an HTML page listing the contents of a directory below ``root``.
+	///
+	/// ``request_path`` is the path of the directory as requested by the client,
+	/// e.g. ``/downloads/`` or the whole request target. It is resolved below ``root``
+	/// like in ``respond_static``, so requests which would escape the root directory
+	/// are answered with ``403 Forbidden`` and missing directories with ``404 Not Found``.
+	/// Directories which can't be read are answered with ``403 Forbidden`` as well.
+	///
+	/// The page links to every entry of the directory and to the parent directory,
+	/// using relative links. These only work if the path ends with a slash, so
+	/// a path without one is redirected to the same path with a slash.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// let target = client.request().clone().unwrap_or_default();
+	/// client.respond_dir_listing("/srv/share", &target).expect("Could not send listing.");
+	/// ```
+	pub fn respond_dir_listing(&mut self, root: impl AsRef<Path>, request_path: &str) -> io::Result<usize> {
+		let path = request_path.split(['?', '#']).next().unwrap_or("");
+
+		match static_files::resolve_dir(root.as_ref(), path) {
+			// A leading "//" would make the location point to another host.
+			Lookup::Found(_) if !path.ends_with('/') => self.respond(
+				"301 Moved Permanently",
+				&[],
+				&vec!(format!("Location: /{}/", path.trim_start_matches('/')))),
+			Lookup::Found(dir) => match static_files::listing(&dir, &percent_decode(path, false)) {
+				Ok(html) => self.respond_html("200 OK", &html),
+				Err(e) => {
+					warn!("Could not list directory {:?}: {}", dir, e);
+					self.respond_error("403 Forbidden")
+				}
+			},
+			Lookup::Forbidden => {
+				warn!("{} tried to access {:?} outside of the served directory", self.addr, request_path);
+				self.respond_error("403 Forbidden")
+			},
+			Lookup::NotFound => self.respond_error("404 Not Found")
+		}
+	}
+
+	/// Serve the file requested by the client from the given root directory.
+	///
+	/// The request path is percent-decoded and ``.`` / ``..`` segments are resolved;
+	/// requests which would escape the root directory (e.g. ``/../../etc/passwd``
+	/// or ``/%2e%2e%2fsecret``) or contain NUL bytes are answered with ``403 Forbidden``.
+	/// Missing files are answered with ``404 Not Found``. For a directory, its
+	/// ``index.html`` is served. The content type is guessed from the file extension.
+	/// Pre-compressed ``.gz`` files are used as described for ``respond_precompressed``.
+	///
+	/// ```no_run
+	/// # use micro_http_server::MicroHTTP;
+	/// # let server = MicroHTTP::new("127.0.0.1:4000").unwrap();
+	/// let mut client = server.next_client().unwrap().unwrap();
+	/// client.respond_static("/var/www").expect("Could not send file.");
+	/// ```
+	pub fn respond_static(&mut self, root: impl AsRef<Path>) -> io::Result<usize> {
+		let target = self.request.clone().unwrap_or_default();
+
+		match static_files::resolve(root.as_ref(), &target) {
+			Lookup::Found(path) => self.respond_precompressed(path),
+			Lookup::Forbidden => {
+				warn!("{} tried to access {:?} outside of the served directory", self.addr, target);
+				self.respond_error("403 Forbidden")
+			},
+			Lookup::NotFound => self.respond_error("404 Not Found")
+		}
+	}
+}
+
+impl Drop for Client {
+	fn drop(&mut self) {
+		self.settings.connection_closed(self.addr);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{accepts_coding, check_header_line, content_disposition, set_linger, etag_matches, if_match_matches, json_string, keep_alive, parse_proxy_header, upgrade_protocol};
+
+	#[test]
+	fn content_dispositions() {
+		assert_eq!(r#"attachment; filename="report.pdf"; filename*=UTF-8''report.pdf"#,
+			content_disposition("report.pdf"));
+		assert_eq!(r#"attachment; filename="my \"best\" file.txt"; filename*=UTF-8''my%20%22best%22%20file.txt"#,
+			content_disposition(r#"my "best" file.txt"#));
+		assert_eq!(r#"attachment; filename="a\\b_c.txt"; filename*=UTF-8''a%5Cb%0Ac.txt"#,
+			content_disposition("a\\b\nc.txt"));
+		assert_eq!(r#"attachment; filename="Gr__e _.txt"; filename*=UTF-8''Gr%C3%BC%C3%9Fe%20%E2%82%AC.txt"#,
+			content_disposition("Grüße €.txt"));
+	}
+
+	#[test]
+	fn json_strings() {
+		assert_eq!(r#""plain""#, json_string("plain"));
+		assert_eq!(r#""""#, json_string(""));
+		assert_eq!(r#""say \"hi\"""#, json_string(r#"say "hi""#));
+		assert_eq!(r#""a\\b""#, json_string(r"a\b"));
+		assert_eq!(r#""line 1\nline 2\r\n\tend""#, json_string("line 1\nline 2\r\n\tend"));
+		assert_eq!(r#""\u0000\u001f""#, json_string("\u{0}\u{1f}"));
+		assert_eq!(r#""Grüße </script>""#, json_string("Grüße </script>"));
+	}
+
+	#[test]
+	fn keep_alive_defaults() {
+		assert!(!keep_alive(Some("HTTP/1.0"), None));
+		assert!(!keep_alive(Some("HTTP/1.0"), Some("close")));
+		assert!(keep_alive(Some("HTTP/1.0"), Some("Keep-Alive")));
+		assert!(keep_alive(Some("HTTP/1.1"), None));
+		assert!(keep_alive(Some("HTTP/1.1"), Some("keep-alive")));
+		assert!(!keep_alive(Some("HTTP/1.1"), Some("Upgrade, close")));
+		assert!(!keep_alive(None, None));
+	}
+
+	#[test]
+	fn proxy_header() {
+		let data = "PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET / HTTP/1.1\r\n\r\n";
+		let (len, addr) = parse_proxy_header(data.as_bytes()).unwrap();
+		assert_eq!("GET / HTTP/1.1\r\n\r\n", &data[len..]);
+		assert_eq!(Some("192.168.0.1:56324".parse().unwrap()), addr);
+
+		let data = "PROXY TCP6 ::1 ::1 4000 80\r\n";
+		assert_eq!(Some("[::1]:4000".parse().unwrap()), parse_proxy_header(data.as_bytes()).unwrap().1);
+
+		assert_eq!(Some((15, None)), parse_proxy_header("PROXY UNKNOWN\r\n".as_bytes()));
+		assert_eq!(None, parse_proxy_header("GET / HTTP/1.1\r\n\r\n".as_bytes()));
+		assert_eq!(None, parse_proxy_header("PROXY TCP4 a b c d\r\n".as_bytes()));
 	}
 
-	const CHUNK_SIZE: usize = 4096;
+	#[test]
+	fn header_injection() {
+		assert!(check_header_line("X-Name: John Doe").is_ok());
+		assert!(check_header_line("X-Name: Jürgen\t(tab)").is_ok());
+
+		let payloads = [
+			"X-Name: a\r\nSet-Cookie: session=evil",
+			"X-Name: a\nSet-Cookie: session=evil",
+			"X-Name: a\rSet-Cookie: session=evil",
+			"X-Name: a\r\n\r\n<html>fake body</html>",
+			"X-Name: a\0b",
+			"200 OK\r\nContent-Length: 0\r\n\r\nHTTP/1.0 200 OK",
+		];
+		for payload in payloads.iter() {
+			let err = check_header_line(payload).unwrap_err();
+			assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+		}
+	}
+
+	#[test]
+	fn etags() {
+		assert!(etag_matches("\"v1\"", "\"v1\""));
+		assert!(etag_matches("\"v0\", W/\"v1\"", "\"v1\""));
+		assert!(etag_matches("\"v1\"", "W/\"v1\""));
+		assert!(etag_matches(" * ", "\"v1\""));
+		assert!(!etag_matches("\"v2\"", "\"v1\""));
+		assert!(!etag_matches("", "\"v1\""));
+	}
+
+	#[test]
+	fn if_match() {
+		assert!(if_match_matches("\"v1\"", Some("\"v1\"")));
+		assert!(if_match_matches("\"v0\", \"v1\"", Some("v1")));
+		assert!(!if_match_matches("\"v2\"", Some("\"v1\"")));
+		assert!(!if_match_matches("W/\"v1\"", Some("\"v1\"")));
+		assert!(!if_match_matches("\"v1\"", Some("W/\"v1\"")));
+		assert!(if_match_matches(" * ", Some("\"v1\"")));
+		assert!(if_match_matches("*", Some("W/\"v1\"")));
+		assert!(!if_match_matches("*", None));
+		assert!(!if_match_matches("\"v1\"", None));
+	}
+
+	#[test]
+	fn accept_encoding() {
+		assert!(accepts_coding("gzip", "gzip"));
+		assert!(accepts_coding("deflate, GZIP;q=0.5", "gzip"));
+		assert!(accepts_coding("*", "gzip"));
+		assert!(accepts_coding("br;q=1.0, *;q=0.1", "gzip"));
+		assert!(!accepts_coding("gzip;q=0", "gzip"));
+		assert!(!accepts_coding("gzip; q=0.000, *", "gzip"));
+		assert!(!accepts_coding("*;q=0", "gzip"));
+		assert!(!accepts_coding("deflate, br", "gzip"));
+		assert!(!accepts_coding("", "gzip"));
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn linger() {
+		use std::{net::{TcpListener, TcpStream}, os::unix::io::AsRawFd, time::Duration};
+
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+		set_linger(&stream, Duration::from_secs(3)).unwrap();
+
+		let mut value = libc::linger { l_onoff: 0, l_linger: 0 };
+		let mut len = std::mem::size_of::<libc::linger>() as libc::socklen_t;
+		let result = unsafe {
+			libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_LINGER,
+				&mut value as *mut libc::linger as *mut libc::c_void, &mut len)
+		};
+		assert_eq!(0, result);
+		assert_ne!(0, value.l_onoff);
+		assert_eq!(3, value.l_linger);
+	}
+
+	#[test]
+	fn upgrade() {
+		let v11 = Some("HTTP/1.1");
+		assert_eq!(Some("websocket"), upgrade_protocol(v11, Some("Upgrade"), Some("websocket")));
+		assert_eq!(Some("h2c"), upgrade_protocol(v11, Some("keep-alive, upgrade"), Some(" h2c, websocket")));
+		assert_eq!(None, upgrade_protocol(v11, Some("keep-alive"), Some("websocket")));
+		assert_eq!(None, upgrade_protocol(v11, None, Some("websocket")));
+		assert_eq!(None, upgrade_protocol(v11, Some("Upgrade"), None));
+		assert_eq!(None, upgrade_protocol(v11, Some("Upgrade"), Some("")));
+		assert_eq!(None, upgrade_protocol(Some("HTTP/1.0"), Some("Upgrade"), Some("websocket")));
+	}
 }
@@ -0,0 +1,197 @@
+// Content coding negotiation and compression of response bodies, and
+// decompression of request bodies.
+
+use std::{fmt, io};
+#[cfg(any(feature = "flate2", feature = "brotli"))]
+use std::io::{Read, Write};
+
+use crate::client::coding_quality;
+
+// The codings this build can produce, most preferred first.
+pub(crate) const SUPPORTED: &[&str] = &[
+	#[cfg(feature = "brotli")] "br",
+	#[cfg(feature = "flate2")] "gzip",
+	#[cfg(feature = "flate2")] "deflate",
+];
+
+// Outcome of the negotiation.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Coding {
+	Identity,
+	Compressed(&'static str),
+}
+
+// Pick the coding for a response from the client's Accept-Encoding header:
+// the supported coding with the highest q value, ties going to the one listed
+// first in supported. The body is sent uncompressed if the client prefers
+// that or accepts none of the codings. Returns None if the client refuses
+// the uncompressed body as well ("identity;q=0"), i.e. nothing is acceptable.
+pub(crate) fn negotiate(accept_encoding: Option<&str>, supported: &[&'static str]) -> Option<Coding> {
+	// Without the header, any coding would do; stay on the safe side.
+	let accept_encoding = match accept_encoding {
+		Some(v) => v,
+		None => return Some(Coding::Identity)
+	};
+
+	let mut best: Option<(&'static str, f32)> = None;
+	for coding in supported {
+		match coding_quality(accept_encoding, coding) {
+			Some(q) if q > 0.0 && best.is_none_or(|(_, best_q)| q > best_q) => best = Some((coding, q)),
+			_ => {}
+		}
+	}
+
+	// Uncompressed is always acceptable unless it is refused explicitly.
+	let identity = coding_quality(accept_encoding, "identity").unwrap_or(1.0);
+
+	match best {
+		Some((coding, q)) if q >= identity => Some(Coding::Compressed(coding)),
+		_ if identity > 0.0 => Some(Coding::Identity),
+		Some((coding, _)) => Some(Coding::Compressed(coding)),
+		None => None
+	}
+}
+
+// Compress data with one of the SUPPORTED codings.
+#[cfg_attr(not(any(feature = "flate2", feature = "brotli")), allow(unused_variables))]
+pub(crate) fn compress(coding: &str, data: &[u8]) -> io::Result<Vec<u8>> {
+	match coding {
+		#[cfg(feature = "flate2")]
+		"gzip" => {
+			let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+			encoder.write_all(data)?;
+			encoder.finish()
+		},
+		// The "deflate" coding is the zlib format, not a raw deflate stream.
+		#[cfg(feature = "flate2")]
+		"deflate" => {
+			let mut encoder = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+			encoder.write_all(data)?;
+			encoder.finish()
+		},
+		#[cfg(feature = "brotli")]
+		"br" => {
+			let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+			encoder.write_all(data)?;
+			Ok(encoder.into_inner())
+		},
+		_ => Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Unsupported content coding: {}", coding)))
+	}
+}
+
+// The codings this build can decode request bodies from; "x-gzip" is an
+// old alias for "gzip" (RFC 9110, section 8.4.1.3).
+pub(crate) const DECODABLE: &[&str] = &[
+	#[cfg(feature = "brotli")] "br",
+	#[cfg(feature = "flate2")] "gzip",
+	#[cfg(feature = "flate2")] "x-gzip",
+	#[cfg(feature = "flate2")] "deflate",
+];
+
+// Reasons why a request body couldn't be decoded
+#[derive(Debug)]
+#[cfg_attr(not(any(feature = "flate2", feature = "brotli")), allow(dead_code))]
+pub(crate) enum DecompressError {
+	// The decoded body would be larger than allowed.
+	TooLarge,
+	// The data is not valid for the coding.
+	Invalid(io::Error),
+}
+
+impl fmt::Display for DecompressError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			DecompressError::TooLarge => f.write_str("decoded body is too large"),
+			DecompressError::Invalid(e) => write!(f, "invalid encoded body: {}", e)
+		}
+	}
+}
+
+// Decompress data encoded with one of the DECODABLE codings. Stops with
+// TooLarge as soon as the output exceeds max_size, so a small body which
+// expands to gigabytes ("zip bomb") never ends up in memory.
+#[cfg_attr(not(any(feature = "flate2", feature = "brotli")), allow(unused_variables))]
+pub(crate) fn decompress(coding: &str, data: &[u8], max_size: usize) -> Result<Vec<u8>, DecompressError> {
+	let decoder: Box<dyn io::Read> = match coding {
+		#[cfg(feature = "flate2")]
+		"gzip" | "x-gzip" => Box::new(flate2::read::GzDecoder::new(data)),
+		#[cfg(feature = "flate2")]
+		"deflate" => Box::new(flate2::read::ZlibDecoder::new(data)),
+		#[cfg(feature = "brotli")]
+		"br" => Box::new(brotli::Decompressor::new(data, 4096)),
+		_ => return Err(DecompressError::Invalid(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!("Unsupported content coding: {}", coding))))
+	};
+
+	#[cfg(any(feature = "flate2", feature = "brotli"))]
+	{
+		let mut decoded = Vec::new();
+		decoder.take(max_size as u64 + 1).read_to_end(&mut decoded).map_err(DecompressError::Invalid)?;
+		match decoded.len() > max_size {
+			true => Err(DecompressError::TooLarge),
+			false => Ok(decoded)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{negotiate, Coding};
+
+	#[test]
+	fn negotiation() {
+		let supported = &["br", "gzip", "deflate"];
+		let pick = |accept| negotiate(accept, supported);
+
+		assert_eq!(Some(Coding::Identity), pick(None));
+		assert_eq!(Some(Coding::Identity), pick(Some("")));
+		assert_eq!(Some(Coding::Identity), pick(Some("compress")));
+		assert_eq!(Some(Coding::Compressed("gzip")), pick(Some("gzip")));
+		assert_eq!(Some(Coding::Compressed("br")), pick(Some("gzip, deflate, br")));
+		assert_eq!(Some(Coding::Compressed("deflate")), pick(Some("gzip;q=0.5, deflate, br;q=0.1")));
+		assert_eq!(Some(Coding::Compressed("br")), pick(Some("*")));
+		assert_eq!(Some(Coding::Compressed("gzip")), pick(Some("br;q=0, *;q=0.3")));
+
+		// The client prefers the uncompressed body.
+		assert_eq!(Some(Coding::Identity), pick(Some("gzip;q=0.5, identity")));
+
+		// identity;q=0 means the body must be compressed, even if no coding is preferred.
+		assert_eq!(Some(Coding::Compressed("gzip")), pick(Some("gzip, identity;q=0")));
+		assert_eq!(None, pick(Some("compress, identity;q=0")));
+		assert_eq!(None, pick(Some("*;q=0")));
+		assert_eq!(None, negotiate(Some("gzip, identity;q=0"), &[]));
+		assert_eq!(Some(Coding::Identity), negotiate(Some("gzip"), &[]));
+	}
+
+	#[cfg(feature = "brotli")]
+	#[test]
+	fn brotli_round_trip() {
+		use std::io::Read;
+
+		let text = "Compress me. ".repeat(100);
+		let compressed = super::compress("br", text.as_bytes()).unwrap();
+		assert!(compressed.len() < text.len());
+
+		let mut decoded = String::new();
+		brotli::Decompressor::new(&compressed[..], 4096).read_to_string(&mut decoded).unwrap();
+		assert_eq!(text, decoded);
+	}
+
+	#[cfg(feature = "flate2")]
+	#[test]
+	fn decompression() {
+		use super::{compress, decompress, DecompressError};
+
+		let text = "Decompress me. ".repeat(100);
+		for coding in ["gzip", "deflate"].iter() {
+			let compressed = compress(coding, text.as_bytes()).unwrap();
+			assert_eq!(text.as_bytes(), &decompress(coding, &compressed, text.len()).unwrap()[..]);
+			assert!(matches!(decompress(coding, &compressed, text.len() - 1), Err(DecompressError::TooLarge)));
+		}
+		assert!(matches!(decompress("gzip", b"not gzip", 1000), Err(DecompressError::Invalid(_))));
+		assert!(matches!(decompress("compress", b"", 1000), Err(DecompressError::Invalid(_))));
+	}
+}
@@ -0,0 +1,175 @@
+/// A parsed ``Content-Type`` header, returned by ``Client::content_type``.
+///
+/// For ``Multipart/Form-Data; Boundary="abc"``, the MIME type is ``multipart/form-data``
+/// and the ``boundary`` parameter is ``abc``. The type and the parameter names are
+/// case-insensitive, so the type is lowercased and parameters are looked up
+/// regardless of case. Quoted parameter values are unquoted; all values are
+/// otherwise returned as they were sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ContentType {
+	mime: String,
+	params: Vec<(String, String)>,
+}
+
+impl ContentType {
+	/// Parse the value of a ``Content-Type`` header.
+	///
+	/// Returns None if it doesn't start with a type of the form ``type/subtype``.
+	/// Malformed parameters are ignored.
+	///
+	/// ```
+	/// use micro_http_server::ContentType;
+	///
+	/// let content_type = ContentType::parse("text/html; charset=utf-8").unwrap();
+	/// assert_eq!("text/html", content_type.mime());
+	/// assert_eq!(Some("utf-8"), content_type.charset());
+	/// assert_eq!(None, ContentType::parse("text"));
+	/// ```
+	pub fn parse(value: &str) -> Option<ContentType> {
+		let (mime, params) = match value.split_once(';') {
+			Some((mime, params)) => (mime.trim(), params),
+			None => (value.trim(), "")
+		};
+
+		let (main, sub) = mime.split_once('/')?;
+		let is_token = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_graphic() && !b"/()<>@,;:\\\"[]?={}".contains(&b));
+		if !is_token(main) || !is_token(sub) {
+			return None;
+		}
+
+		Some(ContentType {
+			mime: mime.to_ascii_lowercase(),
+			params: header_params(params),
+		})
+	}
+
+	/// Return the lowercased MIME type without parameters, e.g. ``text/html``.
+	pub fn mime(&self) -> &str {
+		&self.mime
+	}
+
+	/// Return the value of the named parameter, or None if it is missing.
+	/// The name is case-insensitive; if it occurs more than once, the first one wins.
+	pub fn param(&self, name: &str) -> Option<&str> {
+		self.params.iter()
+			.find(|(key, _)| key.eq_ignore_ascii_case(name))
+			.map(|(_, value)| value.as_str())
+	}
+
+	/// Return the ``charset`` parameter, e.g. ``utf-8``.
+	pub fn charset(&self) -> Option<&str> {
+		self.param("charset")
+	}
+
+	/// Return the ``boundary`` parameter of a multipart type.
+	pub fn boundary(&self) -> Option<&str> {
+		self.param("boundary")
+	}
+}
+
+// Return the parameters from a list like `name="a"; filename="b.txt"` in order.
+// Quoted values are unquoted; they may contain ';' and escaped characters.
+// Parameters without a value are skipped, and an unterminated quoted value
+// ends the list.
+pub(crate) fn header_params(params: &str) -> Vec<(String, String)> {
+	let mut result = Vec::new();
+	let mut rest = params;
+	loop {
+		rest = rest.trim_start_matches(|c: char| c == ';' || c.is_whitespace());
+		if rest.is_empty() {
+			return result;
+		}
+
+		// A parameter without a value ends at the next ';'.
+		let key_end = rest.find(['=', ';']).unwrap_or(rest.len());
+		let key = rest[..key_end].trim();
+		if !rest[key_end..].starts_with('=') {
+			rest = &rest[key_end..];
+			continue;
+		}
+		rest = rest[key_end + 1..].trim_start();
+
+		let value = match rest.strip_prefix('"') {
+			Some(quoted) => {
+				let mut value = String::new();
+				let mut chars = quoted.char_indices();
+				let end = loop {
+					match chars.next() {
+						Some((_, '\\')) => match chars.next() {
+							Some((_, c)) => value.push(c),
+							None => return result
+						},
+						Some((i, '"')) => break i + 1,
+						Some((_, c)) => value.push(c),
+						None => return result
+					}
+				};
+				rest = &quoted[end..];
+				value
+			},
+			None => {
+				let end = rest.find(';').unwrap_or(rest.len());
+				let value = rest[..end].trim().to_string();
+				rest = &rest[end..];
+				value
+			}
+		};
+
+		result.push((key.to_string(), value));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{header_params, ContentType};
+
+	#[test]
+	fn multipart() {
+		let content_type = ContentType::parse("Multipart/Form-Data; BOUNDARY=\"----a; b\"").unwrap();
+		assert_eq!("multipart/form-data", content_type.mime());
+		assert_eq!(Some("----a; b"), content_type.boundary());
+		assert_eq!(Some("----a; b"), content_type.param("boundary"));
+		assert_eq!(None, content_type.charset());
+
+		let content_type = ContentType::parse("multipart/form-data; boundary=----WebKitFormBoundary7MA4YWxk").unwrap();
+		assert_eq!(Some("----WebKitFormBoundary7MA4YWxk"), content_type.boundary());
+	}
+
+	#[test]
+	fn charset() {
+		let content_type = ContentType::parse("text/html; charset=utf-8").unwrap();
+		assert_eq!("text/html", content_type.mime());
+		assert_eq!(Some("utf-8"), content_type.charset());
+		assert_eq!(None, content_type.boundary());
+
+		let content_type = ContentType::parse(" TEXT/Plain ;Charset = \"UTF-8\" ; format=flowed").unwrap();
+		assert_eq!("text/plain", content_type.mime());
+		assert_eq!(Some("UTF-8"), content_type.charset());
+		assert_eq!(Some("flowed"), content_type.param("FORMAT"));
+	}
+
+	#[test]
+	fn invalid() {
+		assert_eq!(None, ContentType::parse(""));
+		assert_eq!(None, ContentType::parse("text"));
+		assert_eq!(None, ContentType::parse("text/"));
+		assert_eq!(None, ContentType::parse("/html"));
+		assert_eq!(None, ContentType::parse("text/html extra; charset=utf-8"));
+
+		let content_type = ContentType::parse("application/json").unwrap();
+		assert_eq!("application/json", content_type.mime());
+		assert_eq!(None, content_type.param("charset"));
+	}
+
+	#[test]
+	fn params() {
+		let params = " name=\"upload\"; filename=\"my \\\"cat\\\"; photo.jpg\"; flag; size=12";
+		assert_eq!(vec!(
+			("name".to_string(), "upload".to_string()),
+			("filename".to_string(), "my \"cat\"; photo.jpg".to_string()),
+			("size".to_string(), "12".to_string()),
+		), header_params(params));
+		assert_eq!(vec!(("a".to_string(), "1".to_string())), header_params("a=1; name=\"unterminated"));
+		assert!(header_params("").is_empty());
+	}
+}
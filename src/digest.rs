@@ -0,0 +1,112 @@
+// Computation of representation digests (RFC 9530) over response bodies.
+
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+const BASE64_ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+// Standard base64 with padding, as used in digest header values.
+pub(crate) fn base64(data: &[u8]) -> String {
+	let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+
+	for chunk in data.chunks(3) {
+		let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+		let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+
+		for i in 0..4 {
+			match i <= chunk.len() {
+				true => result.push(BASE64_ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+				false => result.push('=')
+			}
+		}
+	}
+
+	result
+}
+
+// Read data to the end and return its SHA-256 digest along with the number of bytes read.
+pub(crate) fn sha256(mut data: impl Read) -> io::Result<(Vec<u8>, u64)> {
+	let mut hasher = Sha256::new();
+	let mut buffer = [0; 4096];
+	let mut len = 0;
+
+	loop {
+		let bytes_read = data.read(&mut buffer)?;
+		if bytes_read == 0 { break; }
+		hasher.update(&buffer[..bytes_read]);
+		len += bytes_read as u64;
+	}
+
+	Ok((hasher.finalize().to_vec(), len))
+}
+
+// Reader which computes the SHA-256 digest of everything read through it.
+pub(crate) struct Sha256Reader<R> {
+	inner: R,
+	hasher: Sha256,
+}
+
+impl<R: Read> Sha256Reader<R> {
+	pub(crate) fn new(inner: R) -> Sha256Reader<R> {
+		Sha256Reader { inner, hasher: Sha256::new() }
+	}
+
+	// Return the digest of the data read so far.
+	pub(crate) fn finalize(self) -> Vec<u8> {
+		self.hasher.finalize().to_vec()
+	}
+}
+
+impl<R: Read> Read for Sha256Reader<R> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let bytes_read = self.inner.read(buf)?;
+		self.hasher.update(&buf[..bytes_read]);
+		Ok(bytes_read)
+	}
+}
+
+// The Repr-Digest field for the given SHA-256 digest, as defined by RFC 9530.
+pub(crate) fn repr_digest(digest: &[u8]) -> String {
+	format!("Repr-Digest: sha-256=:{}:", base64(digest))
+}
+
+// The headers announcing the given SHA-256 digest: Repr-Digest as defined by
+// RFC 9530 and the older Digest header from RFC 3230 for existing clients.
+pub(crate) fn digest_headers(digest: &[u8]) -> Vec<String> {
+	vec!(
+		repr_digest(digest),
+		format!("Digest: SHA-256={}", base64(digest)))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{base64, sha256, Sha256Reader};
+	use std::io::Read;
+
+	#[test]
+	fn encode() {
+		assert_eq!("", base64(b""));
+		assert_eq!("Zg==", base64(b"f"));
+		assert_eq!("Zm8=", base64(b"fo"));
+		assert_eq!("Zm9v", base64(b"foo"));
+		assert_eq!("Zm9vYmFy", base64(b"foobar"));
+		assert_eq!("+/8=", base64(&[0xfb, 0xff]));
+	}
+
+	#[test]
+	fn digest() {
+		let (digest, len) = sha256(&b"hello"[..]).unwrap();
+		assert_eq!(5, len);
+		assert_eq!("LPJNul+wow4m6DsqxbninhsWHlwfp0JecwQzYpOLmCQ=", base64(&digest));
+	}
+
+	#[test]
+	fn reader() {
+		let mut reader = Sha256Reader::new(&b"hello"[..]);
+		let mut data = Vec::new();
+		reader.read_to_end(&mut data).unwrap();
+		assert_eq!(b"hello", &data[..]);
+		assert_eq!(sha256(&b"hello"[..]).unwrap().0, reader.finalize());
+	}
+}
@@ -0,0 +1,182 @@
+// Parsing and formatting of HTTP dates as defined in RFC 7231, section 7.1.1.1.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+
+// Number of days since 1970-01-01 for the given date in the proleptic
+// Gregorian calendar (see http://howardhinnant.github.io/date_algorithms.html).
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = if year >= 0 { year } else { year - 399 } / 400;
+	let year_of_era = year - era * 400;
+	let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+	let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+	era * 146097 + day_of_era - 719468
+}
+
+// Year, month and day of the given number of days since 1970-01-01; the
+// inverse of days_from_civil.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let days = days + 719468;
+	let era = if days >= 0 { days } else { days - 146096 } / 146097;
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month_index = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+	let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+	(year, month, day)
+}
+
+// Parse "08:49:37" into seconds since midnight.
+fn parse_time(s: &str) -> Option<i64> {
+	let parts = s.split(':')
+		.map(|p| p.parse::<i64>().ok().filter(|_| p.len() == 2))
+		.collect::<Option<Vec<i64>>>()?;
+
+	match parts[..] {
+		[h, m, s] if h < 24 && m < 60 && s <= 60 => Some(h * 3600 + m * 60 + s),
+		_ => None
+	}
+}
+
+// Parse a four-digit year; longer ones could overflow the calculations.
+fn parse_year(s: &str) -> Option<i64> {
+	match s.len() == 4 && s.bytes().all(|b| b.is_ascii_digit()) {
+		true => s.parse().ok(),
+		false => None
+	}
+}
+
+fn parse_month(s: &str) -> Option<i64> {
+	MONTHS.iter().position(|m| *m == s).map(|m| m as i64 + 1)
+}
+
+// Number of days in the given month (1-12) of the year.
+fn days_in_month(year: i64, month: i64) -> i64 {
+	match month {
+		2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+		2 => 28,
+		4 | 6 | 9 | 11 => 30,
+		_ => 31
+	}
+}
+
+// Combine the date components into a point in time.
+fn to_system_time(year: i64, month: i64, day: i64, seconds: i64) -> Option<SystemTime> {
+	if day < 1 || day > days_in_month(year, month) {
+		return None;
+	}
+
+	let timestamp = days_from_civil(year, month, day) * 86400 + seconds;
+	match timestamp >= 0 {
+		true => UNIX_EPOCH.checked_add(Duration::from_secs(timestamp as u64)),
+		false => UNIX_EPOCH.checked_sub(Duration::from_secs(timestamp.unsigned_abs()))
+	}
+}
+
+// Parse a date in any of the three formats HTTP allows:
+// "Sun, 06 Nov 1994 08:49:37 GMT" (IMF-fixdate),
+// "Sunday, 06-Nov-94 08:49:37 GMT" (obsolete RFC 850 format) and
+// "Sun Nov  6 08:49:37 1994" (ANSI C's asctime() format).
+pub(crate) fn parse_http_date(s: &str) -> Option<SystemTime> {
+	let parts = s.split_whitespace().collect::<Vec<&str>>();
+
+	match parts[..] {
+		[_, day, month, year, time, "GMT"] => to_system_time(
+			parse_year(year)?,
+			parse_month(month)?,
+			day.parse().ok()?,
+			parse_time(time)?),
+		[_, date, time, "GMT"] => {
+			let date = date.split('-').collect::<Vec<&str>>();
+			match date[..] {
+				[day, month, year] if year.len() == 2 => {
+					// Two-digit years are interpreted as in RFC 7231: 70-99 belong
+					// to the 20th century, everything else to the 21st.
+					let year = year.parse::<i64>().ok()?;
+					let year = if year >= 70 { 1900 + year } else { 2000 + year };
+					to_system_time(year, parse_month(month)?, day.parse().ok()?, parse_time(time)?)
+				},
+				_ => None
+			}
+		},
+		[_, month, day, time, year] => to_system_time(
+			parse_year(year)?,
+			parse_month(month)?,
+			day.parse().ok()?,
+			parse_time(time)?),
+		_ => None
+	}
+}
+
+// Format a point in time as IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT".
+// Times before 1970 are formatted as 1970-01-01.
+pub(crate) fn format_http_date(time: SystemTime) -> String {
+	let timestamp = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0) as i64;
+	let (days, seconds) = (timestamp / 86400, timestamp % 86400);
+	let (year, month, day) = civil_from_days(days);
+
+	format!("{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+		WEEKDAYS[(days % 7) as usize],
+		day,
+		MONTHS[(month - 1) as usize],
+		year,
+		seconds / 3600,
+		seconds % 3600 / 60,
+		seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{format_http_date, parse_http_date};
+	use std::time::{Duration, UNIX_EPOCH};
+
+	#[test]
+	fn formats() {
+		let expected = Some(UNIX_EPOCH + Duration::from_secs(784111777));
+		assert_eq!(expected, parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"));
+		assert_eq!(expected, parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"));
+		assert_eq!(expected, parse_http_date("Sun Nov  6 08:49:37 1994"));
+
+		assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(951782400)), parse_http_date("Tue, 29 Feb 2000 00:00:00 GMT"));
+		assert_eq!(Some(UNIX_EPOCH), parse_http_date("Thu, 01 Jan 1970 00:00:00 GMT"));
+		assert_eq!(Some(UNIX_EPOCH + Duration::from_secs(1709164800)), parse_http_date("Thu, 29 Feb 2024 00:00:00 GMT"));
+	}
+
+	#[test]
+	fn invalid() {
+		assert_eq!(None, parse_http_date(""));
+		assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 08:49:37 CET"));
+		assert_eq!(None, parse_http_date("Sun, 06 Foo 1994 08:49:37 GMT"));
+		assert_eq!(None, parse_http_date("Sun, 32 Nov 1994 08:49:37 GMT"));
+		assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 24:00:00 GMT"));
+		assert_eq!(None, parse_http_date("Sun, 06 Nov 1994 8:49:37 GMT"));
+		assert_eq!(None, parse_http_date("Sun, 06 Nov +994 08:49:37 GMT"));
+		assert_eq!(None, parse_http_date("Sat, 31 Feb 2024 00:00:00 GMT"));
+		assert_eq!(None, parse_http_date("Wed, 29 Feb 2023 00:00:00 GMT"));
+		assert_eq!(None, parse_http_date("Tue, 29 Feb 1900 00:00:00 GMT"));
+		assert_eq!(None, parse_http_date("Thu, 31 Apr 2024 00:00:00 GMT"));
+	}
+
+	#[test]
+	fn huge_year() {
+		assert_eq!(None, parse_http_date("Sun Nov  6 08:49:37 999999999999999"));
+		assert_eq!(None, parse_http_date("Sun, 06 Nov 999999999999999 08:49:37 GMT"));
+		assert_eq!(None, parse_http_date("Sun Nov  6 08:49:37 -999999999999999"));
+	}
+
+	#[test]
+	fn format() {
+		assert_eq!("Sun, 06 Nov 1994 08:49:37 GMT", format_http_date(UNIX_EPOCH + Duration::from_secs(784111777)));
+		assert_eq!("Thu, 01 Jan 1970 00:00:00 GMT", format_http_date(UNIX_EPOCH));
+		assert_eq!("Tue, 29 Feb 2000 00:00:00 GMT", format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)));
+		assert_eq!("Fri, 31 Dec 2038 23:59:59 GMT", format_http_date(UNIX_EPOCH + Duration::from_secs(2177452799)));
+
+		let time = UNIX_EPOCH + Duration::from_secs(1234567890);
+		assert_eq!(Some(time), parse_http_date(&format_http_date(time)));
+	}
+}
@@ -10,8 +10,8 @@
 //! µHTTP does not support any kind of load balancing or threading - you
 //! would have to implement this yourself if you want it.
 //!
-//! At the moment, µHTTP only supports GET requests; if you need PUT/POST/ etc.,
-//! feel free to create an issue or a pull request!
+//! Requests of any method are accepted; use ``Client::method`` to tell them apart
+//! and ``Client::body`` to access the data sent along with e.g. a POST request.
 //!
 //! # Example
 //!
@@ -30,7 +30,7 @@
 //! connection.write("GET /cat.txt\r\n\r\n".as_bytes());
 //!
 //! {
-//! 	// Server side: Get client and send a response.
+//!     // Server side: Get client and send a response.
 //!     let mut client = server.next_client().unwrap().unwrap();
 //!     println!("[Server] Client requested: {}", client.request().as_ref().unwrap());
 //!     let bytes_written = client.respond_ok("Cats are nice.\n".as_bytes()).unwrap();
@@ -46,10 +46,50 @@
 #[macro_use] extern crate log;
 
 mod microhttp;
+mod output;
 mod client;
+mod body;
+mod body_decision;
+mod buffered;
+mod chunked;
+mod compression;
+mod content_type;
+mod httpdate;
+mod multipart;
+mod protocol_version;
+mod ratelimit;
+mod range;
+mod response_parts;
+mod request_head;
+mod request_url;
+mod retry_after;
+mod router;
+mod static_files;
+mod status;
+mod urlencoded;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "tokio")]
+mod asynchronous;
+#[cfg(feature = "sha2")]
+mod digest;
 
 pub use microhttp::MicroHTTP;
 pub use client::Client;
+pub use body::BodyReader;
+pub use body_decision::BodyDecision;
+pub use buffered::BufferedResponse;
+pub use chunked::ChunkedResponse;
+pub use content_type::ContentType;
+pub use multipart::UploadedPart;
+pub use protocol_version::ProtocolVersion;
+pub use request_head::{ParseWarning, RequestView};
+pub use request_url::RequestUrl;
+pub use retry_after::RetryAfter;
+pub use response_parts::ResponseParts;
+pub use router::{Router, TrailingSlash};
+#[cfg(feature = "tokio")]
+pub use asynchronous::{AsyncMicroHTTP, AsyncClient};
 
 #[cfg(not(target_os="windows"))]
 fn os_windows() -> bool { false }
@@ -1,11 +1,204 @@
-use std::{io, net::{TcpListener, ToSocketAddrs}};
+use std::{
+	collections::HashMap,
+	fmt,
+	io,
+	io::Write,
+	net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+	sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}},
+	thread,
+	time::{Duration, Instant}
+};
 
-use client::Client;
+use crate::body_decision::{BodyDecision, PreBodyHandler};
+use crate::client::Client;
+use crate::protocol_version::ProtocolVersion;
+use crate::ratelimit::RateLimiter;
+use crate::response_parts::{ResponseMiddleware, ResponseParts};
 
 /// This is the main struct of the µHTTP server.
 pub struct MicroHTTP {
-	// Internal listener which is used for the server part
-	listener: TcpListener,
+	// Internal listeners which are used for the server part
+	listeners: Vec<TcpListener>,
+
+	// Whether the server is in nonblocking mode, see set_nonblocking. With several
+	// listeners, they are nonblocking in any case.
+	nonblocking: bool,
+
+	// Index of the listener which is tried first by the next accept
+	next_listener: AtomicUsize,
+
+	// How long run waits for a client before checking again
+	poll_interval: Duration,
+
+	// Limit for the number of connections per IP address, if any
+	rate_limit: Mutex<Option<RateLimiter>>,
+
+	// Settings which are shared with every incoming client
+	settings: Arc<Settings>,
+}
+
+// Settings which control how incoming requests are handled.
+#[derive(Clone, Debug)]
+pub(crate) struct Settings {
+	// Maximum accepted size of a request body in bytes
+	pub(crate) max_request_size: Option<usize>,
+
+	// Maximum accepted length of the request target in bytes
+	pub(crate) max_uri_length: usize,
+
+	// Maximum accepted number of request headers
+	pub(crate) max_header_count: usize,
+
+	// Size of the buffers used for reading requests and writing responses
+	pub(crate) buffer_size: usize,
+
+	// Upper limit for the memory reserved up front for a request body
+	pub(crate) read_buffer_hint: usize,
+
+	// SO_LINGER timeout applied to accepted connections, if any
+	pub(crate) linger: Option<Duration>,
+
+	// Time allowed for receiving the whole request, if limited
+	pub(crate) request_timeout: Option<Duration>,
+
+	// Whether the sending side is shut down as soon as a response is complete
+	pub(crate) shutdown_after_response: bool,
+
+	// Protocol version of responses to HTTP/1.1 clients
+	pub(crate) protocol_version: ProtocolVersion,
+
+	// Whether request bodies are left on the connection for Client::body_reader
+	pub(crate) stream_bodies: bool,
+
+	// Whether connections start with a trusted PROXY protocol v1 header
+	pub(crate) proxy_protocol: bool,
+
+	// Whether the X-Forwarded-Proto header is trusted to tell the scheme
+	pub(crate) trust_forwarded_proto: bool,
+
+	// Methods which are accepted; None accepts all of them
+	pub(crate) allowed_methods: Option<Vec<String>>,
+
+	// Path which is answered with "200 OK" right away, for load balancers
+	pub(crate) health_check_path: Option<String>,
+
+	// Whether POST requests may ask for another method with X-HTTP-Method-Override
+	pub(crate) method_override: bool,
+
+	// Whether data after the end of the request is rejected instead of ignored
+	pub(crate) strict_framing: bool,
+
+	// Whether a response body reader with more data than announced is an error
+	pub(crate) strict_content_length: bool,
+
+	// Decides whether the body of a request is read at all
+	pub(crate) pre_body_handler: Option<PreBodyHandler>,
+
+	// Functions which may change the status and headers of every response, in order
+	pub(crate) response_middleware: Vec<ResponseMiddleware>,
+
+	// Maximum number of headers a response may have
+	pub(crate) max_response_headers: usize,
+
+	// Maximum total size of the headers of a response in bytes
+	pub(crate) max_response_header_size: usize,
+
+	// Custom bodies for error responses, keyed by status code
+	pub(crate) error_pages: HashMap<u16, ErrorPage>,
+
+	// Called with the client address whenever a connection is closed
+	pub(crate) on_connection_close: Option<ConnectionCloseHook>,
+}
+
+impl Default for Settings {
+	fn default() -> Self {
+		Settings {
+			max_request_size: None,
+			max_uri_length: 8 * 1024,
+			max_header_count: 100,
+			buffer_size: 4096,
+			read_buffer_hint: 1024 * 1024,
+			linger: None,
+			request_timeout: None,
+			shutdown_after_response: false,
+			protocol_version: ProtocolVersion::Http10,
+			stream_bodies: false,
+			proxy_protocol: false,
+			trust_forwarded_proto: false,
+			allowed_methods: None,
+			health_check_path: None,
+			method_override: false,
+			strict_framing: false,
+			strict_content_length: false,
+			pre_body_handler: None,
+			response_middleware: Vec::new(),
+			max_response_headers: 100,
+			max_response_header_size: 64 * 1024,
+			error_pages: HashMap::new(),
+			on_connection_close: None,
+		}
+	}
+}
+
+impl Settings {
+	// Tell the hook set with set_on_connection_close, if any, that the
+	// connection from addr is about to be closed.
+	pub(crate) fn connection_closed(&self, addr: SocketAddr) {
+		if let Some(ref hook) = self.on_connection_close {
+			(hook.0)(addr);
+		}
+	}
+}
+
+// The hook in the settings, which need to implement Debug.
+#[derive(Clone)]
+pub(crate) struct ConnectionCloseHook(Arc<dyn Fn(SocketAddr) + Send + Sync>);
+
+impl fmt::Debug for ConnectionCloseHook {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ConnectionCloseHook")
+	}
+}
+
+// Body and content type of a custom error response
+#[derive(Clone, Debug)]
+pub(crate) struct ErrorPage {
+	pub(crate) body: Vec<u8>,
+	pub(crate) content_type: String,
+}
+
+// Wait until one of the listeners has an incoming connection or the timeout
+// expired; None waits forever. Returns the indices of all ready listeners.
+#[cfg(unix)]
+fn wait_readable(listeners: &[TcpListener], timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+	use std::os::unix::io::AsRawFd;
+
+	let mut fds = listeners.iter()
+		.map(|l| libc::pollfd { fd: l.as_raw_fd(), events: libc::POLLIN, revents: 0 })
+		.collect::<Vec<_>>();
+	let millis = match timeout {
+		Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+		None => -1
+	};
+
+	match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, millis) } {
+		-1 => match io::Error::last_os_error() {
+			// Interrupted by a signal; treat it like a timeout.
+			ref err if err.kind() == io::ErrorKind::Interrupted => Ok(Vec::new()),
+			err => Err(err)
+		},
+		_ => Ok(fds.iter()
+			.enumerate()
+			.filter(|(_, fd)| fd.revents != 0)
+			.map(|(idx, _)| idx)
+			.collect())
+	}
+}
+
+// Without poll(), just fall back to trying every listener.
+#[cfg(not(unix))]
+fn wait_readable(listeners: &[TcpListener], _timeout: Option<Duration>) -> io::Result<Vec<usize>> {
+	Ok((0..listeners.len()).collect())
 }
 
 impl MicroHTTP {
@@ -19,7 +212,7 @@ impl MicroHTTP {
 	/// ```
 	/// use micro_http_server::MicroHTTP;
 	///
-	/// let interface: &str = "127.0.0.1:3000";
+	/// let interface: &str = "127.0.0.1:3001";
 	/// let server = MicroHTTP::new(interface)
 	///     .expect("Could not create server, maybe the port is already being used?");
 	/// ```
@@ -28,14 +221,538 @@ impl MicroHTTP {
 		let listener = TcpListener::bind(interface)?;
 
 		// Return created instance
-		Ok(MicroHTTP {
-			listener : listener
-		})
+		Ok(MicroHTTP::with_listeners(vec!(listener)))
+	}
+
+	/// Create a new MicroHTTP server on a free port of the given host, chosen by the
+	/// operating system, and return it along with the address it listens on.
+	///
+	/// This binds to port 0 and asks for the port with ``local_addr`` in one go, e.g.
+	/// for tests or for services which register their port with a discovery service.
+	/// ``host`` is an IP address like ``127.0.0.1`` or ``::1``, or a host name.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use std::{io::Write, net::TcpStream};
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let (server, addr) = MicroHTTP::new_ephemeral("127.0.0.1").expect("Could not create server.");
+	/// println!("Listening on port {}", addr.port());
+	///
+	/// let mut connection = TcpStream::connect(addr).unwrap();
+	/// connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+	/// let client = server.next_client().unwrap().unwrap();
+	/// ```
+	pub fn new_ephemeral(host: &str) -> Result<(MicroHTTP, SocketAddr), io::Error> {
+		let server = MicroHTTP::new((host, 0))?;
+		let addr = server.local_addr()?;
+		Ok((server, addr))
+	}
+
+	/// Create a new MicroHTTP server which listens on all of the given interfaces,
+	/// e.g. on both ``127.0.0.1:3000`` and ``[::1]:3000``.
+	///
+	/// ``next_client`` then returns clients from whichever interface has one,
+	/// taking turns between the interfaces so none of them is starved.
+	///
+	/// **Note**: On platforms without ``poll()`` (e.g. Windows), a blocking
+	/// ``next_client`` can't wait on several interfaces at once; it checks them in
+	/// turn instead, sleeping up to the poll interval (see ``set_poll_interval``)
+	/// in between.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let server = MicroHTTP::new_multi(&["127.0.0.1:3006", "127.0.0.1:3007"])
+	///     .expect("Could not create server.");
+	/// ```
+	pub fn new_multi(interfaces: &[impl ToSocketAddrs]) -> Result<MicroHTTP,io::Error> {
+		let listeners = interfaces.iter()
+			.map(TcpListener::bind)
+			.collect::<Result<Vec<_>,_>>()?;
+
+		if listeners.is_empty() {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, "No interface given"));
+		}
+		// A blocking accept could hang on one listener while others have clients,
+		// e.g. if its connection was reset after poll() reported it.
+		if listeners.len() > 1 {
+			for listener in &listeners {
+				listener.set_nonblocking(true)?;
+			}
+		}
+		Ok(MicroHTTP::with_listeners(listeners))
+	}
+
+	fn with_listeners(listeners: Vec<TcpListener>) -> MicroHTTP {
+		MicroHTTP {
+			listeners,
+			nonblocking: false,
+			next_listener: AtomicUsize::new(0),
+			poll_interval: Duration::from_millis(100),
+			rate_limit: Mutex::new(None),
+			settings: Arc::new(Settings::default())
+		}
+	}
+
+	/// Return the address the server is listening on.
+	///
+	/// This is useful when binding to port 0, which lets the operating system
+	/// pick a free port - e.g. for integration tests which should not depend
+	/// on a fixed port:
+	///
+	/// ```
+	/// use std::{io::Write, net::TcpStream};
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	/// let addr = server.local_addr().unwrap();
+	///
+	/// let mut connection = TcpStream::connect(addr).unwrap();
+	/// connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+	/// let client = server.next_client().unwrap().unwrap();
+	/// ```
+	///
+	/// For a server listening on several interfaces, this returns the first address;
+	/// see ``local_addrs``.
+	pub fn local_addr(&self) -> Result<SocketAddr, io::Error> {
+		self.listeners[0].local_addr()
+	}
+
+	/// Return the addresses of all interfaces the server is listening on,
+	/// in the order they were passed to ``new_multi``.
+	pub fn local_addrs(&self) -> Result<Vec<SocketAddr>, io::Error> {
+		self.listeners.iter().map(TcpListener::local_addr).collect()
 	}
 
 	/// Set whether or not the underlying TcpListener awaits connections in nonblocking mode
 	pub fn set_nonblocking(&mut self, state: bool) -> Result<(), io::Error> {
-		self.listener.set_nonblocking(state)
+		// Several listeners stay nonblocking anyway, see new_multi.
+		let multi = self.listeners.len() > 1;
+		for listener in &self.listeners {
+			listener.set_nonblocking(state || multi)?;
+		}
+		self.nonblocking = state;
+		Ok(())
+	}
+
+	/// Restrict the server to the given network device, e.g. ``eth1``, with ``SO_BINDTODEVICE``.
+	///
+	/// Only connections arriving on this device are accepted, and responses leave
+	/// through it, no matter which IP addresses the server is bound to. This applies
+	/// to all interfaces passed to ``new_multi``. Setting this typically requires the
+	/// ``CAP_NET_RAW`` capability; otherwise, a ``PermissionDenied`` error is returned.
+	///
+	/// Only available on Linux.
+	#[cfg(target_os = "linux")]
+	pub fn set_bind_device(&mut self, device: &str) -> Result<(), io::Error> {
+		use std::os::unix::io::AsRawFd;
+
+		for listener in &self.listeners {
+			let result = unsafe {
+				libc::setsockopt(
+					listener.as_raw_fd(),
+					libc::SOL_SOCKET,
+					libc::SO_BINDTODEVICE,
+					device.as_ptr() as *const libc::c_void,
+					device.len() as libc::socklen_t)
+			};
+			if result != 0 {
+				return Err(io::Error::last_os_error());
+			}
+		}
+		Ok(())
+	}
+
+	/// Set how long ``run`` waits for a client before it checks again; the default is 100ms.
+	///
+	/// How much this matters depends on how ``run`` waits for clients:
+	///
+	/// * Where ``poll()`` is available (e.g. Linux, macOS), ``run`` wakes up as soon as
+	///   a client connects, so there is no added latency, and no CPU time is spent
+	///   while idle. The interval only limits how long a single wait lasts; the value
+	///   hardly matters.
+	/// * Otherwise (e.g. on Windows), a blocking server (the default) simply blocks in
+	///   ``accept`` until a client connects, with the same result.
+	/// * A nonblocking server without ``poll()`` has to sleep whenever no client is
+	///   waiting. These sleeps are adaptive: they start at 1ms after a client was
+	///   served and double while the server stays idle, up to this interval. Under load,
+	///   clients hardly wait; after an idle period, the first client may wait up to
+	///   the interval. A shorter interval lowers this latency at the cost of more
+	///   wakeups, i.e. CPU time, while idle.
+	///
+	/// A loop around ``next_client`` with a fixed sleep always adds up to the sleep
+	/// time of latency; use ``run`` or ``next_client_timeout`` instead.
+	pub fn set_poll_interval(&mut self, interval: Duration) {
+		self.poll_interval = interval;
+	}
+
+	/// Limit each IP address to ``max`` connections within ``window``; by default,
+	/// there is no limit.
+	///
+	/// Further connections from an address which reached the limit are answered
+	/// with ``429 Too Many Requests`` right away, without reading the request, and
+	/// are not returned by ``next_client``. Rejected connections don't count
+	/// towards the limit, so the client is let in again once its older connections
+	/// are more than ``window`` ago.
+	///
+	/// **Note**: The limit applies to the address of the peer. Behind a load balancer,
+	/// this is the load balancer itself, even with ``set_proxy_protocol`` enabled.
+	pub fn set_per_ip_rate_limit(&mut self, max: usize, window: Duration) {
+		*self.rate_limit.get_mut().unwrap_or_else(|e| e.into_inner()) = Some(RateLimiter::new(max, window));
+	}
+
+	/// Set the maximum size of a request body in bytes; ``None`` means unlimited (the default).
+	///
+	/// Requests with a larger ``Content-Length`` are answered with ``413 Payload Too Large``
+	/// without reading the body; if the client announced the upload with
+	/// ``Expect: 100-continue``, the body is never even transmitted.
+	/// Such requests are not returned by ``next_client``.
+	pub fn set_max_request_size(&mut self, size: Option<usize>) {
+		Arc::make_mut(&mut self.settings).max_request_size = size;
+	}
+
+	/// Set the maximum length of the request target (the URI in the request line)
+	/// in bytes; the default is 8 KiB.
+	///
+	/// Requests with a longer target are answered with ``414 URI Too Long``
+	/// and not returned by ``next_client``.
+	pub fn set_max_uri_length(&mut self, length: usize) {
+		Arc::make_mut(&mut self.settings).max_uri_length = length;
+	}
+
+	/// Set the maximum number of headers a request may have; the default is 100.
+	///
+	/// Requests with more headers are answered with ``431 Request Header Fields Too Large``
+	/// and not returned by ``next_client``. Headers beyond the limit aren't even parsed.
+	///
+	/// Independent of these limits, the head of a request (the request line and the
+	/// headers) must end within 64 KiB. Otherwise, the request is answered with
+	/// ``414 URI Too Long`` if the request line doesn't end in time, or with
+	/// ``431 Request Header Fields Too Large`` if the headers don't.
+	pub fn set_max_header_count(&mut self, count: usize) {
+		Arc::make_mut(&mut self.settings).max_header_count = count;
+	}
+
+	/// Set the size of the buffers used for reading requests and writing
+	/// response bodies in bytes; the default is 4 KiB. Values below 1 byte are
+	/// treated as 1 byte.
+	///
+	/// Each client allocates a buffer of this size while its request is read and
+	/// while a response body is sent. Larger buffers mean fewer system calls and
+	/// better throughput for big uploads and files, at the cost of more memory per
+	/// client. The ``buffer_size`` example measures the effect.
+	pub fn set_buffer_size(&mut self, size: usize) {
+		Arc::make_mut(&mut self.settings).buffer_size = size.max(1);
+	}
+
+	/// Set how many bytes may be reserved for a request body before reading it;
+	/// the default is 1 MiB.
+	///
+	/// The memory for a body is reserved at once based on its ``Content-Length``,
+	/// instead of growing it repeatedly while the body arrives. Since a client
+	/// could announce a huge body without sending it, at most this many bytes are
+	/// reserved up front; larger bodies grow as needed beyond that. Raise this if
+	/// you expect many large uploads, or set it to 0 to disable the reservation.
+	/// The ``read_buffer_hint`` example counts the reallocations saved.
+	///
+	/// This has no effect on bodies read with ``Client::body_reader``.
+	pub fn set_read_buffer_hint(&mut self, size: usize) {
+		Arc::make_mut(&mut self.settings).read_buffer_hint = size;
+	}
+
+	/// Shut down the sending side of the connection as soon as a response is
+	/// complete; this is off by default.
+	///
+	/// Since every response is the last one on its connection, the client only
+	/// sees the end of it once the ``Client`` is dropped. Some clients read until
+	/// the connection is closed even if the body has a ``Content-Length``; if the
+	/// ``Client`` is kept around after responding, e.g. for some more work, they
+	/// would wait for it. With this option, they see the end right away, as if
+	/// ``Client::shutdown_write`` was called after every response.
+	///
+	/// Nothing can be sent on the connection afterwards; the request body can
+	/// still be read.
+	pub fn set_shutdown_after_response(&mut self, enabled: bool) {
+		Arc::make_mut(&mut self.settings).shutdown_after_response = enabled;
+	}
+
+	/// Select the protocol version of responses; the default is ``ProtocolVersion::Http10``,
+	/// i.e. plain ``HTTP/1.0`` responses. With ``ProtocolVersion::Http11``, HTTP/1.1
+	/// clients get ``HTTP/1.1`` responses. See ``ProtocolVersion`` for the details.
+	pub fn set_protocol_version(&mut self, version: ProtocolVersion) {
+		Arc::make_mut(&mut self.settings).protocol_version = version;
+	}
+
+	/// Set the linger timeout (``SO_LINGER``) of accepted connections; by default,
+	/// the operating system's default behavior is kept.
+	///
+	/// With a timeout, closing a connection blocks until the response has been
+	/// delivered to the client or the timeout expired; remaining data is then discarded.
+	/// This makes sure a response was actually delivered before the ``Client`` is
+	/// gone, at the cost of blocking on slow clients. A zero timeout resets the
+	/// connection on close instead, discarding unsent data right away.
+	///
+	/// **Note**: Only supported on Unix; elsewhere, this setting is ignored.
+	pub fn set_linger(&mut self, linger: Option<Duration>) {
+		Arc::make_mut(&mut self.settings).linger = linger;
+	}
+
+	/// Set the time a client has to send its whole request, counted from accepting
+	/// the connection; by default, there is no limit.
+	///
+	/// Unlike a timeout for single reads, this also stops clients which keep the
+	/// connection busy by sending a few bytes now and then. If the head and, unless
+	/// bodies are streamed (see ``set_stream_bodies``), the body haven't arrived in
+	/// time, the client is answered with ``408 Request Timeout`` and the connection
+	/// is closed, so ``next_client`` returns None for it.
+	pub fn set_request_timeout(&mut self, timeout: Option<Duration>) {
+		Arc::make_mut(&mut self.settings).request_timeout = timeout;
+	}
+
+	/// Set whether request bodies are streamed instead of being read up front (default: false).
+	///
+	/// By default, ``next_client`` reads the whole body into memory, so it is
+	/// available via ``Client::body``. In streaming mode, only the headers are read
+	/// and the body is left to be read with ``Client::body_reader``, e.g. to write
+	/// a large upload straight to a file.
+	pub fn set_stream_bodies(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).stream_bodies = state;
+	}
+
+	/// Set whether incoming connections start with a PROXY protocol v1 header (default: false).
+	///
+	/// Enable this only if the server sits behind a load balancer which sends
+	/// such a header, e.g. ``PROXY TCP4 192.168.0.1 192.168.0.11 56324 443``.
+	/// ``Client::addr`` then returns the real client address taken from the header.
+	/// Connections without a valid header are closed and not returned by ``next_client``.
+	///
+	/// **Note**: Never enable this for servers which clients can reach directly,
+	/// since anyone could then fake their address.
+	pub fn set_proxy_protocol(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).proxy_protocol = state;
+	}
+
+	/// Set whether ``Client::scheme`` trusts the ``X-Forwarded-Proto`` header (default: false).
+	///
+	/// Enable this only if the server sits behind a reverse proxy which terminates
+	/// TLS and always sets this header, since anyone else could fake it.
+	pub fn set_trust_forwarded_proto(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).trust_forwarded_proto = state;
+	}
+
+	/// Answer ``GET`` and ``HEAD`` requests for the given path, e.g. ``/healthz``,
+	/// with ``200 OK`` and an empty body, for health checks of load balancers;
+	/// ``None`` turns this off (the default).
+	///
+	/// These requests are answered as soon as their head has been read, before checks
+	/// like ``set_allowed_methods``, and are not returned by ``next_client``, so the
+	/// health check stays cheap and works without any code in the handler. The path must match
+	/// exactly; a query string is ignored.
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	/// server.set_health_check_path(Some("/healthz"));
+	/// ```
+	pub fn set_health_check_path(&mut self, path: Option<&str>) {
+		Arc::make_mut(&mut self.settings).health_check_path = path.map(String::from);
+	}
+
+	/// Set the request methods the server accepts, e.g. ``Some(&["GET", "HEAD"])``
+	/// for a read-only server; ``None`` accepts all methods (the default).
+	///
+	/// Requests with any other method are answered with ``405 Method Not Allowed``
+	/// and an ``Allow`` header listing the accepted methods, and are not returned by
+	/// ``next_client``. Methods are case-sensitive, as in HTTP.
+	pub fn set_allowed_methods(&mut self, methods: Option<&[&str]>) {
+		Arc::make_mut(&mut self.settings).allowed_methods = methods
+			.map(|methods| methods.iter().map(|m| m.to_string()).collect());
+	}
+
+	/// Set whether ``POST`` requests may ask for another method with the
+	/// ``X-HTTP-Method-Override`` header (default: false).
+	///
+	/// This helps HTML forms, which can only send ``GET`` and ``POST``. If enabled,
+	/// ``Client::method`` (and thus a ``Router``) sees the method from the header,
+	/// but only ``PUT``, ``PATCH`` and ``DELETE`` are accepted; other values are ignored.
+	///
+	/// **Note**: Keep this disabled unless you need it: requests which a proxy
+	/// or firewall treats as ``POST`` would be handled as e.g. ``DELETE``.
+	pub fn set_method_override(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).method_override = state;
+	}
+
+	/// Set whether data after the end of a request is a protocol error (default: false).
+	///
+	/// The end of a request is the end of its body as announced by ``Content-Length``,
+	/// or the end of the header section if there is no ``Content-Length``, e.g. for
+	/// a ``GET`` request. By default, anything the client sends beyond that
+	/// (e.g. pipelined requests or a body without ``Content-Length``) is accepted with
+	/// a warning. In strict mode, such requests are answered with ``400 Bad Request``
+	/// and not returned by ``next_client``.
+	pub fn set_strict_framing(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).strict_framing = state;
+	}
+
+	/// Set a handler which decides whether to accept a request body before it is read.
+	///
+	/// The handler is called for every request which announces a body with
+	/// ``Content-Length``, right after the headers have been read and checked
+	/// against ``set_max_request_size``. It gets the ``Client`` to look at the
+	/// method, target and headers (the body is still empty) and returns a
+	/// ``BodyDecision``:
+	///
+	/// * ``Accept``: the body is read as usual (or left for ``Client::body_reader``
+	///   in streaming mode) and the client is returned by ``next_client``.
+	/// * ``Reject(status)``: the request is answered with the given status and not
+	///   returned by ``next_client``. Custom error pages apply.
+	/// * ``Redirect(location)``: the request is answered with ``307 Temporary Redirect``
+	///   and not returned by ``next_client``.
+	///
+	/// This is where to check authentication, content type or size policies before
+	/// accepting a potentially huge upload.
+	///
+	/// # Ordering with ``100 Continue``
+	///
+	/// Clients which send ``Expect: 100-continue`` wait for ``100 Continue`` before
+	/// sending the body. The handler runs before it is sent: a rejected or redirected
+	/// client gets the final response instead and never transmits its body. An
+	/// accepted client gets ``100 Continue`` right before its body is read, i.e. in
+	/// streaming mode only once ``Client::body_reader`` is called.
+	///
+	/// Clients which don't wait may already be sending the body when they are
+	/// rejected; since the connection is closed after the response, they may then
+	/// see a reset connection instead of the response.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use micro_http_server::{BodyDecision, MicroHTTP};
+	///
+	/// let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	/// server.set_pre_body_handler(|client| match client.header("Authorization") {
+	///     Some("Bearer secret") => BodyDecision::Accept,
+	///     _ => BodyDecision::Reject(401)
+	/// });
+	/// ```
+	pub fn set_pre_body_handler(&mut self, handler: impl Fn(&Client) -> BodyDecision + Send + Sync + 'static) {
+		Arc::make_mut(&mut self.settings).pre_body_handler = Some(PreBodyHandler(Arc::new(handler)));
+	}
+
+	/// Set a function which is called whenever a connection is closed, with the
+	/// address of the client, e.g. to clean up state kept per connection.
+	///
+	/// It is called exactly once for every accepted connection, right before it
+	/// is closed: when the ``Client`` is dropped (including after ``Client::finish``),
+	/// no matter whether a response was sent, the request couldn't be read or the
+	/// client disconnected early. It is also called for connections the server
+	/// turns down on its own, e.g. because of ``set_per_ip_rate_limit``, an invalid
+	/// PROXY header or a rejected body (see ``set_pre_body_handler``). The address is
+	/// the one ``Client::addr`` reports, i.e. the real client behind a proxy if
+	/// ``set_proxy_protocol`` is enabled.
+	///
+	/// Every connection carries a single request, so this is called after the
+	/// handler is done with the client and after any access log entry; there is no
+	/// keep-alive loop which could serve further requests on the same connection.
+	/// The function runs on the thread dropping the ``Client``.
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	/// server.set_on_connection_close(|addr| println!("{} disconnected", addr));
+	/// ```
+	pub fn set_on_connection_close(&mut self, hook: impl Fn(SocketAddr) + Send + Sync + 'static) {
+		Arc::make_mut(&mut self.settings).on_connection_close = Some(ConnectionCloseHook(Arc::new(hook)));
+	}
+
+	/// Add a function which may change the status and headers of every response
+	/// before it is sent, e.g. to add security headers application-wide.
+	///
+	/// The function gets the ``Client`` the response is for and the ``ResponseParts``
+	/// of the response. It is called by all respond methods, including the responses
+	/// the server sends on its own (e.g. ``413 Payload Too Large``), but not for
+	/// ``100 Continue`` and the ``429 Too Many Requests`` of ``set_per_ip_rate_limit``,
+	/// which is sent before the request is read. Several functions are called in the
+	/// order they were added.
+	///
+	/// The body can't be changed. The headers are checked for CR, LF and NUL and against
+	/// ``set_max_response_headers`` after all functions have run.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let mut server = MicroHTTP::new("127.0.0.1:0").expect("Could not create server.");
+	/// server.add_response_middleware(|_client, response| {
+	///     response.set_header("X-Content-Type-Options", "nosniff");
+	///     response.set_header("Strict-Transport-Security", "max-age=31536000");
+	/// });
+	/// ```
+	pub fn add_response_middleware(&mut self, middleware: impl Fn(&Client, &mut ResponseParts) + Send + Sync + 'static) {
+		Arc::make_mut(&mut self.settings).response_middleware.push(ResponseMiddleware(Arc::new(middleware)));
+	}
+
+	/// Set whether a response body which doesn't match its ``Content-Length`` exactly
+	/// is an error (default: false).
+	///
+	/// Responses like ``Client::respond_chunked`` announce ``content_size`` bytes and
+	/// read the body from a reader. If the reader ends early, the response can't be
+	/// completed: the connection is shut down so the client notices, and an
+	/// ``UnexpectedEof`` error is returned. This always applies.
+	///
+	/// If the reader has more data, only ``content_size`` bytes are sent and the rest
+	/// is left unread; by default, this is fine. In strict mode, the response is still
+	/// sent like this, but an ``InvalidData`` error is returned afterwards, which helps
+	/// to find wrong sizes, e.g. of files which grow while they are sent.
+	pub fn set_strict_content_length(&mut self, state: bool) {
+		Arc::make_mut(&mut self.settings).strict_content_length = state;
+	}
+
+	/// Set the maximum number of headers a response may have; the default is 100.
+	///
+	/// The respond methods of ``Client`` fail with ``InvalidInput`` instead of
+	/// sending a response with more headers, e.g. if a handler adds a header in
+	/// a loop by mistake.
+	pub fn set_max_response_headers(&mut self, count: usize) {
+		Arc::make_mut(&mut self.settings).max_response_headers = count;
+	}
+
+	/// Set the maximum total size of the headers of a response in bytes;
+	/// the default is 64 KiB.
+	///
+	/// The respond methods of ``Client`` fail with ``InvalidInput`` instead of
+	/// sending a response with larger headers.
+	pub fn set_max_response_header_size(&mut self, size: usize) {
+		Arc::make_mut(&mut self.settings).max_response_header_size = size;
+	}
+
+	/// Set the body and content type used when the server answers a request
+	/// with the given status code on its own, e.g. ``404`` for requests which
+	/// no route of a ``Router`` matches.
+	///
+	/// By default, such responses have an empty body. The page is also used
+	/// by ``Client::respond_error``.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let mut server = MicroHTTP::new("127.0.0.1:3004").expect("Could not create server.");
+	/// server.set_error_page(404, "<h1>Nothing here!</h1>".as_bytes(), "text/html; charset=utf-8");
+	/// ```
+	pub fn set_error_page(&mut self, status: u16, body: &[u8], content_type: &str) {
+		Arc::make_mut(&mut self.settings).error_pages.insert(status, ErrorPage {
+			body: body.to_vec(),
+			content_type: content_type.to_string()
+		});
 	}
 
 
@@ -44,7 +761,12 @@ impl MicroHTTP {
 	/// Returns either:
 	/// * ``Some(client)`` if a client is available
 	/// * ``None`` if no client is currently available (i.e. no one has reached out to the server yet)
-	/// * ``std::io::Error`` if something is wrong with the server.
+	///   or the request was already answered by the server itself (e.g. a rejected upload)
+	/// * ``std::io::Error`` if something is wrong with the server, e.g. accepting connections fails.
+	///
+	/// Problems with a single request, e.g. a connection reset while reading it,
+	/// don't cause an error here: the client is returned anyway, so it can still be
+	/// answered. Check ``Client::read_error`` for them.
 	///
 	/// # Example
 	///
@@ -52,8 +774,10 @@ impl MicroHTTP {
 	/// use std::{io::{Read,Write},net::TcpStream};
 	/// use micro_http_server::MicroHTTP;
 	///
-	/// let server = MicroHTTP::new("127.0.0.1:3000").expect("Could not create server.");
-	/// println!("[Server] Waiting for a client @ 127.0.0.1:3000...");
+	/// let server = MicroHTTP::new("127.0.0.1:3002").expect("Could not create server.");
+	/// println!("[Server] Waiting for a client @ 127.0.0.1:3002...");
+	/// # let mut connection = TcpStream::connect("127.0.0.1:3002").unwrap();
+	/// # connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
 	///
 	/// loop {
 	///     let result = server.next_client();
@@ -75,50 +799,2273 @@ impl MicroHTTP {
 	/// }
 	/// ```
 	pub fn next_client(&self) -> Result<Option<Client>,io::Error> {
-		// See if we have any incoming connections.
-		match self.listener.accept() {
-			// We do - try to create a Client from the incoming socket & addr,
-			// then return it.
-			Ok( (socket, addr) ) => Ok(Some(Client::new(socket, addr)?)),
+		if self.nonblocking || self.listeners.len() == 1 {
+			return self.accept(&(0..self.listeners.len()).collect::<Vec<_>>());
+		}
 
-			// Check if we just don't have an incoming connection or
-			// if really an error occured.
-			Err(err) => match err.kind() {
-				io::ErrorKind::WouldBlock => Ok(None), // No incoming connection
-				_ => Err(err) // We encountered an error :(
+		// The listeners are nonblocking (see new_multi), so wait until one of
+		// them has a connection. If it is gone by the time it is accepted, e.g.
+		// reset by the client, just keep waiting.
+		let mut backoff = IdleBackoff::new(self.poll_interval);
+		loop {
+			let ready = wait_readable(&self.listeners, None)?;
+			if let Some((socket, addr)) = self.accept_connection(&ready)? {
+				return Ok(self.new_client(socket, addr));
+			}
+			// Without poll(), all listeners were tried; don't spin.
+			if !cfg!(unix) {
+				thread::sleep(backoff.next());
 			}
 		}
 	}
-}
 
-#[cfg(test)]
-mod tests {
-	use super::MicroHTTP;
-	use std::{io::{Read,Write},net::TcpStream};
+	// Accept a connection from the first of the given listeners which has one
+	// and read its request.
+	fn accept(&self, candidates: &[usize]) -> Result<Option<Client>,io::Error> {
+		Ok(self.accept_connection(candidates)?.and_then(|(socket, addr)| self.new_client(socket, addr)))
+	}
 
-	#[test]
-	fn echo() {
-		let server = MicroHTTP::new("127.0.0.1:65534").expect("Could not create server");
-		println!("Waiting for a client @ 127.0.0.1:65534...");
+	// Create a Client for an accepted connection, unless it is rejected right away.
+	fn new_client(&self, socket: TcpStream, addr: SocketAddr) -> Option<Client> {
+		if !self.within_rate_limit(&socket, addr) {
+			self.settings.connection_closed(addr);
+			return None;
+		}
+		Client::new(socket, addr, self.settings.clone())
+	}
 
-		let mut connection = TcpStream::connect("127.0.0.1:65534").expect("Could not reach server");
-		println!("Connected!");
+	// Accept a connection from the first of the given listeners which has one,
+	// starting after the listener which was used last. Returns None if none
+	// of them has a connection.
+	fn accept_connection(&self, candidates: &[usize]) -> Result<Option<(TcpStream, SocketAddr)>,io::Error> {
+		let len = self.listeners.len();
+		let start = self.next_listener.load(Ordering::Relaxed) % len;
+		let mut candidates = candidates.to_vec();
+		candidates.sort_by_key(|idx| (idx + len - start) % len);
 
-		connection.write("GET /\r\n\r\n".as_bytes()).unwrap();
+		for idx in candidates {
+			// See if we have any incoming connections.
+			match self.listeners[idx].accept() {
+				// We do - return the incoming socket & addr.
+				Ok( (socket, addr) ) => {
+					self.next_listener.store(idx + 1, Ordering::Relaxed);
+					// On some platforms, the connection inherits nonblocking
+					// mode from the listener, but requests are read blocking.
+					if self.nonblocking || len > 1 {
+						if let Err(e) = socket.set_nonblocking(false) {
+							warn!("Could not make the connection from {} blocking: {}", addr, e);
+						}
+					}
+					return Ok(Some((socket, addr)));
+				},
 
-		{
-			let opt = server.next_client().unwrap();
-			assert_eq!(true, opt.is_some());
-			let mut client = opt.unwrap();
+				// Check if we just don't have an incoming connection or
+				// if really an error occured.
+				Err(err) => match err.kind() {
+					io::ErrorKind::WouldBlock => continue, // No incoming connection
+					_ => return Err(err) // We encountered an error :(
+				}
+			}
+		}
 
-			println!("Got a client!");
-			assert_eq!(true, client.request().is_some());
-			assert_eq!("/", client.request().as_ref().unwrap());
-			client.respond_ok("TEST".as_bytes()).unwrap();
+		Ok(None)
+	}
+
+	/// Serve clients forever, calling ``handler`` for each of them.
+	///
+	/// This replaces the usual loop around ``next_client``: it waits for clients
+	/// without busy-waiting (see ``set_poll_interval``) and passes every client to
+	/// ``handler``. Errors returned by the handler and errors while accepting a
+	/// connection are logged and the server goes on with the next client. Clients
+	/// whose request couldn't be read are passed to the handler as well, see
+	/// ``Client::read_error``.
+	///
+	/// Only returns if waiting for clients fails altogether. Use ``next_client``
+	/// if you need more control, e.g. to stop the server.
+	///
+	/// # Example
+	///
+	/// ```no_run
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let server = MicroHTTP::new("127.0.0.1:3000").expect("Could not create server.");
+	/// server.run(|mut client| {
+	///     let request = client.request().clone().unwrap_or_default();
+	///     client.respond_ok(request.as_bytes())?;
+	///     Ok::<(), std::io::Error>(())
+	/// }).expect("Server failed");
+	/// ```
+	pub fn run<E: fmt::Debug>(&self, mut handler: impl FnMut(Client) -> Result<(), E>) -> io::Result<()> {
+		let mut backoff = IdleBackoff::new(self.poll_interval);
+		loop {
+			let ready = wait_readable(&self.listeners, Some(self.poll_interval))?;
+			match self.accept(&ready) {
+				Ok(Some(client)) => {
+					backoff.reset();
+					let addr = client.addr();
+					if let Err(e) = handler(client) {
+						warn!("Handling the request from {} failed: {:?}", addr, e);
+					}
+				},
+				Ok(None) => if !cfg!(unix) && (self.nonblocking || self.listeners.len() > 1) {
+					thread::sleep(backoff.next());
+				},
+				Err(e) => {
+					warn!("Could not accept client: {}", e);
+					// Don't spin if the error persists, e.g. when out of file descriptors.
+					thread::sleep(self.poll_interval.min(Duration::from_millis(10)));
+				}
+			}
 		}
+	}
 
-		let mut buf = String::new();
+	// Check the connection against the per-IP rate limit and reject it with
+	// "429 Too Many Requests" if the limit is exceeded.
+	fn within_rate_limit(&self, mut socket: &TcpStream, addr: SocketAddr) -> bool {
+		let mut rate_limit = self.rate_limit.lock().unwrap_or_else(|e| e.into_inner());
+		let limiter = match rate_limit.as_mut() {
+			Some(limiter) => limiter,
+			None => return true
+		};
+		if limiter.allow(addr.ip(), Instant::now()) {
+			return true;
+		}
+
+		warn!("Rejecting connection from {}: too many connections", addr);
+		let retry_after = limiter.window.as_secs().max(1);
+		let response = format!(
+			"HTTP/1.0 429 Too Many Requests\r\nContent-Length: 0\r\nRetry-After: {}\r\n\r\n",
+			retry_after);
+		// The client is rejected anyway, so failing to tell it doesn't matter.
+		let _ = socket.write_all(response.as_bytes())
+			.and_then(|_| socket.shutdown(Shutdown::Write));
+		false
+	}
+
+	/// Wait up to ``timeout`` for the next client which is incoming at this server.
+	///
+	/// This is an alternative to calling ``next_client`` in a loop with a fixed sleep:
+	/// it returns as soon as a client connects, without busy-waiting.
+	///
+	/// Returns the same as ``next_client``, i.e. ``None`` if no client connected
+	/// within the timeout. On platforms without ``poll()`` (e.g. Windows), this
+	/// behaves exactly like ``next_client``, except for a server listening on several
+	/// interfaces, which returns None right away if no client is waiting.
+	///
+	/// # Example
+	///
+	/// ```
+	/// use std::time::Duration;
+	/// use micro_http_server::MicroHTTP;
+	///
+	/// let server = MicroHTTP::new("127.0.0.1:3005").expect("Could not create server.");
+	/// match server.next_client_timeout(Duration::from_millis(100)).unwrap() {
+	///     Some(client) => println!("Got a new client from: {:?}", client.addr()),
+	///     None => println!("No client within 100ms")
+	/// }
+	/// ```
+	pub fn next_client_timeout(&self, timeout: Duration) -> Result<Option<Client>,io::Error> {
+		let ready = wait_readable(&self.listeners, Some(timeout))?;
+		self.accept(&ready)
+	}
+}
+
+// How long run and next_client sleep when no client was waiting and they can't
+// wait with poll(): shortly while clients keep coming, doubling up to the poll
+// interval when idle.
+struct IdleBackoff {
+	current: Duration,
+	max: Duration,
+}
+
+impl IdleBackoff {
+	const MIN: Duration = Duration::from_millis(1);
+
+	fn new(max: Duration) -> IdleBackoff {
+		IdleBackoff { current: IdleBackoff::MIN.min(max), max }
+	}
+
+	// Return how long to sleep now; the next sleep is twice as long.
+	fn next(&mut self) -> Duration {
+		let sleep = self.current;
+		self.current = (self.current * 2).min(self.max);
+		sleep
+	}
+
+	// A client came in, so more are likely to follow.
+	fn reset(&mut self) {
+		self.current = IdleBackoff::MIN.min(self.max);
+	}
+}
+
+/// Expose the listening socket, e.g. to register it with your own event loop
+/// (epoll, ``mio``, ...) and call ``next_client`` only when it is readable.
+///
+/// Put the server into nonblocking mode (see ``set_nonblocking``) when doing so,
+/// so ``next_client`` never blocks on a spurious wakeup. For a server listening on
+/// several interfaces (see ``new_multi``), this is the socket of the first one.
+#[cfg(unix)]
+impl std::os::unix::io::AsRawFd for MicroHTTP {
+	fn as_raw_fd(&self) -> std::os::unix::io::RawFd {
+		std::os::unix::io::AsRawFd::as_raw_fd(&self.listeners[0])
+	}
+}
+
+/// Expose the listening socket, e.g. to register it with your own event loop
+/// and call ``next_client`` only when it is readable.
+///
+/// Put the server into nonblocking mode (see ``set_nonblocking``) when doing so.
+/// For a server listening on several interfaces (see ``new_multi``), this is the
+/// socket of the first one.
+#[cfg(windows)]
+impl std::os::windows::io::AsRawSocket for MicroHTTP {
+	fn as_raw_socket(&self) -> std::os::windows::io::RawSocket {
+		std::os::windows::io::AsRawSocket::as_raw_socket(&self.listeners[0])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::MicroHTTP;
+	use crate::{ParseWarning, ProtocolVersion, Router};
+	use crate::testing::TempDir;
+	use std::{io::{self,Read,Write},net::{Shutdown,SocketAddr,TcpStream},thread,time::{Duration,Instant}};
+
+	// Create a server on a free port and return it along with its address,
+	// so tests don't interfere with each other or other programs.
+	fn server() -> (MicroHTTP, SocketAddr) {
+		MicroHTTP::new_ephemeral("127.0.0.1").expect("Could not create server")
+	}
+
+	#[test]
+	fn echo() {
+		let (server, addr) = server();
+		println!("Waiting for a client @ {}...", addr);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		println!("Connected!");
+
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let opt = server.next_client().unwrap();
+			assert!(opt.is_some());
+			let mut client = opt.unwrap();
+
+			println!("Got a client!");
+			assert!(client.request().is_some());
+			assert_eq!("/", client.request().as_ref().unwrap());
+			assert!(client.handling_time().is_none());
+			client.respond_ok("TEST".as_bytes()).unwrap();
+			assert!(client.handling_time().is_some());
+		}
+
+		let mut buf = String::new();
 		connection.read_to_string(&mut buf).unwrap();
 		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\nTEST", buf);
 	}
+
+	#[test]
+	fn expect_continue_too_large() {
+		let (mut server, addr) = server();
+		server.set_max_request_size(Some(10));
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST /upload HTTP/1.1\r\nContent-Length: 100\r\nExpect: 100-continue\r\n\r\n".as_bytes()).unwrap();
+
+		assert!(server.next_client().unwrap().is_none());
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 413 Payload Too Large\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn expect_continue_accepted() {
+		let (mut server, addr) = server();
+		server.set_max_request_size(Some(10));
+
+		let uploader = thread::spawn(move || {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("POST /upload HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\n".as_bytes()).unwrap();
+
+			let mut buf = [0u8; 25];
+			connection.read_exact(&mut buf).unwrap();
+			assert_eq!(b"HTTP/1.1 100 Continue\r\n\r\n", &buf);
+			connection.write_all("HELLO".as_bytes()).unwrap();
+		});
+
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(Some(5), client.content_length());
+		assert_eq!(b"HELLO", client.body());
+		uploader.join().unwrap();
+	}
+
+	#[test]
+	fn respond_file_like() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /blob\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			client.respond_file_like("200 OK", "text/plain", "DATA".as_bytes(), 4, &["X-Extra: 1"]).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\nContent-Type: text/plain\r\nX-Extra: 1\r\n\r\nDATA", buf);
+	}
+
+	#[test]
+	fn respond_download() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /download\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			client.respond_download("DATA".as_bytes(), 4, "Bericht \"März\".pdf").unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\nContent-Type: application/pdf\r\n\
+			Content-Disposition: attachment; filename=\"Bericht \\\"M_rz\\\".pdf\"; filename*=UTF-8''Bericht%20%22M%C3%A4rz%22.pdf\r\n\r\nDATA", buf);
+	}
+
+	#[test]
+	fn custom_error_page() {
+		let (mut server, addr) = server();
+		server.set_error_page(404, "{}".as_bytes(), "application/json");
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /missing\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			Router::new().handle(&mut client).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 404 Not Found\r\nContent-Length: 2\r\nContent-Type: application/json\r\n\r\n{}", buf);
+	}
+
+	#[test]
+	fn next_client_timeout() {
+		let (server, addr) = server();
+
+		let start = Instant::now();
+		assert!(server.next_client_timeout(Duration::from_millis(50)).unwrap().is_none());
+		assert!(start.elapsed() >= Duration::from_millis(50));
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client_timeout(Duration::from_secs(5)).unwrap().is_some());
+	}
+
+	#[test]
+	fn query_params() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /posts?tag=a&q=J%C3%BCrgen+M.&tag=b&flag HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+
+		let client = server.next_client().unwrap().unwrap();
+		let params = client.query_params();
+		assert_eq!(Some("b"), params.get("tag").map(|v| v.as_str()));
+		assert_eq!(Some("Jürgen M."), params.get("q").map(|v| v.as_str()));
+		assert_eq!(3, params.len());
+
+		let params = client.query_params_multi();
+		assert_eq!(Some(&vec!("a".to_string(), "b".to_string())), params.get("tag"));
+		assert_eq!(Some(&vec!(String::new())), params.get("flag"));
+		assert_eq!(3, params.len());
+		drop(client);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /posts HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert!(client.query_params().is_empty());
+		assert!(client.query_params_multi().is_empty());
+	}
+
+	#[test]
+	fn form_params() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all(concat!(
+			"POST /form HTTP/1.1\r\n",
+			"Content-Type: application/x-www-form-urlencoded\r\n",
+			"Content-Length: 20\r\n\r\n",
+			"name=J%C3%BCrgen+M.&").as_bytes()).unwrap();
+
+		let client = server.next_client().unwrap().unwrap();
+		let params = client.form_params().unwrap();
+		assert_eq!(Some("Jürgen M."), params.get("name").map(|v| v.as_str()));
+		assert_eq!(1, params.len());
+	}
+
+	#[test]
+	fn respond_status_reason() {
+		let (server, addr) = server();
+		let mut first = TcpStream::connect(addr).expect("Could not reach server");
+		first.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		server.next_client().unwrap().unwrap().respond_status(404, &[]).unwrap();
+
+		let mut second = TcpStream::connect(addr).expect("Could not reach server");
+		second.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		server.next_client().unwrap().unwrap().respond_status_reason(200, "Totally Fine", &[]).unwrap();
+
+		let mut buf = String::new();
+		first.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 404 Not Found\r\nContent-Length: 0\r\n\r\n", buf);
+
+		buf.clear();
+		second.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 Totally Fine\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn respond_unframed() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /stream\r\n\r\n".as_bytes()).unwrap();
+
+		// Keep the client alive: the body must end without dropping it.
+		let mut client = server.next_client().unwrap().unwrap();
+		client.respond_unframed("200 OK", "STREAM".as_bytes(), &vec!()).unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\n\r\nSTREAM", buf);
+	}
+
+	#[test]
+	fn multiple_interfaces() {
+		let mut server = MicroHTTP::new_multi(&["127.0.0.1:0", "127.0.0.1:0"]).expect("Could not create server");
+		let addrs = server.local_addrs().unwrap();
+
+		let mut connection = TcpStream::connect(addrs[1]).expect("Could not reach server");
+		connection.write_all("GET /second\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!("/second", client.request().as_ref().unwrap());
+
+		// Even in blocking mode, trying a listener without a connection, e.g.
+		// because it was reset after poll() reported it, must not block.
+		assert!(server.accept(&[0, 1]).unwrap().is_none());
+		server.set_nonblocking(true).unwrap();
+		server.set_nonblocking(false).unwrap();
+		assert!(server.accept(&[0, 1]).unwrap().is_none());
+
+		server.set_nonblocking(true).unwrap();
+		assert!(server.next_client().unwrap().is_none());
+
+		let mut connection = TcpStream::connect(addrs[0]).expect("Could not reach server");
+		connection.write_all("GET /first\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client_timeout(Duration::from_secs(5)).unwrap().unwrap();
+		assert_eq!("/first", client.request().as_ref().unwrap());
+	}
+
+	#[test]
+	fn uri_too_long() {
+		let (mut server, addr) = server();
+		server.set_max_uri_length(10);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /0123456789 HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_none());
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 414 URI Too Long\r\nContent-Length: 0\r\n\r\n", buf);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /012345678 HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_some());
+	}
+
+	#[test]
+	fn respond_html() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		server.next_client().unwrap().unwrap().respond_html("200 OK", "<p>ä</p>").unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 9\r\nContent-Type: text/html; charset=utf-8\r\n\r\n<p>ä</p>", buf);
+	}
+
+	#[test]
+	fn body_shorter_than_declared() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nSHORT".as_bytes()).unwrap();
+		connection.shutdown(Shutdown::Write).unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		assert_eq!(io::ErrorKind::UnexpectedEof, client.read_error().unwrap().kind());
+		assert_eq!(b"SHORT", client.body());
+		assert!(!client.body_complete());
+		client.respond_error("400 Bad Request").unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn delayed_body() {
+		let (server, addr) = server();
+
+		let uploader = thread::spawn(move || {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("POST /upload HTTP/1.1\r\nHost: localhost\r\n".as_bytes()).unwrap();
+			thread::sleep(Duration::from_millis(50));
+			connection.write_all("Content-Length: 10\r\n\r\n".as_bytes()).unwrap();
+			thread::sleep(Duration::from_millis(50));
+			connection.write_all("HELLO".as_bytes()).unwrap();
+			thread::sleep(Duration::from_millis(50));
+			connection.write_all("WORLD".as_bytes()).unwrap();
+		});
+
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(Some("localhost"), client.header("Host"));
+		assert_eq!(Some(10), client.content_length());
+		assert_eq!(b"HELLOWORLD", client.body());
+		uploader.join().unwrap();
+	}
+
+	#[test]
+	fn body_longer_than_declared() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nABCDEF".as_bytes()).unwrap();
+
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(b"ABC", client.body());
+	}
+
+	#[test]
+	fn strict_framing() {
+		let (mut server, addr) = server();
+
+		// Lenient by default: the request is accepted.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nHost: localhost\r\n\r\nUNEXPECTED".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_some());
+
+		server.set_strict_framing(true);
+		let requests = [
+			"GET / HTTP/1.1\r\nHost: localhost\r\n\r\nUNEXPECTED",
+			"POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nABCDEF",
+		];
+		for request in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			assert!(server.next_client().unwrap().is_none());
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf);
+		}
+
+		// Requests without trailing data are not affected.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\nABC".as_bytes()).unwrap();
+		assert_eq!(b"ABC", server.next_client().unwrap().unwrap().body());
+	}
+
+	#[test]
+	fn respond_file_handle() {
+		let dir = TempDir::new("file_handle");
+		let path = dir.join("file.txt");
+		std::fs::write(&path, "FILE CONTENT").unwrap();
+
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		let file = std::fs::File::open(&path).unwrap();
+		server.next_client().unwrap().unwrap().respond_file_handle(file).unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 12\r\n\r\nFILE CONTENT", buf);
+	}
+
+	#[cfg(feature = "sha2")]
+	#[test]
+	fn respond_with_digest() {
+		use crate::digest::{base64, sha256};
+
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		let data = io::Cursor::new(b"DIGESTED CONTENT".to_vec());
+		server.next_client().unwrap().unwrap().respond_with_digest("200 OK", "text/plain", data, &[]).unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		let (head, body) = buf.split_at(buf.find("\r\n\r\n").unwrap() + 4);
+		assert_eq!("DIGESTED CONTENT", body);
+
+		let digest = base64(&sha256(body.as_bytes()).unwrap().0);
+		assert!(head.contains("Content-Length: 16\r\n"));
+		assert!(head.contains(&format!("\r\nRepr-Digest: sha-256=:{}:\r\n", digest)));
+		assert!(head.contains(&format!("\r\nDigest: SHA-256={}\r\n", digest)));
+	}
+
+	#[cfg(feature = "sha2")]
+	#[test]
+	fn respond_with_trailing_digest() {
+		use crate::digest::{base64, sha256};
+
+		let (mut server, addr) = server();
+		server.set_protocol_version(ProtocolVersion::Http11);
+		server.set_buffer_size(8);
+		let fetch = |request: &str| {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+
+			let mut client = server.next_client().unwrap().unwrap();
+			client.respond_with_trailing_digest("200 OK", "text/plain", &b"DIGESTED CONTENT"[..], &[]).unwrap();
+			drop(client);
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			buf
+		};
+
+		let head = "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n";
+		let body = "8\r\nDIGESTED\r\n8\r\n CONTENT\r\n0\r\n";
+		let digest = base64(&sha256(&b"DIGESTED CONTENT"[..]).unwrap().0);
+		assert_eq!(
+			format!("{}Trailer: Repr-Digest\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n{}Repr-Digest: sha-256=:{}:\r\n\r\n", head, body, digest),
+			fetch("GET /data HTTP/1.1\r\nTE: trailers\r\n\r\n"));
+
+		// Without TE: trailers, there is no digest.
+		assert_eq!(
+			format!("{}Transfer-Encoding: chunked\r\nConnection: close\r\n\r\n{}\r\n", head, body),
+			fetch("GET /data HTTP/1.1\r\n\r\n"));
+	}
+
+	#[test]
+	fn begin_chunked() {
+		let (mut server, addr) = server();
+		server.set_protocol_version(ProtocolVersion::Http11);
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /log HTTP/1.1\r\nHost: localhost\r\nTE: trailers\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			assert!(client.accepts_trailers());
+			let mut response = client.begin_chunked("200 OK", &["Content-Type: text/plain"]).unwrap();
+			response.write_chunk(b"first line\n").unwrap();
+			response.write_chunk(b"").unwrap();
+			response.write_chunk(b"second line, a bit longer\n").unwrap();
+			let bytes_written = response.finish(&["X-Lines: 2"]).unwrap();
+			assert!(client.handling_time().is_some());
+			assert_eq!(157, bytes_written);
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+			b\r\nfirst line\n\r\n1a\r\nsecond line, a bit longer\n\r\n0\r\nX-Lines: 2\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn begin_chunked_without_te_trailers() {
+		let (mut server, addr) = server();
+		server.set_protocol_version(ProtocolVersion::Http11);
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /log HTTP/1.1\r\nTE: deflate\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			assert!(!client.accepts_trailers());
+			let mut response = client.begin_chunked("200 OK", &[]).unwrap();
+			response.write_chunk(b"data").unwrap();
+			response.finish(&["X-Lines: 1"]).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n\
+			4\r\ndata\r\n0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn begin_chunked_http10() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /log HTTP/1.0\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			let mut response = client.begin_chunked("200 OK", &[]).unwrap();
+			response.write_chunk(b"first\n").unwrap();
+			response.write_chunk(b"second\n").unwrap();
+			response.finish(&["X-Lines: 2"]).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\n\r\nfirst\nsecond\n", buf);
+	}
+
+	#[test]
+	fn begin_chunked_default_protocol() {
+		// In the default HTTP/1.0 mode, HTTP/1.1 clients don't get chunks either.
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /log HTTP/1.1\r\nHost: localhost\r\nTE: trailers\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			let mut response = client.begin_chunked("200 OK", &["Content-Type: text/plain"]).unwrap();
+			response.write_chunk(b"first\n").unwrap();
+			response.write_chunk(b"second\n").unwrap();
+			response.finish(&["X-Lines: 2"]).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\n\r\nfirst\nsecond\n", buf);
+	}
+
+	#[test]
+	fn response_header_limits() {
+		let (mut server, addr) = server();
+		server.set_max_response_headers(2);
+		server.set_max_response_header_size(20);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			let too_many = vec!("A: 1".to_string(), "B: 2".to_string(), "C: 3".to_string());
+			let too_large = vec!("X-Large: 0123456789".to_string());
+			let injected = vec!("X-Name: a\r\nSet-Cookie: session=evil".to_string());
+			for headers in [too_many, too_large, injected].iter() {
+				let err = client.respond("200 OK", &[], headers).unwrap_err();
+				assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+			}
+
+			let err = client.respond("200 OK", &[], &vec!("X-Name: a\nb".to_string())).unwrap_err();
+			assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+			let err = client.respond_status_reason(200, "OK\r\nSet-Cookie: session=evil", &[]).unwrap_err();
+			assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+			let err = client.respond_file_like("200 OK", "text/html\0", &[][..], 0, &[]).unwrap_err();
+			assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+
+			client.respond("200 OK", &[], &vec!("A: 1".to_string(), "B: 2".to_string())).unwrap();
+		}
+
+		// Nothing was sent for the rejected responses.
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 0\r\nA: 1\r\nB: 2\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn respond_cached() {
+		let (server, addr) = server();
+		let requests = [
+			("GET / HTTP/1.1\r\nIf-None-Match: \"rev-1\"\r\n\r\n", "HTTP/1.0 200 OK\r\nContent-Length: 5\r\nETag: \"rev-2\"\r\n\r\nrev 2", true),
+			("GET / HTTP/1.1\r\nIf-None-Match: \"rev-2\"\r\n\r\n", "HTTP/1.0 304 Not Modified\r\nETag: \"rev-2\"\r\n\r\n", false),
+			("GET / HTTP/1.1\r\n\r\n", "HTTP/1.0 200 OK\r\nContent-Length: 5\r\nETag: \"rev-2\"\r\n\r\nrev 2", true),
+		];
+
+		for (request, expected, produced) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+
+			let mut called = false;
+			server.next_client().unwrap().unwrap().respond_cached("rev-2", || {
+				called = true;
+				"rev 2"
+			}).unwrap();
+			assert_eq!(*produced, called);
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(*expected, buf);
+		}
+	}
+
+	#[test]
+	fn small_buffer_size() {
+		let (mut server, addr) = server();
+		server.set_buffer_size(3);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST /upload HTTP/1.1\r\nContent-Length: 5\r\n\r\nHELLO".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			assert_eq!(Some("/upload".to_string()), *client.request());
+			assert_eq!(b"HELLO", client.body());
+			client.respond_ok(b"0123456789").unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 10\r\n\r\n0123456789", buf);
+	}
+
+	#[test]
+	fn read_buffer_hint() {
+		// Bodies are read completely, whether they fit into the reservation or not.
+		for hint in [0, 100, 1024 * 1024].iter() {
+			let (mut server, addr) = server();
+			server.set_read_buffer_hint(*hint);
+
+			let uploader = thread::spawn(move || {
+				let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+				connection.write_all(b"POST /upload HTTP/1.1\r\nContent-Length: 100000\r\n\r\n").unwrap();
+				connection.write_all(&[b'x'; 100000]).unwrap();
+				connection.read_to_end(&mut Vec::new()).unwrap();
+			});
+
+			let mut client = server.next_client().unwrap().unwrap();
+			assert_eq!(&[b'x'; 100000][..], client.body());
+			client.respond_ok(&[]).unwrap();
+			drop(client);
+			uploader.join().unwrap();
+		}
+	}
+
+	#[test]
+	fn respond_precompressed() {
+		let dir = TempDir::new("precompressed");
+		std::fs::write(dir.join("app.js"), "PLAIN").unwrap();
+		std::fs::write(dir.join("app.js.gz"), "GZ").unwrap();
+		std::fs::write(dir.join("other.js"), "OTHER").unwrap();
+
+		let (server, addr) = server();
+		let requests = [
+			("GET /app.js HTTP/1.1\r\nAccept-Encoding: gzip, deflate\r\n\r\n",
+				"Content-Length: 2\r\nContent-Type: text/javascript\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n\r\nGZ"),
+			("GET /app.js HTTP/1.1\r\nAccept-Encoding: gzip;q=0\r\n\r\n",
+				"Content-Length: 5\r\nContent-Type: text/javascript\r\nVary: Accept-Encoding\r\n\r\nPLAIN"),
+			("GET /app.js HTTP/1.1\r\n\r\n",
+				"Content-Length: 5\r\nContent-Type: text/javascript\r\nVary: Accept-Encoding\r\n\r\nPLAIN"),
+			("GET /other.js HTTP/1.1\r\nAccept-Encoding: gzip\r\n\r\n",
+				"Content-Length: 5\r\nContent-Type: text/javascript\r\n\r\nOTHER"),
+		];
+
+		for (request, expected) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			server.next_client().unwrap().unwrap().respond_static(&dir).unwrap();
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(format!("HTTP/1.0 200 OK\r\n{}", expected), buf);
+		}
+	}
+
+	#[test]
+	fn options_asterisk() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("OPTIONS * HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut router = Router::new();
+			router.add_method("GET", "/", |client| client.respond_ok(&[]));
+			router.add_method("PUT", "/upload", |client| client.respond_ok(&[]));
+
+			let mut client = server.next_client().unwrap().unwrap();
+			assert_eq!(Some("OPTIONS"), client.method());
+			router.handle(&mut client).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 0\r\nAllow: GET, PUT, OPTIONS\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn finish() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST /job\r\n\r\n".as_bytes()).unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		client.respond("202 Accepted", &[], &vec!()).unwrap();
+		client.finish().unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 202 Accepted\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn stream_body() {
+		let (mut server, addr) = server();
+		server.set_stream_bodies(true);
+
+		let uploader = thread::spawn(move || {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nHELLO".as_bytes()).unwrap();
+			thread::sleep(Duration::from_millis(50));
+			connection.write_all("WORLD".as_bytes()).unwrap();
+		});
+
+		let mut client = server.next_client().unwrap().unwrap();
+		assert!(!client.body_complete());
+		let head_len = client.request_header_bytes();
+		let sent_up_front = client.request_bytes();
+		assert!(sent_up_front >= head_len);
+		let mut reader = client.body_reader().unwrap();
+		assert_eq!(Some(10), reader.expected_len());
+
+		let mut body = String::new();
+		reader.read_to_string(&mut body).unwrap();
+		assert_eq!("HELLOWORLD", body);
+		assert!(client.body_complete());
+		assert_eq!(head_len + 10, client.request_bytes());
+		uploader.join().unwrap();
+	}
+
+	#[test]
+	fn stream_body_truncated() {
+		let (mut server, addr) = server();
+		server.set_stream_bodies(true);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nHELLO".as_bytes()).unwrap();
+		connection.shutdown(Shutdown::Write).unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		let mut body = Vec::new();
+		let err = client.body_reader().unwrap().read_to_end(&mut body).unwrap_err();
+		assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+		assert_eq!(b"HELLO", &body[..]);
+		assert!(!client.body_complete());
+	}
+
+	#[cfg(unix)]
+	#[test]
+	fn as_raw_fd() {
+		use std::os::unix::io::AsRawFd;
+
+		let (mut server, addr) = server();
+		server.set_nonblocking(true).unwrap();
+		let mut fds = [libc::pollfd { fd: server.as_raw_fd(), events: libc::POLLIN, revents: 0 }];
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		assert_eq!(1, unsafe { libc::poll(fds.as_mut_ptr(), 1, 5000) });
+		assert!(server.next_client().unwrap().is_some());
+		assert!(server.next_client().unwrap().is_none());
+	}
+
+	#[test]
+	fn run() {
+		let (server, addr) = server();
+		thread::spawn(move || server.run(|mut client| match client.request().as_deref() {
+			Some("/fail") => Err("failing on purpose"),
+			_ => client.respond_ok(b"served").map(|_| ()).map_err(|_| "could not respond")
+		}));
+
+		for request in ["GET /fail\r\n\r\n", "GET /\r\n\r\n", "GET /\r\n\r\n"].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			match *request {
+				"GET /fail\r\n\r\n" => assert_eq!("", buf),
+				_ => assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 6\r\n\r\nserved", buf)
+			}
+		}
+	}
+
+	#[test]
+	fn idle_backoff() {
+		use super::IdleBackoff;
+
+		let mut backoff = IdleBackoff::new(Duration::from_millis(10));
+		let sleeps = (0..6).map(|_| backoff.next().as_millis()).collect::<Vec<u128>>();
+		assert_eq!(vec!(1, 2, 4, 8, 10, 10), sleeps);
+
+		backoff.reset();
+		assert_eq!(Duration::from_millis(1), backoff.next());
+
+		let mut backoff = IdleBackoff::new(Duration::from_micros(100));
+		assert_eq!(Duration::from_micros(100), backoff.next());
+		assert_eq!(Duration::from_micros(100), backoff.next());
+	}
+
+	#[test]
+	fn referer_and_origin() {
+		let (server, addr) = server();
+		let requests = [
+			("Referer: https://example.com/page?q=1\r\nOrigin: https://example.com:8080\r\n",
+				Some("https://example.com/page?q=1"), Some("https://example.com:8080")),
+			("origin: https://example.com/path/\r\n", None, Some("https://example.com")),
+			("Origin: null\r\n", None, Some("null")),
+			("", None, None),
+		];
+
+		for (headers, referer, origin) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("POST / HTTP/1.1\r\n{}\r\n", headers).as_bytes()).unwrap();
+
+			let client = server.next_client().unwrap().unwrap();
+			assert_eq!(*referer, client.referer());
+			assert_eq!(*origin, client.origin());
+		}
+	}
+
+	#[test]
+	fn per_ip_rate_limit() {
+		let (mut server, addr) = server();
+		server.set_per_ip_rate_limit(2, Duration::from_secs(60));
+
+		for i in 0..3 {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+			let client = server.next_client().unwrap();
+
+			match i {
+				2 => {
+					assert!(client.is_none());
+					let mut buf = String::new();
+					connection.read_to_string(&mut buf).unwrap();
+					assert_eq!("HTTP/1.0 429 Too Many Requests\r\nContent-Length: 0\r\nRetry-After: 60\r\n\r\n", buf);
+				},
+				_ => assert!(client.is_some())
+			}
+		}
+	}
+
+	#[test]
+	fn scheme() {
+		let (mut server, addr) = server();
+		let requests = [
+			(false, "X-Forwarded-Proto: https\r\n", "http"),
+			(true, "X-Forwarded-Proto: https\r\n", "https"),
+			(true, "x-forwarded-proto: HTTPS, http\r\n", "https"),
+			(true, "X-Forwarded-Proto: ftp\r\n", "http"),
+			(true, "", "http"),
+		];
+
+		for (trust, headers, scheme) in requests.iter() {
+			server.set_trust_forwarded_proto(*trust);
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes()).unwrap();
+			assert_eq!(*scheme, server.next_client().unwrap().unwrap().scheme());
+		}
+	}
+
+	#[test]
+	fn duplicate_host() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nHost: example.com\r\nhost: evil.com\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_none());
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn ambiguous_body_length() {
+		let (server, addr) = server();
+
+		for request in [
+			"POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 5\r\n\r\nabc",
+			"POST / HTTP/1.1\r\nContent-Length: 3\r\nTransfer-Encoding: chunked\r\n\r\nabc",
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			assert!(server.next_client().unwrap().is_none());
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf, "{:?}", request);
+		}
+
+		// Repeating the same length is fine.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 3\r\nContent-Length: 3\r\n\r\nabc".as_bytes()).unwrap();
+		assert_eq!(b"abc", server.next_client().unwrap().unwrap().body());
+	}
+
+	#[test]
+	fn begin_buffered() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		{
+			let mut client = server.next_client().unwrap().unwrap();
+			let mut response = client.begin_buffered("200 OK", &["Content-Type: text/plain"], 10);
+			write!(response, "{}-{}", 123, 456).unwrap();
+			assert_eq!(7, response.len());
+			assert_eq!(io::ErrorKind::InvalidInput, response.write_all(b"7890").unwrap_err().kind());
+			response.write_all(b"789").unwrap();
+			response.finish().unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 10\r\nContent-Type: text/plain\r\n\r\n123-456789", buf);
+	}
+
+	#[test]
+	fn respond_dir_listing() {
+		let dir = TempDir::new("dir_listing");
+		std::fs::create_dir_all(dir.join("sub")).unwrap();
+		std::fs::write(dir.join("sub").join("file.txt"), "data").unwrap();
+
+		let (server, addr) = server();
+		let requests = [
+			("/sub", "HTTP/1.0 301 Moved Permanently\r\nContent-Length: 0\r\nLocation: /sub/\r\n\r\n"),
+			("//sub", "\r\nLocation: /sub/\r\n"),
+			("/sub/?sort=name", "<li><a href=\"./file.txt\">file.txt</a></li>"),
+			("/sub/", "<li><a href=\"../\">../</a></li>"),
+			("/%2e%2e/", "HTTP/1.0 403 Forbidden\r\n"),
+			("/missing/", "HTTP/1.0 404 Not Found\r\n"),
+		];
+
+		for (path, expected) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("GET {} HTTP/1.1\r\n\r\n", path).as_bytes()).unwrap();
+			{
+				let mut client = server.next_client().unwrap().unwrap();
+				let target = client.request().clone().unwrap();
+				client.respond_dir_listing(&dir, &target).unwrap();
+			}
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert!(buf.contains(expected), "{} not in {}", expected, buf);
+		}
+	}
+
+	#[test]
+	fn method_override() {
+		let (mut server, addr) = server();
+		let requests = [
+			(false, "POST", "DELETE", "POST"),
+			(true, "POST", "delete", "DELETE"),
+			(true, "POST", "PATCH", "PATCH"),
+			(true, "POST", "CONNECT", "POST"),
+			(true, "GET", "DELETE", "GET"),
+		];
+
+		for (enabled, method, header, expected) in requests.iter() {
+			server.set_method_override(*enabled);
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("{} / HTTP/1.1\r\nX-HTTP-Method-Override: {}\r\n\r\n", method, header).as_bytes()).unwrap();
+			assert_eq!(Some(*expected), server.next_client().unwrap().unwrap().method());
+		}
+	}
+
+	#[test]
+	fn request_bytes() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\nContent-Length: 5\r\n\r\nHELLO".as_bytes()).unwrap();
+
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(38, client.request_header_bytes());
+		assert_eq!(43, client.request_bytes());
+	}
+
+	#[test]
+	fn respond_partially_consumed_reader() {
+		use std::io::{BufRead, BufReader};
+
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		// The reader buffers more than the header line, so part of the body
+		// is only in its buffer when the response starts.
+		let content = "HEADER LINE\nbody line 1\nbody line 2\ntrailing data";
+		let mut reader = BufReader::with_capacity(16, content.as_bytes());
+		let mut header = String::new();
+		reader.read_line(&mut header).unwrap();
+		assert!(!reader.buffer().is_empty());
+
+		server.next_client().unwrap().unwrap().respond_ok_chunked(&mut reader, 24).unwrap();
+		let mut rest = String::new();
+		reader.read_to_string(&mut rest).unwrap();
+		assert_eq!("trailing data", rest);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 24\r\n\r\nbody line 1\nbody line 2\n", buf);
+	}
+
+	#[test]
+	fn request_line() {
+		let (server, addr) = server();
+		let requests = [
+			("GET /index.html HTTP/1.1\r\n\r\n", Some("GET /index.html HTTP/1.1"), Some("GET"), Some("/index.html")),
+			("BREW\r\n\r\n", Some("BREW"), Some("BREW"), None),
+			("\x16\x03\x01 garbage\r\n\r\n", Some("\x16\x03\x01 garbage"), None, Some("garbage")),
+			("", None, None, None),
+		];
+
+		for (data, line, method, request) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(data.as_bytes()).unwrap();
+			connection.shutdown(Shutdown::Write).unwrap();
+
+			let client = server.next_client().unwrap().unwrap();
+			assert_eq!(*line, client.request_line());
+			assert_eq!(*method, client.method());
+			assert_eq!(*request, client.request().as_deref());
+		}
+	}
+
+	#[test]
+	fn output_filter() {
+		use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+
+		let bytes_sent = Arc::new(AtomicUsize::new(0));
+		{
+			let counter = bytes_sent.clone();
+			let mut client = server.next_client().unwrap().unwrap();
+			client.set_output_filter(move |data, connection| {
+				counter.fetch_add(data.len(), Ordering::Relaxed);
+				connection.write_all(&data.to_ascii_uppercase())
+			});
+
+			let bytes_written = client.respond_ok(b"hello").unwrap();
+			assert_eq!(bytes_written, bytes_sent.load(Ordering::Relaxed));
+
+			let mut response = client.begin_chunked("200 OK", &[]).unwrap();
+			response.write_chunk(b"more").unwrap();
+			response.finish(&[]).unwrap();
+		}
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!(buf.len(), bytes_sent.load(Ordering::Relaxed));
+		assert!(buf.starts_with("HTTP/1.0 200 OK\r\nCONTENT-LENGTH: 5\r\n\r\nHELLO"));
+		assert!(buf.ends_with("HTTP/1.0 200 OK\r\n\r\nMORE"));
+	}
+
+	#[test]
+	fn shutdown_write() {
+		let (mut server, addr) = server();
+		server.set_linger(Some(Duration::from_secs(1)));
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		client.respond_ok(b"done").unwrap();
+		client.shutdown_write().unwrap();
+
+		// The client sees the end of the response while the Client still exists.
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\ndone", buf);
+		drop(client);
+	}
+
+	#[test]
+	fn allowed_methods() {
+		let (mut server, addr) = server();
+		server.set_allowed_methods(Some(&["GET", "HEAD"]));
+
+		for method in ["GET", "HEAD", "POST", "get"].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("{} / HTTP/1.1\r\n\r\n", method).as_bytes()).unwrap();
+			let client = server.next_client().unwrap();
+
+			match *method {
+				"GET" | "HEAD" => assert_eq!(Some(*method), client.unwrap().method()),
+				_ => {
+					assert!(client.is_none());
+					let mut buf = String::new();
+					connection.read_to_string(&mut buf).unwrap();
+					assert_eq!("HTTP/1.0 405 Method Not Allowed\r\nContent-Length: 0\r\nAllow: GET, HEAD\r\n\r\n", buf);
+				}
+			}
+		}
+
+		server.set_allowed_methods(None);
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("POST / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_some());
+	}
+
+	#[test]
+	fn respond_negotiated_compressed_uncompressed() {
+		let (server, addr) = server();
+		let requests = [
+			("GET / HTTP/1.1\r\n\r\n",
+				"200 OK\r\nContent-Length: 5\r\nVary: Accept-Encoding\r\n\r\nPLAIN"),
+			("GET / HTTP/1.1\r\nAccept-Encoding: compress, identity\r\n\r\n",
+				"200 OK\r\nContent-Length: 5\r\nVary: Accept-Encoding\r\n\r\nPLAIN"),
+			("GET / HTTP/1.1\r\nAccept-Encoding: compress, identity;q=0\r\n\r\n",
+				"406 Not Acceptable\r\nContent-Length: 0\r\nVary: Accept-Encoding\r\n\r\n"),
+		];
+
+		for (request, expected) in requests.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			server.next_client().unwrap().unwrap().respond_negotiated_compressed("200 OK", b"PLAIN").unwrap();
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(format!("HTTP/1.0 {}", expected), buf);
+		}
+	}
+
+	#[cfg(feature = "flate2")]
+	#[test]
+	fn respond_negotiated_compressed_gzip() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nAccept-Encoding: deflate;q=0.5, gzip, identity;q=0.1\r\n\r\n".as_bytes()).unwrap();
+
+		let text = "Compress me. ".repeat(100);
+		server.next_client().unwrap().unwrap().respond_negotiated_compressed("200 OK", text.as_bytes()).unwrap();
+
+		let mut buf = Vec::new();
+		connection.read_to_end(&mut buf).unwrap();
+		let head_end = buf.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+		let head = String::from_utf8_lossy(&buf[..head_end]);
+		assert!(head.contains("\r\nContent-Encoding: gzip\r\nVary: Accept-Encoding\r\n"));
+		assert!(head.contains(&format!("Content-Length: {}\r\n", buf.len() - head_end)));
+
+		let mut decoded = String::new();
+		flate2::read::GzDecoder::new(&buf[head_end..]).read_to_string(&mut decoded).unwrap();
+		assert_eq!(text, decoded);
+	}
+
+	#[test]
+	fn respond_temp_file() {
+		let dir = TempDir::new("temp_file");
+		let path = dir.join("report.txt");
+		let mut file = std::fs::File::options().read(true).write(true).create(true).truncate(true).open(&path).unwrap();
+		file.write_all(b"GENERATED").unwrap();
+
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /report\r\n\r\n".as_bytes()).unwrap();
+		let bytes_written = server.next_client().unwrap().unwrap()
+			.respond_temp_file("200 OK", file, Some(&path), &["Content-Type: text/plain"]).unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 9\r\nContent-Type: text/plain\r\n\r\nGENERATED", buf);
+		assert_eq!(buf.len(), bytes_written);
+		assert!(!path.exists());
+	}
+
+	#[test]
+	fn request_url_fragment() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /foo?x=1#bar HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+
+		let mut router = Router::new();
+		router.add("/foo", |client| client.respond_ok(b"found"));
+
+		let mut client = server.next_client().unwrap().unwrap();
+		assert_eq!(Some("/foo?x=1"), client.request().as_deref());
+		let url = client.request_url().unwrap();
+		assert_eq!("/foo", url.path());
+		assert_eq!(Some("x=1"), url.query());
+		assert_eq!(Some("bar"), url.fragment());
+
+		router.handle(&mut client).unwrap();
+		drop(client);
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert!(buf.starts_with("HTTP/1.0 200 OK\r\n"));
+		assert!(buf.ends_with("found"));
+	}
+
+	#[test]
+	fn malformed_request() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all(b"\xff\xfe\x00garbage\r\n\r\n").unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		assert!(client.request().is_none());
+		assert!(client.read_error().is_none());
+		client.respond_error("400 Bad Request").unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 400 Bad Request\r\nContent-Length: 0\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn respond_debug() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /debug?x=1 HTTP/1.1\r\nHost: localhost\r\nx-forwarded-for: 10.0.0.1\r\nCookie: session=secret\r\n\r\n".as_bytes()).unwrap();
+		server.next_client().unwrap().unwrap().respond_debug().unwrap();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		let (head, body) = buf.split_at(buf.find("\r\n\r\n").unwrap() + 4);
+		assert!(head.starts_with("HTTP/1.0 200 OK\r\n"));
+		assert!(head.contains("\r\nContent-Type: text/plain; charset=utf-8\r\n"));
+		assert_eq!("GET /debug?x=1 HTTP/1.1\nHost: localhost\nx-forwarded-for: 10.0.0.1\nCookie: <redacted>\n", body);
+	}
+
+	#[test]
+	fn read_body_into() {
+		for stream_bodies in [false, true].iter() {
+			let (mut server, addr) = server();
+			server.set_stream_bodies(*stream_bodies);
+
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("POST /upload HTTP/1.1\r\nContent-Length: 11\r\n\r\nHELLO".as_bytes()).unwrap();
+			let uploader = thread::spawn(move || {
+				thread::sleep(Duration::from_millis(50));
+				connection.write_all(" WORLD".as_bytes()).unwrap();
+				connection
+			});
+
+			let mut client = server.next_client().unwrap().unwrap();
+			let mut sink = Vec::new();
+			assert_eq!(11, client.read_body_into(&mut sink).unwrap());
+			assert_eq!(b"HELLO WORLD", sink.as_slice());
+			assert!(client.body_complete());
+			drop(uploader.join().unwrap());
+		}
+	}
+
+	#[test]
+	fn client_local_addr() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(addr, client.local_addr());
+		assert_ne!(addr, client.addr());
+	}
+
+	#[test]
+	fn max_header_count() {
+		let (mut server, addr) = server();
+		server.set_max_header_count(10);
+
+		for (count, expected) in [(10, "HTTP/1.0 200 OK\r\n"), (11, "HTTP/1.0 431 Request Header Fields Too Large\r\n")].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			let headers = (0..*count).map(|i| format!("X-{}: {}\r\n", i, i)).collect::<String>();
+			connection.write_all(format!("GET / HTTP/1.1\r\n{}\r\n", headers).as_bytes()).unwrap();
+
+			if let Some(mut client) = server.next_client().unwrap() {
+				assert_eq!(*count, client.headers_raw().len());
+				client.respond_ok(&[]).unwrap();
+			}
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert!(buf.starts_with(expected), "{}", buf);
+		}
+	}
+
+	#[test]
+	fn harness_round_trip() {
+		use crate::testing::{get, post, TestServer};
+
+		let server = TestServer::start(|mut client| {
+			let text = format!("{} {} {}", client.method().unwrap_or("-"),
+				client.request().as_deref().unwrap_or("-"), String::from_utf8_lossy(client.body()));
+			client.respond_text("200 OK", &text).map(|_| ())
+		});
+
+		let response = get(&server.url("/hello")).unwrap();
+		assert_eq!(200, response.status);
+		assert_eq!(Some("text/plain; charset=utf-8"), response.header("content-type"));
+		assert_eq!("GET /hello ", response.text());
+
+		let response = post(&server.url("/upload"), b"DATA").unwrap();
+		assert_eq!("POST /upload DATA", response.text());
+	}
+
+	#[test]
+	fn harness_settings() {
+		use crate::testing::{get, post, TestServer};
+
+		let server = TestServer::start_with(
+			|server| server.set_allowed_methods(Some(&["POST"])),
+			|mut client| client.respond_ok(&[]).map(|_| ()));
+
+		assert_eq!(200, post(&server.url("/"), b"1234").unwrap().status);
+		let response = get(&server.url("/")).unwrap();
+		assert_eq!(405, response.status);
+		assert_eq!(Some("POST"), response.header("Allow"));
+	}
+
+	#[test]
+	fn content_length_mismatch() {
+		for strict in [false, true].iter() {
+			let (mut server, addr) = server();
+			server.set_strict_content_length(*strict);
+
+			// Too little data
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+			let err = server.next_client().unwrap().unwrap()
+				.respond_chunked("200 OK", "ABC".as_bytes(), 5, &vec!()).unwrap_err();
+			assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nABC", buf);
+
+			// Too much data
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+			let result = server.next_client().unwrap().unwrap()
+				.respond_chunked("200 OK", "ABCDEFG".as_bytes(), 5, &vec!());
+			match strict {
+				true => assert_eq!(io::ErrorKind::InvalidData, result.unwrap_err().kind()),
+				false => assert_eq!(43, result.unwrap())
+			}
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nABCDE", buf);
+		}
+	}
+
+	#[test]
+	fn pre_body_handler() {
+		use crate::BodyDecision;
+
+		let (mut server, addr) = server();
+		server.set_pre_body_handler(|client| match (client.request().as_deref(), client.header("Authorization")) {
+			(Some("/old"), _) => BodyDecision::Redirect("/new".to_string()),
+			(_, Some("secret")) => BodyDecision::Accept,
+			_ => BodyDecision::Reject(401)
+		});
+
+		let rejections = [
+			("/upload", "", "HTTP/1.0 401 Unauthorized\r\nContent-Length: 0\r\n\r\n"),
+			("/old", "Authorization: secret\r\n", "HTTP/1.0 307 Temporary Redirect\r\nContent-Length: 0\r\nLocation: /new\r\n\r\n"),
+		];
+		for (target, auth, expected) in rejections.iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("PUT {} HTTP/1.1\r\n{}Content-Length: 4\r\nExpect: 100-continue\r\n\r\n", target, auth).as_bytes()).unwrap();
+			assert!(server.next_client().unwrap().is_none());
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(*expected, buf);
+		}
+
+		// Accepted uploads get "100 Continue" and are read as usual.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("PUT /upload HTTP/1.1\r\nAuthorization: secret\r\nContent-Length: 4\r\nExpect: 100-continue\r\n\r\n".as_bytes()).unwrap();
+		let uploader = thread::spawn(move || {
+			let mut buf = [0u8; 25];
+			connection.read_exact(&mut buf).unwrap();
+			assert_eq!(b"HTTP/1.1 100 Continue\r\n\r\n", &buf);
+			connection.write_all(b"DATA").unwrap();
+		});
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(b"DATA", client.body());
+		uploader.join().unwrap();
+
+		// Requests without a body don't go through the handler.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /upload HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		assert!(server.next_client().unwrap().is_some());
+	}
+
+	#[cfg(target_os = "linux")]
+	#[test]
+	fn bind_device() {
+		let (mut server, addr) = server();
+		assert!(server.set_bind_device("no-such-device0").is_err());
+
+		match server.set_bind_device("lo") {
+			Ok(()) => {
+				let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+				connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+				assert!(server.next_client().unwrap().is_some());
+			},
+			// Without CAP_NET_RAW, there is nothing more to test.
+			Err(e) => assert_eq!(io::ErrorKind::PermissionDenied, e.kind())
+		}
+	}
+
+	#[test]
+	fn response_middleware() {
+		use crate::testing::{get, TestServer};
+
+		let server = TestServer::start_with(
+			|server| {
+				server.set_allowed_methods(Some(&["GET"]));
+				server.add_response_middleware(|_, response| response.set_header("X-Content-Type-Options", "nosniff"));
+				server.add_response_middleware(|client, response| {
+					if client.request().as_deref() == Some("/hidden") {
+						response.set_status("404 Not Found");
+						response.remove_header("X-Secret");
+					}
+				});
+			},
+			|mut client| client.respond("200 OK", b"DATA", &vec!("X-Secret: 1".to_string())).map(|_| ()));
+
+		let response = get(&server.url("/")).unwrap();
+		assert_eq!(200, response.status);
+		assert_eq!(Some("1"), response.header("X-Secret"));
+		assert_eq!(Some("nosniff"), response.header("X-Content-Type-Options"));
+
+		let response = get(&server.url("/hidden")).unwrap();
+		assert_eq!(404, response.status);
+		assert_eq!(None, response.header("X-Secret"));
+		assert_eq!("DATA", response.text());
+
+		// Responses the server sends on its own go through the middleware as well.
+		let response = crate::testing::request("DELETE", &server.url("/"), &[], &[]).unwrap();
+		assert_eq!(405, response.status);
+		assert_eq!(Some("nosniff"), response.header("X-Content-Type-Options"));
+	}
+
+	#[test]
+	fn response_middleware_drops_body() {
+		let file = TempDir::new("middleware_drops_body");
+		std::fs::write(file.join("data.txt"), "file data").unwrap();
+
+		let (mut server, addr) = server();
+		server.add_response_middleware(|_, response| response.set_status("304 Not Modified"));
+
+		for request in &["GET /data\r\n\r\n", "GET /file\r\n\r\n"] {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+
+			let mut client = server.next_client().unwrap().unwrap();
+			match client.request().as_deref() {
+				Some("/data") => client.respond("200 OK", b"hello", &vec!()).unwrap(),
+				_ => client.respond_file_handle(std::fs::File::open(file.join("data.txt")).unwrap()).unwrap()
+			};
+			drop(client);
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 304 Not Modified\r\n\r\n", buf);
+		}
+	}
+
+	#[test]
+	fn respond_bytes_range() {
+		use crate::testing::{request, TestServer};
+
+		let server = TestServer::start(|mut client| {
+			let range = client.header("Range").map(String::from);
+			client.respond_bytes_range("200 OK", b"0123456789", range.as_deref()).map(|_| ())
+		});
+		let get_range = |range: &str| {
+			let header = format!("Range: {}", range);
+			let headers: &[&str] = match range.is_empty() {
+				true => &[],
+				false => &[&header]
+			};
+			request("GET", &server.url("/blob"), headers, &[]).unwrap()
+		};
+
+		let cases = [
+			("", 200, None, "0123456789"),
+			("bytes=2-4", 206, Some("bytes 2-4/10"), "234"),
+			("bytes=7-", 206, Some("bytes 7-9/10"), "789"),
+			("bytes=-2", 206, Some("bytes 8-9/10"), "89"),
+			("bytes=5-50", 206, Some("bytes 5-9/10"), "56789"),
+			("bytes=10-", 416, Some("bytes */10"), ""),
+			("bytes=0-1,5-6", 200, None, "0123456789"),
+		];
+		for (range, status, content_range, body) in cases.iter() {
+			let response = get_range(range);
+			assert_eq!(*status, response.status, "{}", range);
+			assert_eq!(*content_range, response.header("Content-Range"), "{}", range);
+			assert_eq!(Some("bytes"), response.header("Accept-Ranges"));
+			assert_eq!(Some(body.len().to_string().as_str()), response.header("Content-Length"));
+			assert_eq!(*body, response.text());
+		}
+	}
+
+	#[test]
+	fn respond_retry_after() {
+		use crate::testing::{get, TestServer};
+		use std::time::{Duration, UNIX_EPOCH};
+
+		let server = TestServer::start(|mut client| {
+			match client.request().as_deref() {
+				Some("/seconds") => client.respond_retry_after("429 Too Many Requests", 30),
+				_ => client.respond_retry_after(
+					"503 Service Unavailable",
+					UNIX_EPOCH + Duration::from_secs(784111777))
+			}.map(|_| ())
+		});
+
+		let response = get(&server.url("/seconds")).unwrap();
+		assert_eq!(429, response.status);
+		assert_eq!(Some("30"), response.header("Retry-After"));
+
+		let response = get(&server.url("/date")).unwrap();
+		assert_eq!(503, response.status);
+		assert_eq!(Some("Sun, 06 Nov 1994 08:49:37 GMT"), response.header("Retry-After"));
+	}
+
+	#[test]
+	fn respond_large_file_handle() {
+		let dir = TempDir::new("large_file");
+		let path = dir.join("large_file.bin");
+		let content = (0..3 * 1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+		std::fs::write(&path, &content).unwrap();
+
+		for filtered in [false, true].iter() {
+			let (server, addr) = server();
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+
+			let mut client = server.next_client().unwrap().unwrap();
+			if *filtered {
+				// Data passing a filter can't be sent by the kernel directly.
+				client.set_output_filter(|data, connection| connection.write_all(data));
+			}
+			let file = std::fs::File::open(&path).unwrap();
+			let sender = std::thread::spawn(move || client.respond_file_handle(file).unwrap());
+
+			let mut buf = Vec::new();
+			connection.read_to_end(&mut buf).unwrap();
+			let head = format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", content.len());
+			assert_eq!(head.len() + content.len(), sender.join().unwrap());
+			assert_eq!(head.as_bytes(), &buf[..head.len()]);
+			assert!(buf[head.len()..] == content[..]);
+		}
+	}
+
+	#[test]
+	fn on_connection_close() {
+		use crate::testing::{get, request, TestServer};
+		use std::sync::{mpsc, Mutex};
+
+		let (sender, receiver) = mpsc::channel();
+		let sender = Mutex::new(sender);
+		let server = TestServer::start_with(
+			move |server| {
+				server.set_allowed_methods(Some(&["GET"]));
+				server.set_on_connection_close(move |addr| sender.lock().unwrap().send(addr).unwrap());
+			},
+			|mut client| match client.request() {
+				Some(_) => client.respond_ok(b"OK").map(|_| ()),
+				None => Ok(())
+			});
+
+		// Once after a response sent by the handler ...
+		assert_eq!(200, get(&server.url("/")).unwrap().status);
+		assert!(receiver.recv_timeout(Duration::from_secs(5)).unwrap().ip().is_loopback());
+
+		// ... and once after the server answered on its own.
+		assert_eq!(405, request("DELETE", &server.url("/"), &[], &[]).unwrap().status);
+		assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+
+		// ... and once for a client which disconnects without a request.
+		drop(TcpStream::connect(server.addr()).unwrap());
+		assert!(receiver.recv_timeout(Duration::from_secs(5)).is_ok());
+		assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+	}
+
+	#[test]
+	fn respond_json_error() {
+		use crate::testing::{get, TestServer};
+
+		let server = TestServer::start(|mut client| {
+			client.respond_json_error("400 Bad Request", "Missing \"id\",\nsee docs").map(|_| ())
+		});
+
+		let response = get(&server.url("/")).unwrap();
+		assert_eq!(400, response.status);
+		assert_eq!(Some("application/json"), response.header("Content-Type"));
+		assert_eq!(r#"{"error":{"code":400,"message":"Missing \"id\",\nsee docs"}}"#, response.text());
+	}
+
+	#[test]
+	fn oversized_head() {
+		let requests = [
+			(format!("GET /?{}", "a".repeat(64 * 1024)), "HTTP/1.0 414 URI Too Long"),
+			(format!("GET / HTTP/1.1\r\nX-Big: {}", "a".repeat(64 * 1024)), "HTTP/1.0 431 Request Header Fields Too Large"),
+		];
+
+		for (request, status) in requests.iter() {
+			let (mut server, addr) = server();
+			// Let the request line through, so only the size of the head counts.
+			server.set_max_uri_length(usize::MAX);
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			// Exactly as much as the server reads, so closing doesn't reset the connection.
+			connection.write_all(&request.as_bytes()[..64 * 1024]).unwrap();
+			connection.shutdown(Shutdown::Write).unwrap();
+
+			assert!(server.next_client().unwrap().is_none());
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert!(buf.starts_with(&format!("{}\r\n", status)), "{}", buf);
+		}
+	}
+
+	#[test]
+	fn health_check_path() {
+		use crate::testing::{get, request, TestServer};
+
+		let server = TestServer::start_with(
+			|server| {
+				server.set_health_check_path(Some("/healthz"));
+				server.set_allowed_methods(Some(&["POST"]));
+			},
+			|mut client| client.respond_ok(b"handler").map(|_| ()));
+
+		for url in ["/healthz", "/healthz?from=lb"].iter() {
+			let response = get(&server.url(url)).unwrap();
+			assert_eq!(200, response.status);
+			assert_eq!(Some("0"), response.header("Content-Length"));
+			assert_eq!("", response.text());
+		}
+		assert_eq!(200, request("HEAD", &server.url("/healthz"), &[], &[]).unwrap().status);
+
+		// Anything else goes through the usual checks and on to the handler.
+		assert_eq!(405, get(&server.url("/healthz/")).unwrap().status);
+		assert_eq!("handler", request("POST", &server.url("/healthz"), &[], &[]).unwrap().text());
+	}
+
+	#[test]
+	fn save_multipart() {
+		use crate::testing::{request, TestServer};
+
+		let dir = TempDir::new("save_multipart");
+		let upload_dir = dir.to_path_buf();
+		let server = TestServer::start(move |mut client| {
+			match client.save_multipart(&upload_dir, 16, 1024) {
+				Ok(parts) => {
+					let names = parts.iter().map(|p| p.name()).collect::<Vec<&str>>().join(",");
+					client.respond_ok(names.as_bytes())
+				},
+				Err(_) => Ok(0)
+			}.map(|_| ())
+		});
+		let upload = |files: &[(&str, &str)]| {
+			let mut body = String::new();
+			for (name, content) in files {
+				body += &format!("--B\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}.txt\"\r\n\r\n{}\r\n", name, name, content);
+			}
+			body += "--B--\r\n";
+			request("POST", &server.url("/upload"), &["Content-Type: multipart/form-data; boundary=B"], body.as_bytes()).unwrap()
+		};
+
+		let response = upload(&[("small", "tiny"), ("other", "fits as well")]);
+		assert_eq!((200, "small,other"), (response.status, response.text().as_str()));
+		assert_eq!(2, std::fs::read_dir(&dir).unwrap().count());
+
+		// The oversized part is rejected, and no file of this upload is left behind.
+		let response = upload(&[("small", "tiny"), ("large", "far too large for the limit")]);
+		assert_eq!(413, response.status);
+		assert_eq!(2, std::fs::read_dir(&dir).unwrap().count());
+
+		let response = request("POST", &server.url("/upload"), &["Content-Type: text/plain"], b"").unwrap();
+		assert_eq!(415, response.status);
+	}
+
+	#[test]
+	fn http10_response_bytes() {
+		// Clients which don't ask for a persistent connection get exactly these
+		// bytes: in particular no Connection header, which some old clients choke on.
+		let requests = [
+			"GET /plain HTTP/1.0\r\n\r\n",
+			"GET /plain HTTP/1.0\r\nHost: localhost\r\nUser-Agent: old/1.0\r\n\r\n",
+			"GET /plain\r\n\r\n",
+		];
+
+		for request in requests.iter() {
+			let (server, addr) = server();
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			server.next_client().unwrap().unwrap().respond_text("200 OK", "Hello").unwrap();
+
+			let mut buf = Vec::new();
+			connection.read_to_end(&mut buf).unwrap();
+			assert_eq!(
+				&b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nHello"[..],
+				&buf[..],
+				"{}", request);
+		}
+	}
+
+	#[test]
+	fn abort_on_failing_reader() {
+		// Yields some data, then fails like a file on a broken disk.
+		struct Failing(usize);
+
+		impl io::Read for Failing {
+			fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+				match self.0 {
+					0 => Err(io::Error::other("disk on fire")),
+					_ => {
+						self.0 -= 1;
+						buf[..4].copy_from_slice(b"data");
+						Ok(4)
+					}
+				}
+			}
+		}
+
+		// With Content-Length, the client sees the connection close early,
+		// even while the server still holds on to the client.
+		let (mut server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		let mut client = server.next_client().unwrap().unwrap();
+		let error = client.respond_chunked("200 OK", Failing(2), 100, &vec!()).unwrap_err();
+		assert_eq!("disk on fire", error.to_string());
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 100\r\n\r\ndatadata", buf);
+		drop(client);
+
+		// Without framing, the connection is reset instead of closed.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /\r\n\r\n".as_bytes()).unwrap();
+		let mut client = server.next_client().unwrap().unwrap();
+		assert!(client.respond_unframed("200 OK", Failing(2), &vec!()).is_err());
+		drop(client);
+		assert!(connection.read_to_end(&mut Vec::new()).is_err());
+
+		// An aborted chunked response lacks the terminating chunk.
+		server.set_protocol_version(ProtocolVersion::Http11);
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+		let mut client = server.next_client().unwrap().unwrap();
+		let mut response = client.begin_chunked("200 OK", &[]).unwrap();
+		response.write_chunk(b"partial").unwrap();
+		response.abort();
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert!(buf.ends_with("\r\n\r\n7\r\npartial\r\n"), "{}", buf);
+		drop(client);
+	}
+
+	#[test]
+	fn new_ephemeral() {
+		for host in ["127.0.0.1", "localhost"].iter() {
+			let (server, addr) = MicroHTTP::new_ephemeral(host).unwrap();
+			assert_ne!(0, addr.port());
+			assert_eq!(addr, server.local_addr().unwrap());
+
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all("GET /ephemeral\r\n\r\n".as_bytes()).unwrap();
+			let client = server.next_client().unwrap().unwrap();
+			assert_eq!(Some("/ephemeral"), client.request().as_deref());
+		}
+	}
+
+	#[test]
+	fn parse_warnings() {
+		let (server, addr) = server();
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert!(client.warnings().is_empty());
+		drop(client);
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\nHost : localhost\r\nX-Folded: a\r\n b\r\n\r\n".as_bytes()).unwrap();
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(&[
+			ParseWarning::WhitespaceBeforeColon("Host".to_string()),
+			ParseWarning::ObsoleteLineFolding(" b".to_string())
+		], client.warnings());
+		assert_eq!(Some("localhost"), client.header("Host "));
+	}
+
+	#[test]
+	fn no_content() {
+		let (server, addr) = server();
+
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("DELETE /item HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+		server.next_client().unwrap().unwrap().respond_no_content().unwrap();
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 204 No Content\r\n\r\n", buf);
+
+		// The general respond methods know about bodiless statuses as well.
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /item HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+		let mut client = server.next_client().unwrap().unwrap();
+		let err = client.respond("204 No Content", b"data", &vec!()).unwrap_err();
+		assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+		client.respond("304 Not Modified", &[], &vec!("ETag: \"1\"".to_string())).unwrap();
+		drop(client);
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.0 304 Not Modified\r\nETag: \"1\"\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn router_fallback() {
+		let (server, addr) = server();
+		let mut router = Router::new();
+		router.add_method("GET", "/users", |client| client.respond_ok(b"users"));
+		router.set_fallback(|client| {
+			let body = format!("no route for {}", client.request().as_deref().unwrap_or(""));
+			client.respond("404 Not Found", body.as_bytes(), &vec!())
+		});
+
+		for (request, expected) in [
+			("GET /users", "HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nusers"),
+			("GET /other?x=1", "HTTP/1.0 404 Not Found\r\nContent-Length: 23\r\n\r\nno route for /other?x=1"),
+			("POST /users", "HTTP/1.0 405 Method Not Allowed\r\nContent-Length: 0\r\nAllow: GET, OPTIONS\r\n\r\n"),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("{} HTTP/1.1\r\nHost: localhost\r\n\r\n", request).as_bytes()).unwrap();
+			router.handle(&mut server.next_client().unwrap().unwrap()).unwrap();
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(*expected, buf);
+		}
+	}
+
+	#[test]
+	fn shutdown_after_response() {
+		for enabled in [false, true].iter() {
+			let (mut server, addr) = server();
+			server.set_shutdown_after_response(*enabled);
+
+			let reader = thread::spawn(move || {
+				let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+				connection.write_all("GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".as_bytes()).unwrap();
+				let mut buf = String::new();
+				connection.read_to_string(&mut buf).unwrap();
+				(Instant::now(), buf)
+			});
+
+			// Keep the Client around for a while after responding.
+			let mut client = server.next_client().unwrap().unwrap();
+			client.respond_ok(b"done").unwrap();
+			let responded = Instant::now();
+			thread::sleep(Duration::from_millis(300));
+			drop(client);
+
+			let (eof, buf) = reader.join().unwrap();
+			assert_eq!("HTTP/1.0 200 OK\r\nContent-Length: 4\r\n\r\ndone", buf);
+			match enabled {
+				true => assert!(eof.saturating_duration_since(responded) < Duration::from_millis(200)),
+				false => assert!(eof.saturating_duration_since(responded) >= Duration::from_millis(300))
+			}
+		}
+	}
+
+	#[cfg(feature = "flate2")]
+	#[test]
+	fn decompress_request_body() {
+		let text = "Decompress me. ".repeat(100);
+		let compressed = crate::compression::compress("gzip", text.as_bytes()).unwrap();
+		let upload = |addr, coding: &str, body: &[u8]| {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			let head = format!("POST /upload HTTP/1.1\r\nContent-Encoding: {}\r\nContent-Length: {}\r\n\r\n", coding, body.len());
+			connection.write_all(head.as_bytes()).unwrap();
+			connection.write_all(body).unwrap();
+			connection
+		};
+
+		let (mut server, addr) = server();
+		let _connection = upload(addr, "gzip", &compressed);
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(text.as_bytes(), client.body());
+		assert_eq!(&compressed[..], client.raw_body());
+		drop(client);
+
+		// Unknown codings are left alone.
+		let _connection = upload(addr, "compress", b"LZW");
+		let client = server.next_client().unwrap().unwrap();
+		assert_eq!(b"LZW", client.body());
+		assert_eq!(b"LZW", client.raw_body());
+		drop(client);
+
+		// The size limit applies to the decoded body.
+		server.set_max_request_size(Some(1000));
+		for (coding, body, expected) in [
+			("gzip", &compressed[..], "413 Payload Too Large"),
+			("deflate", &b"not deflate"[..], "400 Bad Request"),
+		].iter() {
+			let mut connection = upload(addr, coding, body);
+			assert!(server.next_client().unwrap().is_none());
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(format!("HTTP/1.0 {}\r\nContent-Length: 0\r\n\r\n", expected), buf);
+		}
+	}
+
+	#[test]
+	fn if_match() {
+		let (server, addr) = server();
+
+		for (if_match, current, passes) in [
+			(None, Some("v2"), true),
+			(Some("\"v1\", \"v2\""), Some("v2"), true),
+			(Some("\"v1\""), Some("v2"), false),
+			(Some("*"), Some("v2"), true),
+			(Some("*"), None, false),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			let header = if_match.map_or(String::new(), |v| format!("If-Match: {}\r\n", v));
+			connection.write_all(format!("PUT /doc HTTP/1.1\r\n{}\r\n", header).as_bytes()).unwrap();
+
+			let client = server.next_client().unwrap().unwrap();
+			assert_eq!(if_match.map(|v| v.split(", ").collect::<Vec<&str>>()), client.if_match());
+			assert_eq!(*passes, client.if_match_passes(*current), "{:?} {:?}", if_match, current);
+		}
+	}
+
+	#[test]
+	fn protocol_version() {
+		for (version, request, expected) in [
+			(ProtocolVersion::Http10, "GET / HTTP/1.1\r\n\r\n", "HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok"),
+			(ProtocolVersion::Http11, "GET / HTTP/1.1\r\n\r\n", "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"),
+			(ProtocolVersion::Http11, "GET / HTTP/1.0\r\n\r\n", "HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok"),
+			(ProtocolVersion::Http11, "GET /\r\n\r\n", "HTTP/1.0 200 OK\r\nContent-Length: 2\r\n\r\nok"),
+		].iter() {
+			let (mut server, addr) = server();
+			server.set_protocol_version(*version);
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+			server.next_client().unwrap().unwrap().respond_ok(b"ok").unwrap();
+
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!(*expected, buf, "{:?}", version);
+		}
+
+		// A Connection header set by the application is kept.
+		let (mut server, addr) = server();
+		server.set_protocol_version(ProtocolVersion::Http11);
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET / HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+		let mut client = server.next_client().unwrap().unwrap();
+		client.respond("204 No Content", &[], &vec!("connection: close".to_string())).unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!("HTTP/1.1 204 No Content\r\nconnection: close\r\n\r\n", buf);
+	}
+
+	#[test]
+	fn bearer_token() {
+		let (server, addr) = server();
+
+		for (header, expected) in [
+			("", None),
+			("Authorization: Bearer abc.DEF-123_~+/==\r\n", Some("abc.DEF-123_~+/==")),
+			("Authorization: bearer  token \r\n", Some("token")),
+			("Authorization: Basic dXNlcjpwYXNz\r\n", None),
+			("Authorization: Bearer\r\n", None),
+			("Authorization: Bearer two words\r\n", None),
+			("Authorization: Bearer a=b\r\n", None),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("GET /api HTTP/1.1\r\n{}\r\n", header).as_bytes()).unwrap();
+			let client = server.next_client().unwrap().unwrap();
+			assert_eq!(*expected, client.bearer_token(), "{:?}", header);
+		}
+	}
+
+	#[test]
+	fn cookies() {
+		let (server, addr) = server();
+		let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+		connection.write_all("GET /login HTTP/1.1\r\n\r\n".as_bytes()).unwrap();
+
+		let mut client = server.next_client().unwrap().unwrap();
+		client.add_cookie("session=38afes7a8; HttpOnly; Path=/");
+		client.add_cookie("theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT");
+		client.add_cookie("lang=en");
+		client.respond("200 OK", b"hi", &vec!("Content-Type: text/plain".to_string())).unwrap();
+		drop(client);
+
+		let mut buf = String::new();
+		connection.read_to_string(&mut buf).unwrap();
+		assert_eq!(concat!(
+			"HTTP/1.0 200 OK\r\nContent-Length: 2\r\nContent-Type: text/plain\r\n",
+			"Set-Cookie: session=38afes7a8; HttpOnly; Path=/\r\n",
+			"Set-Cookie: theme=dark; Expires=Wed, 21 Oct 2026 07:28:00 GMT\r\n",
+			"Set-Cookie: lang=en\r\n\r\nhi"), buf);
+	}
+
+	#[test]
+	fn content_type() {
+		let (server, addr) = server();
+
+		for (header, mime, charset, boundary) in [
+			("", None, None, None),
+			("Content-Type: text/html; charset=utf-8\r\n", Some("text/html"), Some("utf-8"), None),
+			("Content-Type: Multipart/Form-Data; Boundary=\"x; y\"\r\n", Some("multipart/form-data"), None, Some("x; y")),
+			("Content-Type: nonsense\r\n", None, None, None),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(format!("POST /upload HTTP/1.1\r\n{}\r\n", header).as_bytes()).unwrap();
+			let client = server.next_client().unwrap().unwrap();
+			let content_type = client.content_type();
+			assert_eq!(*mime, content_type.as_ref().map(|c| c.mime()), "{:?}", header);
+			assert_eq!(*charset, content_type.as_ref().and_then(|c| c.charset()), "{:?}", header);
+			assert_eq!(*boundary, content_type.as_ref().and_then(|c| c.boundary()), "{:?}", header);
+		}
+	}
+
+	#[test]
+	fn request_timeout() {
+		let (mut server, addr) = server();
+		server.set_request_timeout(Some(Duration::from_millis(300)));
+
+		// A client which never finishes its head, one which stops sending its
+		// body and one which sends everything in time.
+		for (request, complete) in [
+			("GET /slow HTTP/1.1\r\nHost: localhost\r\n", false),
+			("POST /slow HTTP/1.1\r\nContent-Length: 10\r\n\r\nabc", false),
+			("POST /fast HTTP/1.1\r\nContent-Length: 3\r\n\r\nabc", true),
+		].iter() {
+			let mut connection = TcpStream::connect(addr).expect("Could not reach server");
+			connection.write_all(request.as_bytes()).unwrap();
+
+			let start = Instant::now();
+			let client = server.next_client().unwrap();
+			assert!(start.elapsed() < Duration::from_secs(5));
+			if *complete {
+				let mut client = client.unwrap();
+				assert_eq!(b"abc", client.body());
+				client.respond_ok(&[]).unwrap();
+				continue;
+			}
+
+			assert!(client.is_none());
+			let mut buf = String::new();
+			connection.read_to_string(&mut buf).unwrap();
+			assert_eq!("HTTP/1.0 408 Request Timeout\r\nContent-Length: 0\r\n\r\n", buf, "{:?}", request);
+		}
+	}
 }
@@ -0,0 +1,350 @@
+// Streaming parser for multipart/form-data request bodies (RFC 7578), which
+// saves every part to a file of its own, see Client::save_multipart.
+
+use std::{
+	fs::{self, File},
+	io::{self, Read, Write},
+	path::{Path, PathBuf},
+	process,
+	sync::atomic::{AtomicUsize, Ordering},
+	time::{SystemTime, UNIX_EPOCH}
+};
+
+use crate::content_type::{header_params, ContentType};
+
+// Give up looking for the end of the headers of a part after this many bytes.
+const MAX_PART_HEADER_SIZE: usize = 16 * 1024;
+
+// Number of files created so far, to keep their names unique.
+static FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// A part of a ``multipart/form-data`` upload, saved to a file of its own by
+/// ``Client::save_multipart``.
+///
+/// The file belongs to the caller: move it to its final place or delete it
+/// once it has been processed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UploadedPart {
+	name: String,
+	filename: Option<String>,
+	content_type: Option<String>,
+	path: PathBuf,
+	size: u64,
+}
+
+impl UploadedPart {
+	/// Return the name of the form field this part belongs to.
+	pub fn name(&self) -> &str {
+		&self.name
+	}
+
+	/// Return the file name the client sent along with the part, if any.
+	///
+	/// This is whatever the client claims, so never use it as a path as it is;
+	/// it may e.g. contain ``../``.
+	pub fn filename(&self) -> Option<&str> {
+		self.filename.as_deref()
+	}
+
+	/// Return the ``Content-Type`` of the part, if the client sent one.
+	pub fn content_type(&self) -> Option<&str> {
+		self.content_type.as_deref()
+	}
+
+	/// Return the path of the file the content of the part was saved to.
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	/// Return the size of the content of the part in bytes.
+	pub fn size(&self) -> u64 {
+		self.size
+	}
+}
+
+// Reasons why saving the parts failed.
+#[derive(Debug)]
+pub(crate) enum MultipartError {
+	// A part or the whole upload exceeds its size limit
+	TooLarge(String),
+	// The body isn't valid multipart/form-data
+	Malformed(String),
+	// Reading the body or writing a file failed
+	Io(io::Error),
+}
+
+impl From<io::Error> for MultipartError {
+	fn from(e: io::Error) -> MultipartError {
+		MultipartError::Io(e)
+	}
+}
+
+// Return the boundary from a Content-Type like "multipart/form-data; boundary=abc",
+// or None if it's a different type or the boundary is missing or invalid.
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+	let content_type = ContentType::parse(content_type)?;
+	if content_type.mime() != "multipart/form-data" {
+		return None;
+	}
+
+	// RFC 2046 limits boundaries to 70 characters.
+	content_type.boundary().filter(|b| !b.is_empty() && b.len() <= 70).map(String::from)
+}
+
+// Return the value of the named parameter from a list like `name="a"; filename="b.txt"`.
+fn header_param(params: &str, name: &str) -> Option<String> {
+	header_params(params).into_iter()
+		.find(|(key, _)| key.eq_ignore_ascii_case(name))
+		.map(|(_, value)| value)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+	haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+// Append the next chunk of the body to buf; returns false at the end of the body.
+fn fill(reader: &mut impl Read, buf: &mut Vec<u8>) -> io::Result<bool> {
+	let mut chunk = [0u8; 8192];
+	let bytes_read = reader.read(&mut chunk)?;
+	buf.extend_from_slice(&chunk[..bytes_read]);
+	Ok(bytes_read > 0)
+}
+
+// Create a new file with a name of its own in dir.
+fn new_file(dir: &Path) -> io::Result<(PathBuf, File)> {
+	let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.subsec_nanos());
+	let path = dir.join(format!(
+		"upload-{}-{}-{}",
+		process::id(),
+		nanos,
+		FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+	let file = File::options().write(true).create_new(true).open(&path)?;
+	Ok((path, file))
+}
+
+// Save every part of the multipart body read from reader to a new file in dir.
+// If anything goes wrong, all files created so far are deleted again.
+pub(crate) fn save_parts(
+	reader: impl Read,
+	boundary: &str,
+	dir: &Path,
+	max_part_size: u64,
+	max_total_size: u64) -> Result<Vec<UploadedPart>, MultipartError>
+{
+	let mut parts = Vec::new();
+	let result = read_parts(reader, boundary, dir, max_part_size, max_total_size, &mut parts);
+
+	if result.is_err() {
+		for part in &parts {
+			if let Err(e) = fs::remove_file(&part.path) {
+				warn!("Could not delete the partial upload {}: {}", part.path.display(), e);
+			}
+		}
+	}
+	result.map(|_| parts)
+}
+
+fn read_parts(
+	mut reader: impl Read,
+	boundary: &str,
+	dir: &Path,
+	max_part_size: u64,
+	max_total_size: u64,
+	parts: &mut Vec<UploadedPart>) -> Result<(), MultipartError>
+{
+	let malformed = |message: &str| Err(MultipartError::Malformed(message.to_string()));
+
+	// Every boundary is preceded by a line break, except for the first one if
+	// there is no preamble; pretend there is one.
+	let delimiter = format!("\r\n--{}", boundary).into_bytes();
+	let mut buf = b"\r\n".to_vec();
+	let mut total_size = 0;
+
+	// Skip the preamble.
+	loop {
+		if let Some(pos) = find(&buf, &delimiter) {
+			buf.drain(..pos + delimiter.len());
+			break;
+		}
+		buf.drain(..buf.len().saturating_sub(delimiter.len() - 1));
+		if !fill(&mut reader, &mut buf)? {
+			return malformed("No boundary found");
+		}
+	}
+
+	loop {
+		// After a boundary, "--" ends the body; anything after it is ignored.
+		// Otherwise, the boundary line ends, possibly after some whitespace.
+		while buf.len() < 2 {
+			if !fill(&mut reader, &mut buf)? {
+				return malformed("Body ends after a boundary");
+			}
+		}
+		if buf.starts_with(b"--") {
+			return Ok(());
+		}
+		let line_end = loop {
+			if let Some(pos) = find(&buf, b"\r\n") {
+				break pos;
+			}
+			if buf.len() > MAX_PART_HEADER_SIZE || !fill(&mut reader, &mut buf)? {
+				return malformed("Invalid boundary line");
+			}
+		};
+		if !buf[..line_end].iter().all(|&c| c == b' ' || c == b'\t') {
+			return malformed("Invalid boundary line");
+		}
+		buf.drain(..line_end + 2);
+
+		// The headers of the part, which may be missing altogether.
+		let header_end = loop {
+			if buf.starts_with(b"\r\n") {
+				break 2;
+			}
+			if let Some(pos) = find(&buf, b"\r\n\r\n") {
+				break pos + 4;
+			}
+			if buf.len() > MAX_PART_HEADER_SIZE {
+				return malformed("Headers of a part are too large");
+			}
+			if !fill(&mut reader, &mut buf)? {
+				return malformed("Body ends within the headers of a part");
+			}
+		};
+		let headers = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+		buf.drain(..header_end);
+
+		let mut disposition = None;
+		let mut content_type = None;
+		for (name, value) in headers.split("\r\n").filter_map(|line| line.split_once(':')) {
+			if name.trim().eq_ignore_ascii_case("Content-Disposition") {
+				disposition = Some(value.trim().to_string());
+			} else if name.trim().eq_ignore_ascii_case("Content-Type") {
+				content_type = Some(value.trim().to_string());
+			}
+		}
+		let params = match disposition.as_deref().and_then(|d| d.split_once(';')) {
+			Some((kind, params)) if kind.trim().eq_ignore_ascii_case("form-data") => params,
+			_ => return malformed("Part without Content-Disposition: form-data")
+		};
+		let name = match header_param(params, "name") {
+			Some(name) => name,
+			None => return malformed("Part without a name")
+		};
+
+		let (path, mut file) = new_file(dir)?;
+		parts.push(UploadedPart {
+			filename: header_param(params, "filename"),
+			name,
+			content_type,
+			path,
+			size: 0,
+		});
+		let part = parts.last_mut().expect("Part was just added");
+
+		// The content of the part, up to the next boundary. The end of the buffer
+		// may be the start of the boundary, so it is kept until more data arrives.
+		loop {
+			let found = find(&buf, &delimiter);
+			let end = found.unwrap_or_else(|| buf.len().saturating_sub(delimiter.len() - 1));
+
+			part.size += end as u64;
+			total_size += end as u64;
+			if part.size > max_part_size {
+				return Err(MultipartError::TooLarge(format!(
+					"Part {:?} is larger than {} bytes", part.name, max_part_size)));
+			}
+			if total_size > max_total_size {
+				return Err(MultipartError::TooLarge(format!(
+					"Upload is larger than {} bytes", max_total_size)));
+			}
+
+			file.write_all(&buf[..end])?;
+			buf.drain(..end);
+
+			if found.is_some() {
+				buf.drain(..delimiter.len());
+				break;
+			}
+			if !fill(&mut reader, &mut buf)? {
+				return malformed("Body ends within a part");
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{boundary, header_param, save_parts, MultipartError};
+	use crate::testing::TempDir;
+	use std::{fs, io, io::Read};
+
+	// Reader which returns at most a few bytes at once, like a slow connection.
+	struct Trickle<'a>(&'a [u8]);
+
+	impl Read for Trickle<'_> {
+		fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+			let len = buf.len().min(self.0.len()).min(3);
+			buf[..len].copy_from_slice(&self.0[..len]);
+			self.0 = &self.0[len..];
+			Ok(len)
+		}
+	}
+
+	#[test]
+	fn params() {
+		assert_eq!(Some("abc".to_string()), boundary("multipart/form-data; boundary=abc"));
+		assert_eq!(Some("a b;c".to_string()), boundary("Multipart/Form-Data; charset=utf-8; BOUNDARY=\"a b;c\""));
+		assert_eq!(None, boundary("multipart/mixed; boundary=abc"));
+		assert_eq!(None, boundary("multipart/form-data"));
+		assert_eq!(None, boundary("multipart/form-data; boundary="));
+
+		let params = " name=\"upload\"; filename=\"my \\\"cat\\\"; photo.jpg\"; flag; size=12";
+		assert_eq!(Some("upload".to_string()), header_param(params, "name"));
+		assert_eq!(Some("my \"cat\"; photo.jpg".to_string()), header_param(params, "filename"));
+		assert_eq!(Some("12".to_string()), header_param(params, "size"));
+		assert_eq!(None, header_param(params, "flag"));
+		assert_eq!(None, header_param("name=\"unterminated", "name"));
+	}
+
+	#[test]
+	fn parts() {
+		let dir = TempDir::new("multipart_parts");
+
+		let body = b"preamble\r\n--XyZ\r\n\
+			Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+			Cats\r\n--XyZ  \r\n\
+			Content-Disposition: form-data; name=\"file\"; filename=\"cat.txt\"\r\n\
+			Content-Type: text/plain\r\n\r\n\
+			Meow.\r\n--X\r\nstill meow\r\n--XyZ--\r\nepilogue";
+
+		let parts = save_parts(Trickle(body), "XyZ", &dir, 100, 100).unwrap();
+		assert_eq!(2, parts.len());
+		assert_eq!(("title", None, None, 4), (parts[0].name(), parts[0].filename(), parts[0].content_type(), parts[0].size()));
+		assert_eq!(b"Cats", &fs::read(parts[0].path()).unwrap()[..]);
+		assert_eq!(("file", Some("cat.txt"), Some("text/plain")), (parts[1].name(), parts[1].filename(), parts[1].content_type()));
+		assert_eq!(b"Meow.\r\n--X\r\nstill meow", &fs::read(parts[1].path()).unwrap()[..]);
+		assert!(parts[0].path().starts_with(&dir));
+	}
+
+	#[test]
+	fn limits_and_errors() {
+		let dir = TempDir::new("multipart_errors");
+
+		let body = "--b\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\n0123456789\r\n\
+			--b\r\nContent-Disposition: form-data; name=\"b\"\r\n\r\n01234\r\n--b--";
+		assert_eq!(2, save_parts(body.as_bytes(), "b", &dir, 10, 15).unwrap().len());
+
+		match save_parts(body.as_bytes(), "b", &dir, 9, 100) {
+			Err(MultipartError::TooLarge(message)) => assert_eq!("Part \"a\" is larger than 9 bytes", message),
+			result => panic!("Unexpected result: {:?}", result)
+		}
+		assert!(matches!(save_parts(body.as_bytes(), "b", &dir, 10, 14), Err(MultipartError::TooLarge(_))));
+		assert!(matches!(save_parts(&body.as_bytes()[..60], "b", &dir, 10, 100), Err(MultipartError::Malformed(_))));
+		assert!(matches!(save_parts(body.as_bytes(), "other", &dir, 10, 100), Err(MultipartError::Malformed(_))));
+		assert!(matches!(save_parts(&b"--b\r\n\r\nno name\r\n--b--"[..], "b", &dir, 10, 100), Err(MultipartError::Malformed(_))));
+
+		// Nothing is left behind after a failure; only the files of the first upload remain.
+		assert_eq!(2, fs::read_dir(&dir).unwrap().count());
+	}
+}
@@ -0,0 +1,45 @@
+// The path all response data takes to the connection, optionally through
+// a filter set with Client::set_output_filter.
+
+use std::{
+	fmt,
+	io,
+	io::Write,
+	net::TcpStream
+};
+
+type Filter = Box<dyn FnMut(&[u8], &mut dyn Write) -> io::Result<()> + Send>;
+
+// A user-supplied function which gets every piece of response data along
+// with the connection, and writes the (possibly transformed) data to it.
+pub(crate) struct OutputFilter(pub(crate) Filter);
+
+impl fmt::Debug for OutputFilter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("OutputFilter")
+	}
+}
+
+// Writer for response data: passes everything through the filter, if any,
+// or straight to the connection otherwise.
+#[derive(Debug)]
+pub(crate) struct Output<'a> {
+	pub(crate) stream: &'a mut TcpStream,
+	pub(crate) filter: Option<&'a mut OutputFilter>,
+}
+
+impl Write for Output<'_> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		match self.filter {
+			Some(ref mut filter) => {
+				(filter.0)(buf, self.stream)?;
+				Ok(buf.len())
+			},
+			None => self.stream.write(buf)
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		self.stream.flush()
+	}
+}
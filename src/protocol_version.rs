@@ -0,0 +1,20 @@
+/// The protocol version of responses, set with ``MicroHTTP::set_protocol_version``.
+///
+/// In either mode, each connection carries a single request and is closed after
+/// the response; persistent connections (keep-alive) are not supported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ProtocolVersion {
+	/// Send all responses as ``HTTP/1.0`` (the default). The end of a response is
+	/// signalled by closing the connection, which HTTP/1.0 clients expect anyway,
+	/// so no ``Connection`` header is sent. Chunked transfer coding is never used,
+	/// so responses started with ``Client::begin_chunked`` end when the connection
+	/// is closed.
+	#[default]
+	Http10,
+	/// Answer HTTP/1.1 clients with ``HTTP/1.1`` responses, along with
+	/// ``Connection: close`` since HTTP/1.1 clients would expect the connection
+	/// to stay open otherwise. Older clients still get ``HTTP/1.0`` responses.
+	/// Responses started with ``Client::begin_chunked`` use chunked transfer coding
+	/// for HTTP/1.1 clients.
+	Http11,
+}
@@ -0,0 +1,74 @@
+// Parsing of Range request headers (RFC 7233) for partial responses.
+
+// The part of a representation of a given length which a Range header asks for.
+#[derive(Debug, PartialEq)]
+pub(crate) enum ByteRange {
+	// No (usable) range was requested, the whole representation is sent.
+	Full,
+	// The bytes from start to end, both inclusive.
+	Partial(u64, u64),
+	// The range lies completely outside the representation.
+	Unsatisfiable,
+}
+
+// Determine the requested range from a header like "bytes=0-499", "bytes=500-"
+// or "bytes=-500" (the last 500 bytes). Only a single range is supported;
+// headers with several ranges, other units or invalid syntax are ignored,
+// as the RFC allows, and the whole representation is sent.
+pub(crate) fn parse_range(header: Option<&str>, len: u64) -> ByteRange {
+	let spec = match header.and_then(|h| h.trim().strip_prefix("bytes=")) {
+		Some(spec) if !spec.contains(',') => spec.trim(),
+		_ => return ByteRange::Full
+	};
+	let (first, last) = match spec.split_once('-') {
+		Some((first, last)) => (first.trim(), last.trim()),
+		None => return ByteRange::Full
+	};
+
+	match (first.parse::<u64>(), last.parse::<u64>()) {
+		// bytes=a-b
+		(Ok(start), Ok(end)) if start <= end => match start < len {
+			true => ByteRange::Partial(start, end.min(len - 1)),
+			false => ByteRange::Unsatisfiable
+		},
+		// bytes=a-
+		(Ok(start), Err(_)) if last.is_empty() => match start < len {
+			true => ByteRange::Partial(start, len - 1),
+			false => ByteRange::Unsatisfiable
+		},
+		// bytes=-n
+		(Err(_), Ok(suffix)) if first.is_empty() => match suffix > 0 && len > 0 {
+			true => ByteRange::Partial(len.saturating_sub(suffix), len - 1),
+			false => ByteRange::Unsatisfiable
+		},
+		_ => ByteRange::Full
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_range, ByteRange};
+
+	#[test]
+	fn ranges() {
+		assert_eq!(ByteRange::Full, parse_range(None, 10));
+		assert_eq!(ByteRange::Partial(0, 4), parse_range(Some("bytes=0-4"), 10));
+		assert_eq!(ByteRange::Partial(5, 9), parse_range(Some("bytes=5-100"), 10));
+		assert_eq!(ByteRange::Partial(3, 9), parse_range(Some("bytes=3-"), 10));
+		assert_eq!(ByteRange::Partial(7, 9), parse_range(Some("bytes=-3"), 10));
+		assert_eq!(ByteRange::Partial(0, 9), parse_range(Some("bytes=-30"), 10));
+		assert_eq!(ByteRange::Partial(9, 9), parse_range(Some(" bytes= 9 - 9 "), 10));
+
+		assert_eq!(ByteRange::Unsatisfiable, parse_range(Some("bytes=10-"), 10));
+		assert_eq!(ByteRange::Unsatisfiable, parse_range(Some("bytes=10-20"), 10));
+		assert_eq!(ByteRange::Unsatisfiable, parse_range(Some("bytes=-0"), 10));
+		assert_eq!(ByteRange::Unsatisfiable, parse_range(Some("bytes=-5"), 0));
+
+		assert_eq!(ByteRange::Full, parse_range(Some("bytes=5-2"), 10));
+		assert_eq!(ByteRange::Full, parse_range(Some("bytes=0-1,4-5"), 10));
+		assert_eq!(ByteRange::Full, parse_range(Some("items=0-1"), 10));
+		assert_eq!(ByteRange::Full, parse_range(Some("bytes=x-1"), 10));
+		assert_eq!(ByteRange::Full, parse_range(Some("bytes=-"), 10));
+		assert_eq!(ByteRange::Full, parse_range(Some("bytes=5"), 10));
+	}
+}
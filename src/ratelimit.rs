@@ -0,0 +1,96 @@
+// Throttling of clients which open too many connections in a short time.
+
+use std::{
+	collections::{HashMap, VecDeque},
+	net::IpAddr,
+	time::{Duration, Instant}
+};
+
+// Allows at most max connections per IP address within a sliding window.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+	max: usize,
+	pub(crate) window: Duration,
+
+	// Points in time of the recent connections of each address, oldest first
+	connections: HashMap<IpAddr, VecDeque<Instant>>,
+
+	// Entries of addresses which stopped connecting are removed now and then
+	last_sweep: Instant,
+}
+
+impl RateLimiter {
+	pub(crate) fn new(max: usize, window: Duration) -> RateLimiter {
+		RateLimiter {
+			max,
+			window,
+			connections: HashMap::new(),
+			last_sweep: Instant::now(),
+		}
+	}
+
+	// Record a connection from ip at the given time and return whether it is
+	// within the limit. Rejected connections are not recorded, so a client which
+	// keeps trying is let in again as soon as its older connections expire.
+	pub(crate) fn allow(&mut self, ip: IpAddr, now: Instant) -> bool {
+		let window = self.window;
+		let expired = |t: &Instant| now.saturating_duration_since(*t) >= window;
+
+		// Keep memory bounded by dropping addresses without recent connections.
+		if now.saturating_duration_since(self.last_sweep) >= window {
+			self.connections.retain(|_, times| !times.back().is_some_and(expired));
+			self.last_sweep = now;
+		}
+
+		let times = self.connections.entry(ip).or_default();
+		while times.front().is_some_and(expired) {
+			times.pop_front();
+		}
+
+		match times.len() < self.max {
+			true => {
+				times.push_back(now);
+				true
+			},
+			false => false
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::RateLimiter;
+	use std::time::{Duration, Instant};
+
+	#[test]
+	fn sliding_window() {
+		let mut limiter = RateLimiter::new(2, Duration::from_secs(10));
+		let a = "192.168.0.1".parse().unwrap();
+		let b = "::1".parse().unwrap();
+		let start = Instant::now();
+		let at = |secs| start + Duration::from_secs(secs);
+
+		assert!(limiter.allow(a, at(0)));
+		assert!(limiter.allow(a, at(5)));
+		assert!(!limiter.allow(a, at(6)));
+		assert!(limiter.allow(b, at(6)));
+
+		// The first connection expired, the second one didn't.
+		assert!(limiter.allow(a, at(10)));
+		assert!(!limiter.allow(a, at(11)));
+		assert!(limiter.allow(a, at(15)));
+	}
+
+	#[test]
+	fn sweep() {
+		let mut limiter = RateLimiter::new(1, Duration::from_secs(10));
+		let start = Instant::now();
+		for i in 0..100u8 {
+			assert!(limiter.allow([10, 0, 0, i].into(), start));
+		}
+		assert_eq!(100, limiter.connections.len());
+
+		assert!(limiter.allow([10, 0, 1, 0].into(), start + Duration::from_secs(20)));
+		assert_eq!(1, limiter.connections.len());
+	}
+}
@@ -0,0 +1,609 @@
+// Parsing of the request head, i.e. the request line and the header section.
+//
+// This works on the bytes received so far, no matter where they came from,
+// so the synchronous and the asynchronous server share it.
+
+use std::{fmt, str};
+
+use crate::request_url::split_fragment;
+
+// Limits which a request head must stay within.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct ParseLimits {
+	// Maximum length of the request target in bytes
+	pub(crate) max_target_len: usize,
+	// Maximum number of header fields
+	pub(crate) max_header_count: usize,
+	// Size at which an unterminated head is considered too large, rather than
+	// incomplete; reading stops there
+	pub(crate) max_head_len: usize,
+}
+
+impl ParseLimits {
+	// No limits at all.
+	#[cfg(test)]
+	pub(crate) fn unlimited() -> ParseLimits {
+		ParseLimits { max_target_len: usize::MAX, max_header_count: usize::MAX, max_head_len: usize::MAX }
+	}
+}
+
+// The parsed request head. Parts which are missing or invalid are None; such
+// requests are still passed on, so the application can answer them.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct RequestHead {
+	// Method from the request line, e.g. "GET"
+	pub(crate) method: Option<String>,
+	// The whole request line, with invalid UTF-8 replaced
+	pub(crate) request_line: Option<String>,
+	// Request target without the fragment, e.g. "/index.html?lang=en"
+	pub(crate) target: Option<String>,
+	// Fragment cut off the request target, without the "#"
+	pub(crate) fragment: Option<String>,
+	// Protocol version from the request line, e.g. "HTTP/1.1"
+	pub(crate) http_version: Option<String>,
+	// Header fields in the order and casing the client sent them
+	pub(crate) headers: Vec<(String, String)>,
+	// Value of a valid Content-Length header, i.e. the length of the body
+	pub(crate) content_length: Option<usize>,
+	// Length of the head including the terminating empty line, i.e. where the
+	// body starts; the length of the whole input if the head is incomplete
+	pub(crate) len: usize,
+	// Whether the head was terminated by an empty line
+	pub(crate) complete: bool,
+	// Unusual things which were tolerated, in the order they were found
+	pub(crate) warnings: Vec<ParseWarning>,
+}
+
+/// Something unusual in a request which the parser tolerated, returned by
+/// ``Client::warnings``.
+///
+/// Such requests are still handled as well as possible, like most servers do;
+/// security-conscious applications may reject them instead, e.g. with
+/// ``400 Bad Request``, since other servers or proxies in front of this one
+/// could interpret them differently.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ParseWarning {
+	/// The request line isn't valid UTF-8.
+	InvalidUtf8,
+	/// The request line doesn't consist of method, target and version separated
+	/// by single spaces, e.g. because of extra whitespace.
+	InvalidRequestLine,
+	/// The method contains characters which aren't allowed in a method.
+	InvalidMethod,
+	/// The request line has no protocol version, as in HTTP/0.9.
+	MissingVersion,
+	/// A line of the head ends with LF alone instead of CRLF.
+	BareLineFeed,
+	/// A header line without a colon, which was skipped.
+	InvalidHeaderLine(String),
+	/// A header name followed by whitespace before the colon, e.g. ``Host : example.com``.
+	WhitespaceBeforeColon(String),
+	/// A header line starting with whitespace, i.e. the obsolete continuation of
+	/// the previous line, which is ignored.
+	ObsoleteLineFolding(String),
+	/// The head didn't end with an empty line, e.g. because the client stopped sending.
+	IncompleteHead,
+	/// Several ``Content-Length`` headers with different values. ``MicroHTTP``
+	/// rejects such requests with ``400 Bad Request``.
+	ConflictingContentLength,
+	/// Both ``Content-Length`` and ``Transfer-Encoding``. ``MicroHTTP`` rejects
+	/// such requests with ``400 Bad Request``.
+	ContentLengthWithTransferEncoding,
+}
+
+impl fmt::Display for ParseWarning {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParseWarning::InvalidUtf8 => f.write_str("request line is not valid UTF-8"),
+			ParseWarning::InvalidRequestLine => f.write_str("invalid request line"),
+			ParseWarning::InvalidMethod => f.write_str("invalid method"),
+			ParseWarning::MissingVersion => f.write_str("request line without protocol version"),
+			ParseWarning::BareLineFeed => f.write_str("line ending with LF instead of CRLF"),
+			ParseWarning::InvalidHeaderLine(line) => write!(f, "invalid header line {:?}", line),
+			ParseWarning::WhitespaceBeforeColon(name) => write!(f, "whitespace after header name {:?}", name),
+			ParseWarning::ObsoleteLineFolding(line) => write!(f, "folded header line {:?}", line),
+			ParseWarning::IncompleteHead => f.write_str("incomplete header section"),
+			ParseWarning::ConflictingContentLength => f.write_str("conflicting Content-Length headers"),
+			ParseWarning::ContentLengthWithTransferEncoding => f.write_str("both Content-Length and Transfer-Encoding")
+		}
+	}
+}
+
+impl ParseWarning {
+	// Describe the warning for the log. Header lines are left out, since they
+	// may belong to headers like Cookie or Authorization.
+	pub(crate) fn redacted(&self) -> String {
+		match self {
+			ParseWarning::InvalidHeaderLine(line) => format!("invalid header line of {} bytes", line.len()),
+			ParseWarning::ObsoleteLineFolding(line) => format!("folded header line of {} bytes", line.len()),
+			warning => warning.to_string()
+		}
+	}
+}
+
+// Reasons to turn a request down based on its head alone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ParseError {
+	// The request target exceeds ParseLimits::max_target_len
+	TargetTooLong,
+	// There are more header fields than ParseLimits::max_header_count
+	TooManyHeaders,
+	// The request line reaches ParseLimits::max_head_len without ending
+	RequestLineTooLong,
+	// The header section reaches ParseLimits::max_head_len without ending
+	HeadersTooLarge,
+}
+
+impl ParseError {
+	// The status line of the response to send for this error.
+	pub(crate) fn status(&self) -> &'static str {
+		match self {
+			ParseError::TargetTooLong | ParseError::RequestLineTooLong => "414 URI Too Long",
+			ParseError::TooManyHeaders | ParseError::HeadersTooLarge => "431 Request Header Fields Too Large"
+		}
+	}
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ParseError::TargetTooLong => f.write_str("request target is too long"),
+			ParseError::TooManyHeaders => f.write_str("too many header fields"),
+			ParseError::RequestLineTooLong => f.write_str("request line is too long"),
+			ParseError::HeadersTooLarge => f.write_str("header section is too large")
+		}
+	}
+}
+
+// Parse the request head at the start of buf. Anything after the empty line
+// terminating it belongs to the body and is left alone.
+pub(crate) fn parse_request(buf: &[u8], limits: &ParseLimits) -> Result<RequestHead, ParseError> {
+	let (len, complete) = match find_header_end(buf) {
+		Some(end) => (end, true),
+		None => (buf.len(), false)
+	};
+	let buf = &buf[..len];
+
+	// Tell which part didn't end in time: an endless request line is almost
+	// always due to a huge request target.
+	if !complete && len >= limits.max_head_len {
+		return match buf.windows(2).any(|w| w == b"\r\n") {
+			true => Err(ParseError::HeadersTooLarge),
+			false => Err(ParseError::RequestLineTooLong)
+		};
+	}
+	if request_target_len(buf) > limits.max_target_len {
+		return Err(ParseError::TargetTooLong);
+	}
+	let mut warnings = request_line_warnings(buf);
+	let headers = extract_headers(buf, limits.max_header_count, &mut warnings);
+	if headers.len() > limits.max_header_count {
+		return Err(ParseError::TooManyHeaders);
+	}
+	if !complete && !buf.is_empty() {
+		warnings.push(ParseWarning::IncompleteHead);
+	}
+
+	let (target, fragment) = match extract_request_url(buf) {
+		Some((target, fragment)) => (Some(target), fragment),
+		None => (None, None)
+	};
+	let content_lengths = headers.iter()
+		.filter(|(n, _)| n.eq_ignore_ascii_case("Content-Length"))
+		.map(|(_, v)| v.as_str())
+		.collect::<Vec<&str>>();
+	if content_lengths.windows(2).any(|w| w[0] != w[1]) {
+		warnings.push(ParseWarning::ConflictingContentLength);
+	}
+	if !content_lengths.is_empty() && headers.iter().any(|(n, _)| n.eq_ignore_ascii_case("Transfer-Encoding")) {
+		warnings.push(ParseWarning::ContentLengthWithTransferEncoding);
+	}
+	let content_length = content_lengths.first().and_then(|v| v.parse().ok());
+
+	Ok(RequestHead {
+		method: extract_method(buf),
+		request_line: raw_request_line(buf),
+		target,
+		fragment,
+		http_version: extract_http_version(buf),
+		headers,
+		content_length,
+		len,
+		complete,
+		warnings,
+	})
+}
+
+// Check the request line and the line endings for anything unusual.
+fn request_line_warnings(buf: &[u8]) -> Vec<ParseWarning> {
+	let mut warnings = Vec::new();
+	if buf.is_empty() {
+		return warnings;
+	}
+
+	match request_line(buf) {
+		None => warnings.push(ParseWarning::InvalidUtf8),
+		Some(line) => {
+			// Without collecting the components, so RequestView doesn't allocate.
+			match line.split(' ').count() {
+				_ if line.split(' ').any(str::is_empty) => warnings.push(ParseWarning::InvalidRequestLine),
+				2 => warnings.push(ParseWarning::MissingVersion),
+				3 => {},
+				_ => warnings.push(ParseWarning::InvalidRequestLine)
+			}
+			if !line.is_empty() && !line.starts_with(' ') && method(buf).is_none() {
+				warnings.push(ParseWarning::InvalidMethod);
+			}
+		}
+	}
+
+	let bare_lf = buf.iter().enumerate().any(|(i, &c)| c == b'\n' && (i == 0 || buf[i - 1] != b'\r'));
+	if bare_lf {
+		warnings.push(ParseWarning::BareLineFeed);
+	}
+	warnings
+}
+
+// Return the position right after the empty line which terminates the
+// header section, or None if the header section is incomplete.
+pub(crate) fn find_header_end(buf: &[u8]) -> Option<usize> {
+	buf.windows(4)
+		.position(|w| w == b"\r\n\r\n")
+		.map(|pos| pos + 4)
+}
+
+// Return the request line, i.e. the first line of the request, or None
+// if it is not valid UTF-8.
+fn request_line(buf: &[u8]) -> Option<&str> {
+	let line_end = buf.windows(2)
+		.position(|w| w == b"\r\n")
+		.unwrap_or(buf.len());
+
+	str::from_utf8(&buf[..line_end]).ok()
+}
+
+// Return the request line, replacing invalid UTF-8, or None if it is empty.
+fn raw_request_line(buf: &[u8]) -> Option<String> {
+	let line_end = buf.windows(2)
+		.position(|w| w == b"\r\n")
+		.unwrap_or(buf.len());
+
+	match line_end {
+		0 => None,
+		end => Some(String::from_utf8_lossy(&buf[..end]).into_owned())
+	}
+}
+
+// Extract the method from the request line, e.g. "GET" for "GET / HTTP/1.1".
+fn extract_method(buf: &[u8]) -> Option<String> {
+	method(buf).map(String::from)
+}
+
+// Return the method from the request line if it is a valid token.
+fn method(buf: &[u8]) -> Option<&str> {
+	let method = request_line(buf)?.split(' ').next()?;
+	let is_token = !method.is_empty() && method.bytes()
+		.all(|c| c.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&c));
+
+	match is_token {
+		true => Some(method),
+		false => None
+	}
+}
+
+// Extract the request target from the request line, e.g. "/" for "GET / HTTP/1.1",
+// along with the fragment, which is cut off the target if the client sent one.
+fn extract_request_url(buf: &[u8]) -> Option<(String, Option<String>)> {
+	let line = request_line(buf)?;
+	let components = line.split(' ').collect::<Vec<&str>>();
+	if components.len() < 2 || components[1].is_empty() {
+		return None;
+	}
+
+	let (target, fragment) = split_fragment(components[1]);
+	Some((String::from(target), fragment.map(String::from)))
+}
+
+// Return the length of the request target in the request line, e.g. 1 for "GET / HTTP/1.1".
+fn request_target_len(buf: &[u8]) -> usize {
+	let line_end = buf.windows(2)
+		.position(|w| w == b"\r\n")
+		.unwrap_or(buf.len());
+
+	buf[..line_end].split(|&c| c == b' ')
+		.nth(1)
+		.map_or(0, |target| target.len())
+}
+
+// Extract the protocol version from the request line, e.g. "HTTP/1.1".
+fn extract_http_version(buf: &[u8]) -> Option<String> {
+	let s = String::from_utf8_lossy(buf);
+	let line = s.split("\r\n").next()?;
+
+	line.split(' ')
+		.nth(2)
+		.filter(|v| v.starts_with("HTTP/"))
+		.map(String::from)
+}
+
+// Extract the header fields in the order and casing the client sent them.
+// Stops after max_count + 1 fields, so the caller can tell that there are too many.
+// Anything unusual about the header lines is added to warnings.
+fn extract_headers(buf: &[u8], max_count: usize, warnings: &mut Vec<ParseWarning>) -> Vec<(String, String)> {
+	let s = String::from_utf8_lossy(buf);
+	split_headers(&s, max_count, warnings).into_iter()
+		.map(|(name, value)| (String::from(name), String::from(value)))
+		.collect()
+}
+
+// Split the header lines of the head into names and values, as extract_headers.
+fn split_headers<'a>(head: &'a str, max_count: usize, warnings: &mut Vec<ParseWarning>) -> Vec<(&'a str, &'a str)> {
+	let mut result = Vec::new();
+
+	// Skip the request line; the header section ends with an empty line.
+	for line in head.split("\r\n").skip(1) {
+		if line.is_empty() || result.len() > max_count {
+			break;
+		}
+		// Continuations of the previous line are dropped; they would need
+		// a copy of the value to be joined with it.
+		if line.starts_with([' ', '\t']) {
+			warnings.push(ParseWarning::ObsoleteLineFolding(line.to_string()));
+			continue;
+		}
+		match line.find(':') {
+			Some(pos) => {
+				let name = &line[..pos];
+				if name.ends_with([' ', '\t']) {
+					warnings.push(ParseWarning::WhitespaceBeforeColon(name.trim().to_string()));
+				}
+				result.push((name, line[pos + 1..].trim()));
+			},
+			None => warnings.push(ParseWarning::InvalidHeaderLine(line.to_string()))
+		}
+	}
+
+	result
+}
+
+/// A request head parsed without copying anything, created with ``RequestView::parse``.
+///
+/// All parts are slices of the buffer the request was read into, so parsing
+/// a request costs a single allocation for the list of headers, where
+/// ``Client`` needs an owned ``String`` for every part. This is useful if
+/// you read requests yourself, e.g. to pick requests apart in a tight loop.
+/// The ``request_view`` example counts the allocations of both.
+///
+/// ```
+/// use micro_http_server::RequestView;
+///
+/// let buf = b"GET /search?q=cats HTTP/1.1\r\nHost: example.com\r\n\r\n";
+/// let view = RequestView::parse(buf).unwrap();
+/// assert_eq!("GET", view.method());
+/// assert_eq!("/search", view.path());
+/// assert_eq!(Some("example.com"), view.header("host"));
+/// assert_eq!(buf.len(), view.head_len());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestView<'a> {
+	method: &'a str,
+	target: &'a str,
+	http_version: Option<&'a str>,
+	headers: Vec<(&'a str, &'a str)>,
+	warnings: Vec<ParseWarning>,
+	head_len: usize,
+}
+
+impl<'a> RequestView<'a> {
+	/// Parse the request head at the start of ``buf``; anything after the empty line
+	/// ending it, i.e. the body, is left alone.
+	///
+	/// Returns None if the head is incomplete, isn't valid UTF-8 or has no valid
+	/// request line with a method and a target. Unlike ``Client``, no limits are
+	/// applied; check the length of ``buf`` before.
+	pub fn parse(buf: &'a [u8]) -> Option<RequestView<'a>> {
+		let head_len = find_header_end(buf)?;
+		let head = str::from_utf8(&buf[..head_len]).ok()?;
+
+		let method = method(buf)?;
+		let mut components = request_line(buf)?.split(' ').skip(1);
+		let target = components.next().filter(|t| !t.is_empty())?;
+		let http_version = components.next().filter(|v| v.starts_with("HTTP/"));
+
+		// Nothing is logged here; the warnings are up to the caller.
+		let mut warnings = request_line_warnings(&buf[..head_len]);
+		let headers = split_headers(head, usize::MAX, &mut warnings);
+
+		Some(RequestView {
+			method,
+			target,
+			http_version,
+			headers,
+			warnings,
+			head_len,
+		})
+	}
+
+	/// Return the method, e.g. ``GET``.
+	pub fn method(&self) -> &'a str {
+		self.method
+	}
+
+	/// Return the request target exactly as it was sent, e.g. ``/search?q=cats``.
+	pub fn target(&self) -> &'a str {
+		self.target
+	}
+
+	/// Return the path of the request target, i.e. without the query and the
+	/// fragment, e.g. ``/search`` for ``/search?q=cats``.
+	pub fn path(&self) -> &'a str {
+		let (target, _) = split_fragment(self.target);
+		target.split_once('?').map_or(target, |(path, _)| path)
+	}
+
+	/// Return the protocol version, e.g. ``HTTP/1.1``, or None if the request
+	/// line has none.
+	pub fn http_version(&self) -> Option<&'a str> {
+		self.http_version
+	}
+
+	/// Return all header fields as ``(name, value)`` pairs, in the order and casing
+	/// they were sent.
+	pub fn headers(&self) -> &[(&'a str, &'a str)] {
+		&self.headers
+	}
+
+	/// Return the value of the first header with the given name, compared
+	/// case-insensitively, or None if there is none.
+	pub fn header(&self, name: &str) -> Option<&'a str> {
+		self.headers.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|&(_, v)| v)
+	}
+
+	/// Return the unusual things found in the request line and the header lines,
+	/// e.g. a folded header line, as for ``Client::warnings``. Unlike ``Client``,
+	/// ``RequestView`` doesn't log them. The list is empty for well-formed requests.
+	pub fn warnings(&self) -> &[ParseWarning] {
+		&self.warnings
+	}
+
+	/// Return the length of the head including the empty line ending it,
+	/// i.e. the position in the buffer where the body starts.
+	pub fn head_len(&self) -> usize {
+		self.head_len
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{extract_headers, extract_http_version, parse_request, ParseError, ParseLimits, ParseWarning, RequestHead, RequestView};
+
+	#[test]
+	fn headers_keep_order_and_casing() {
+		let data = "GET / HTTP/1.1\r\nHost: localhost\r\nX-Custom:  a b \r\naccept: */*\r\n\r\nbody: no";
+		let headers = extract_headers(data.as_bytes(), usize::MAX, &mut Vec::new());
+
+		assert_eq!(3, headers.len());
+		assert_eq!(("Host".to_string(), "localhost".to_string()), headers[0]);
+		assert_eq!(("X-Custom".to_string(), "a b".to_string()), headers[1]);
+		assert_eq!(("accept".to_string(), "*/*".to_string()), headers[2]);
+	}
+
+	#[test]
+	fn http_version() {
+		let version = extract_http_version("GET / HTTP/1.1\r\n\r\n".as_bytes());
+		assert_eq!(Some("HTTP/1.1"), version.as_deref());
+		assert_eq!(None, extract_http_version("GET /\r\n\r\n".as_bytes()));
+	}
+
+	#[test]
+	fn parse() {
+		let data = b"POST /upload?x=1#top HTTP/1.1\r\nHost: localhost\r\nContent-Length: 4\r\n\r\nBODY";
+		let head = parse_request(data, &ParseLimits::unlimited()).unwrap();
+
+		assert_eq!(RequestHead {
+			method: Some("POST".to_string()),
+			request_line: Some("POST /upload?x=1#top HTTP/1.1".to_string()),
+			target: Some("/upload?x=1".to_string()),
+			fragment: Some("top".to_string()),
+			http_version: Some("HTTP/1.1".to_string()),
+			headers: vec!(
+				("Host".to_string(), "localhost".to_string()),
+				("Content-Length".to_string(), "4".to_string())),
+			content_length: Some(4),
+			len: data.len() - 4,
+			complete: true,
+			warnings: vec!(),
+		}, head);
+	}
+
+	#[test]
+	fn parse_incomplete_or_invalid() {
+		let head = parse_request(b"GET / HTTP/1.1\r\nHost: loc", &ParseLimits::unlimited()).unwrap();
+		assert!(!head.complete);
+		assert_eq!(25, head.len);
+		assert_eq!(Some("/"), head.target.as_deref());
+
+		let head = parse_request(b"", &ParseLimits::unlimited()).unwrap();
+		assert_eq!(RequestHead::default(), head);
+
+		let head = parse_request(b"G{T  HTTP/1.1\r\nContent-Length: x\r\n\r\n", &ParseLimits::unlimited()).unwrap();
+		assert_eq!((None, None, None), (head.method, head.target, head.content_length));
+		assert!(head.complete);
+	}
+
+	#[test]
+	fn parse_limits() {
+		let limits = ParseLimits { max_target_len: 5, max_header_count: 1, max_head_len: 30 };
+
+		assert!(parse_request(b"GET /abcd HTTP/1.1\r\nA: 1\r\n\r\n", &limits).is_ok());
+		assert_eq!(Err(ParseError::TargetTooLong), parse_request(b"GET /abcde HTTP/1.1\r\n\r\n", &limits));
+		assert_eq!(Err(ParseError::TooManyHeaders), parse_request(b"GET / HTTP/1.1\r\nA: 1\r\nB: 2\r\n\r\n", &limits));
+
+		// Heads which don't end within max_head_len; shorter ones are just incomplete.
+		assert_eq!(Err(ParseError::RequestLineTooLong), parse_request(b"GET /?aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", &limits));
+		assert_eq!(Err(ParseError::HeadersTooLarge), parse_request(b"GET / HTTP/1.1\r\nA: aaaaaaaaaaaaaaaaa", &limits));
+		assert!(parse_request(b"GET / HTTP/1.1\r\nA: aaaaaaaa", &limits).is_ok());
+		assert!(parse_request(b"GET / HTTP/1.1\r\nA: aaaaaaaaaaa\r\n\r\n", &limits).is_ok());
+	}
+
+	#[test]
+	fn warnings() {
+		let warnings = |data: &[u8]| parse_request(data, &ParseLimits::unlimited()).unwrap().warnings;
+
+		assert_eq!(Vec::<ParseWarning>::new(), warnings(b""));
+		assert_eq!(Vec::<ParseWarning>::new(), warnings(b"GET / HTTP/1.1\r\nHost: a\r\n\r\n"));
+		assert_eq!(vec!(ParseWarning::MissingVersion), warnings(b"GET /\r\n\r\n"));
+		assert_eq!(vec!(ParseWarning::InvalidRequestLine), warnings(b"GET  / HTTP/1.1\r\n\r\n"));
+		assert_eq!(vec!(ParseWarning::InvalidRequestLine, ParseWarning::InvalidMethod), warnings(b"G(T / HTTP/1.1 x\r\n\r\n"));
+		assert_eq!(vec!(ParseWarning::InvalidUtf8), warnings(b"GET /\xff HTTP/1.1\r\n\r\n"));
+		assert_eq!(vec!(ParseWarning::IncompleteHead), warnings(b"GET / HTTP/1.1\r\nHost: a"));
+		assert_eq!(
+			vec!(ParseWarning::BareLineFeed, ParseWarning::WhitespaceBeforeColon("Host".to_string())),
+			warnings(b"GET / HTTP/1.1\r\nX: a\nb\r\nHost : a\r\n\r\n"));
+		assert_eq!(
+			vec!(ParseWarning::ObsoleteLineFolding(" more".to_string())),
+			warnings(b"GET / HTTP/1.1\r\nX: a\r\n more\r\n\r\n"));
+
+		let head = parse_request(b"GET / HTTP/1.1\r\nX: a\r\n\tX-Foo: b\r\n\r\n", &ParseLimits::unlimited()).unwrap();
+		assert_eq!(vec!(("X".to_string(), "a".to_string())), head.headers);
+		assert_eq!(vec!(ParseWarning::ObsoleteLineFolding("\tX-Foo: b".to_string())), head.warnings);
+
+		// Logged warnings don't contain the lines, which may hold credentials.
+		let head = parse_request(b"GET / HTTP/1.1\r\nCookie: a=1;\r\n b=secret\r\nsecret\r\n\r\n", &ParseLimits::unlimited()).unwrap();
+		let logged = head.warnings.iter().map(ParseWarning::redacted).collect::<Vec<String>>();
+		assert_eq!(vec!("folded header line of 9 bytes", "invalid header line of 6 bytes"), logged);
+		assert_eq!(
+			vec!(ParseWarning::ConflictingContentLength, ParseWarning::ContentLengthWithTransferEncoding),
+			warnings(b"POST / HTTP/1.1\r\nContent-Length: 1\r\nContent-Length: 2\r\nTransfer-Encoding: chunked\r\n\r\n"));
+	}
+
+	#[test]
+	fn view() {
+		let data = b"POST /upload?x=1#top HTTP/1.1\r\nHost: localhost\r\nX-Custom:  a b \r\n\r\nBODY";
+		let view = RequestView::parse(data).unwrap();
+		assert_eq!("POST", view.method());
+		assert_eq!("/upload?x=1#top", view.target());
+		assert_eq!("/upload", view.path());
+		assert_eq!(Some("HTTP/1.1"), view.http_version());
+		assert_eq!(&[("Host", "localhost"), ("X-Custom", "a b")], view.headers());
+		assert_eq!(Some("a b"), view.header("x-custom"));
+		assert_eq!(b"BODY", &data[view.head_len()..]);
+		assert!(view.warnings().is_empty());
+
+		let view = RequestView::parse(b"GET / HTTP/1.1\r\nHost : a\r\n folded\r\n\r\n").unwrap();
+		assert_eq!(&[("Host ", "a")], view.headers());
+		assert_eq!(&[
+			ParseWarning::WhitespaceBeforeColon("Host".to_string()),
+			ParseWarning::ObsoleteLineFolding(" folded".to_string())
+		], view.warnings());
+
+		assert_eq!(None, RequestView::parse(b"GET / HTTP/1.1\r\nHost: a"));
+		assert_eq!(None, RequestView::parse(b"GET /\xff HTTP/1.1\r\n\r\n"));
+		assert_eq!(None, RequestView::parse(b"G(T / HTTP/1.1\r\n\r\n"));
+		assert_eq!(None, RequestView::parse(b"GET\r\n\r\n"));
+		assert_eq!(None, RequestView::parse(b"\r\n\r\n"));
+		assert_eq!(None, RequestView::parse(b"GET /\r\n\r\n").unwrap().http_version());
+	}
+}
@@ -0,0 +1,74 @@
+/// The request target split into its parts, returned by ``Client::request_url``.
+///
+/// For ``/search?q=cats#results``, the path is ``/search``, the query is ``q=cats``
+/// and the fragment is ``results``. Clients shouldn't send a fragment at all, but
+/// some do; it is never part of the path or the query. All parts are returned as
+/// they were sent, i.e. still percent-encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestUrl {
+	path: String,
+	query: Option<String>,
+	fragment: Option<String>,
+}
+
+impl RequestUrl {
+	pub(crate) fn new(target: &str, fragment: Option<&str>) -> RequestUrl {
+		let (path, query) = match target.split_once('?') {
+			Some((path, query)) => (path, Some(query)),
+			None => (target, None)
+		};
+
+		RequestUrl {
+			path: path.to_string(),
+			query: query.map(String::from),
+			fragment: fragment.map(String::from),
+		}
+	}
+
+	/// Return the path, e.g. ``/search``.
+	pub fn path(&self) -> &str {
+		&self.path
+	}
+
+	/// Return the query string without the leading ``?``, e.g. ``q=cats``,
+	/// or None if the target has none.
+	pub fn query(&self) -> Option<&str> {
+		self.query.as_deref()
+	}
+
+	/// Return the fragment without the leading ``#``, or None if the client
+	/// didn't send one.
+	pub fn fragment(&self) -> Option<&str> {
+		self.fragment.as_deref()
+	}
+}
+
+// Split a request target like "/a?b#c" into the part before the fragment and the fragment.
+pub(crate) fn split_fragment(target: &str) -> (&str, Option<&str>) {
+	match target.split_once('#') {
+		Some((target, fragment)) => (target, Some(fragment)),
+		None => (target, None)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{split_fragment, RequestUrl};
+
+	#[test]
+	fn parts() {
+		let url = RequestUrl::new("/foo?x=1", Some("bar"));
+		assert_eq!("/foo", url.path());
+		assert_eq!(Some("x=1"), url.query());
+		assert_eq!(Some("bar"), url.fragment());
+
+		let url = RequestUrl::new("/a/b?", None);
+		assert_eq!("/a/b", url.path());
+		assert_eq!(Some(""), url.query());
+		assert_eq!(None, url.fragment());
+
+		assert_eq!(("/foo?x=1", Some("bar#baz")), split_fragment("/foo?x=1#bar#baz"));
+		assert_eq!(("/foo", None), split_fragment("/foo"));
+		assert_eq!(("", Some("")), split_fragment("#"));
+	}
+}
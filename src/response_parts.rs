@@ -0,0 +1,111 @@
+// Response middleware, see MicroHTTP::add_response_middleware.
+
+use std::{fmt, sync::Arc};
+
+use crate::client::Client;
+
+/// Status line and headers of a response which is about to be sent, passed to
+/// the middleware added with ``MicroHTTP::add_response_middleware``.
+///
+/// Headers are kept as complete lines like ``X-Frame-Options: DENY``, in the
+/// order they will be sent. ``Content-Length`` is not among them; it is added
+/// when the response is sent, since the body can't be changed here.
+#[derive(Debug)]
+pub struct ResponseParts {
+	pub(crate) status: String,
+	pub(crate) headers: Vec<String>,
+}
+
+// Name of a header line, e.g. "Content-Type" for "Content-Type: text/html".
+fn header_name(line: &str) -> &str {
+	line.split(':').next().unwrap_or("").trim()
+}
+
+impl ResponseParts {
+	pub(crate) fn new<'a>(status: &str, headers: impl IntoIterator<Item = &'a String>) -> ResponseParts {
+		ResponseParts {
+			status: status.to_string(),
+			headers: headers.into_iter().cloned().collect(),
+		}
+	}
+
+	/// Return the status, e.g. ``200 OK``.
+	pub fn status(&self) -> &str {
+		&self.status
+	}
+
+	/// Return the numeric status code, e.g. ``200``, or None if the status doesn't start with one.
+	pub fn status_code(&self) -> Option<u16> {
+		self.status.split(' ').next()?.parse().ok()
+	}
+
+	/// Replace the status, e.g. with ``503 Service Unavailable``.
+	pub fn set_status(&mut self, status: &str) {
+		self.status = status.to_string();
+	}
+
+	/// Return all header lines.
+	pub fn headers(&self) -> &[String] {
+		&self.headers
+	}
+
+	/// Return the value of the first header with the given name (compared
+	/// case-insensitively) or None if there is none.
+	pub fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|h| header_name(h).eq_ignore_ascii_case(name))
+			.and_then(|h| h.split_once(':'))
+			.map(|(_, value)| value.trim())
+	}
+
+	/// Add a header line, e.g. ``Strict-Transport-Security: max-age=31536000``,
+	/// keeping any header of the same name.
+	pub fn add_header(&mut self, header: &str) {
+		self.headers.push(header.to_string());
+	}
+
+	/// Set the header with the given name, replacing all headers of this name.
+	pub fn set_header(&mut self, name: &str, value: &str) {
+		self.remove_header(name);
+		self.headers.push(format!("{}: {}", name, value));
+	}
+
+	/// Remove all headers with the given name (compared case-insensitively).
+	pub fn remove_header(&mut self, name: &str) {
+		self.headers.retain(|h| !header_name(h).eq_ignore_ascii_case(name));
+	}
+}
+
+type Middleware = Arc<dyn Fn(&Client, &mut ResponseParts) + Send + Sync>;
+
+// A middleware in the settings, which need to implement Debug.
+#[derive(Clone)]
+pub(crate) struct ResponseMiddleware(pub(crate) Middleware);
+
+impl fmt::Debug for ResponseMiddleware {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str("ResponseMiddleware")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::ResponseParts;
+
+	#[test]
+	fn headers() {
+		let mut parts = ResponseParts::new("200 OK", &vec!("Content-Type: text/plain".to_string(), "X-A: 1".to_string()));
+		assert_eq!(Some(200), parts.status_code());
+		assert_eq!(Some("text/plain"), parts.header("content-type"));
+
+		parts.add_header("X-A: 2");
+		parts.set_header("Content-Type", "text/html");
+		parts.remove_header("x-missing");
+		assert_eq!(&["X-A: 1", "X-A: 2", "Content-Type: text/html"], parts.headers());
+
+		parts.remove_header("x-a");
+		parts.set_status("404 Not Found");
+		assert_eq!(&["Content-Type: text/html"], parts.headers());
+		assert_eq!("404 Not Found", parts.status());
+	}
+}
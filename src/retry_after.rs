@@ -0,0 +1,74 @@
+use std::{
+	fmt,
+	time::{Duration, SystemTime}
+};
+
+use crate::httpdate::format_http_date;
+
+/// The value of a ``Retry-After`` header, passed to ``Client::respond_retry_after``.
+///
+/// It can be created from a number of seconds or a ``Duration`` (sent as delta-seconds,
+/// e.g. ``Retry-After: 120``) or from a ``SystemTime`` (sent as HTTP-date, e.g.
+/// ``Retry-After: Fri, 31 Dec 1999 23:59:59 GMT``):
+///
+/// ```
+/// use std::time::{Duration, SystemTime};
+/// use micro_http_server::RetryAfter;
+///
+/// assert_eq!("120", RetryAfter::from(120).to_string());
+/// assert_eq!("90", RetryAfter::from(Duration::from_secs(90)).to_string());
+/// let tomorrow = RetryAfter::from(SystemTime::now() + Duration::from_secs(86400));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RetryAfter {
+	/// Retry after the given number of seconds.
+	Seconds(u64),
+	/// Retry at (or after) the given point in time.
+	Date(SystemTime),
+}
+
+impl From<u64> for RetryAfter {
+	fn from(seconds: u64) -> RetryAfter {
+		RetryAfter::Seconds(seconds)
+	}
+}
+
+impl From<Duration> for RetryAfter {
+	// Fractions of a second are rounded up, so the client doesn't retry too early.
+	fn from(duration: Duration) -> RetryAfter {
+		let seconds = duration.as_secs() + if duration.subsec_nanos() > 0 { 1 } else { 0 };
+		RetryAfter::Seconds(seconds)
+	}
+}
+
+impl From<SystemTime> for RetryAfter {
+	fn from(time: SystemTime) -> RetryAfter {
+		RetryAfter::Date(time)
+	}
+}
+
+impl fmt::Display for RetryAfter {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RetryAfter::Seconds(seconds) => write!(f, "{}", seconds),
+			RetryAfter::Date(time) => f.write_str(&format_http_date(*time))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::RetryAfter;
+	use std::time::{Duration, UNIX_EPOCH};
+
+	#[test]
+	fn header_value() {
+		assert_eq!("0", RetryAfter::from(0).to_string());
+		assert_eq!("3600", RetryAfter::from(3600).to_string());
+		assert_eq!("2", RetryAfter::from(Duration::from_millis(1500)).to_string());
+		assert_eq!("1", RetryAfter::from(Duration::from_secs(1)).to_string());
+		assert_eq!(
+			"Sun, 06 Nov 1994 08:49:37 GMT",
+			RetryAfter::from(UNIX_EPOCH + Duration::from_secs(784111777)).to_string());
+	}
+}
@@ -0,0 +1,323 @@
+use std::io;
+
+use crate::client::Client;
+
+/// Controls how the ``Router`` treats a trailing slash in the request path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+	/// ``/users`` and ``/users/`` are different paths (the default).
+	Strict,
+	/// ``/users`` and ``/users/`` are treated as the same path.
+	Merge,
+	/// A request to ``/users/`` is redirected to ``/users`` with ``301 Moved Permanently``.
+	RedirectToNoSlash,
+	/// A request to ``/users`` is redirected to ``/users/`` with ``301 Moved Permanently``.
+	RedirectToSlash,
+}
+
+// Handler which is invoked for a matching request
+type Handler = Box<dyn Fn(&mut Client) -> io::Result<usize>>;
+
+// Methods which a route registered without a specific method handles
+const ALL_METHODS: [&str; 7] = ["GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS"];
+
+// A registered route; method is None if the route handles every method.
+struct Route {
+	method: Option<String>,
+	path: String,
+	handler: Handler,
+}
+
+// Outcome of looking up a request
+#[derive(Debug, PartialEq)]
+enum Resolution {
+	Route(usize),
+	Redirect(String),
+	Options(String),
+	MethodNotAllowed(String),
+	NotFound,
+}
+
+/// A simple router which dispatches clients to handlers based on the request path and method.
+///
+/// Repeated slashes in the request path are collapsed before matching, i.e.
+/// ``//users///list`` matches the route ``/users/list``. A query string is
+/// ignored for matching. If no route matches, the fallback handler set with
+/// ``set_fallback`` is called, or ``404 Not Found`` is sent if there is none;
+/// if only the method doesn't match, ``405 Method Not Allowed`` is sent.
+///
+/// ``OPTIONS`` requests are answered automatically unless a route handles them:
+/// ``OPTIONS /path`` lists the methods registered for the path in the ``Allow``
+/// header, ``OPTIONS *`` lists all methods the router handles.
+///
+/// # Example
+///
+/// ```
+/// use micro_http_server::{MicroHTTP, Router, TrailingSlash};
+/// # use std::{io::Write, net::TcpStream};
+///
+/// let mut router = Router::new();
+/// router.set_trailing_slash(TrailingSlash::Merge);
+/// router.add("/hello", |client| client.respond_ok("Hello!".as_bytes()));
+///
+/// let server = MicroHTTP::new("127.0.0.1:3003").expect("Could not create server.");
+/// # let mut connection = TcpStream::connect("127.0.0.1:3003").unwrap();
+/// # connection.write_all("GET /hello/\r\n\r\n".as_bytes()).unwrap();
+/// let mut client = server.next_client().unwrap().unwrap();
+/// router.handle(&mut client).expect("Could not send response.");
+/// ```
+pub struct Router {
+	routes: Vec<Route>,
+	trailing_slash: TrailingSlash,
+	fallback: Option<Handler>,
+}
+
+// Collapse repeated slashes, e.g. "//a///b" becomes "/a/b".
+fn collapse_slashes(path: &str) -> String {
+	let mut result = String::with_capacity(path.len());
+	for c in path.chars() {
+		if c == '/' && result.ends_with('/') {
+			continue;
+		}
+		result.push(c);
+	}
+	result
+}
+
+// Remove a single trailing slash unless the path is the root.
+fn strip_slash(path: &str) -> &str {
+	match path.len() > 1 && path.ends_with('/') {
+		true => &path[..path.len() - 1],
+		false => path
+	}
+}
+
+impl Default for Router {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Router {
+	/// Create a new router without any routes.
+	pub fn new() -> Router {
+		Router {
+			routes: Vec::new(),
+			trailing_slash: TrailingSlash::Strict,
+			fallback: None,
+		}
+	}
+
+	/// Register a handler for the given path, regardless of the request method.
+	///
+	/// If several routes match a request, the one added first wins.
+	pub fn add(&mut self, path: &str, handler: impl Fn(&mut Client) -> io::Result<usize> + 'static) {
+		self.push(None, path, handler);
+	}
+
+	/// Register a handler for the given method and path, e.g. ``("POST", "/users")``.
+	///
+	/// If several routes match a request, the one added first wins.
+	pub fn add_method(&mut self, method: &str, path: &str, handler: impl Fn(&mut Client) -> io::Result<usize> + 'static) {
+		self.push(Some(method), path, handler);
+	}
+
+	fn push(&mut self, method: Option<&str>, path: &str, handler: impl Fn(&mut Client) -> io::Result<usize> + 'static) {
+		self.routes.push(Route {
+			method: method.map(String::from),
+			path: collapse_slashes(path),
+			handler: Box::new(handler)
+		});
+	}
+
+	/// Register a handler for requests which no route matches, e.g. to render a
+	/// custom page, to serve static files or to pass the request on to another service.
+	///
+	/// Routes always take precedence: the fallback is only called if no route has
+	/// the request path, not even after applying the ``TrailingSlash`` policy. If a
+	/// route has the path but not the method, ``405 Method Not Allowed`` is still sent
+	/// (or the ``OPTIONS`` request answered). Without a fallback, ``404 Not Found`` is sent.
+	pub fn set_fallback(&mut self, handler: impl Fn(&mut Client) -> io::Result<usize> + 'static) {
+		self.fallback = Some(Box::new(handler));
+	}
+
+	/// Select how a trailing slash in the request path is treated.
+	pub fn set_trailing_slash(&mut self, policy: TrailingSlash) {
+		self.trailing_slash = policy;
+	}
+
+	/// Dispatch the client to the matching handler.
+	///
+	/// Returns the result of the handler (or of the fallback), or of the redirect /
+	/// ``404 Not Found`` response the router sent on its own.
+	pub fn handle(&self, client: &mut Client) -> io::Result<usize> {
+		let target = client.request().clone().unwrap_or_default();
+		let (path, query) = match target.find('?') {
+			Some(pos) => target.split_at(pos),
+			None => (target.as_str(), "")
+		};
+
+		match self.resolve(client.method().unwrap_or(""), path) {
+			Resolution::Route(idx) => (self.routes[idx].handler)(client),
+			Resolution::Redirect(location) => client.respond(
+				"301 Moved Permanently",
+				&[],
+				&vec!(format!("Location: {}{}", location, query))),
+			Resolution::Options(allow) => client.respond(
+				"200 OK",
+				&[],
+				&vec!(format!("Allow: {}", allow))),
+			Resolution::MethodNotAllowed(allow) => client.respond(
+				"405 Method Not Allowed",
+				&[],
+				&vec!(format!("Allow: {}", allow))),
+			Resolution::NotFound => match self.fallback {
+				Some(ref fallback) => fallback(client),
+				None => client.respond_error("404 Not Found")
+			}
+		}
+	}
+
+	// Find the route, redirect or automatic response for the given request.
+	fn resolve(&self, method: &str, path: &str) -> Resolution {
+		if method == "OPTIONS" && path == "*" {
+			return Resolution::Options(self.allow(0..self.routes.len()));
+		}
+
+		let candidates = match self.resolve_path(path) {
+			Ok(candidates) => candidates,
+			Err(resolution) => return resolution
+		};
+
+		let matching = candidates.iter()
+			.find(|&&idx| self.routes[idx].method.as_ref().is_none_or(|m| m == method));
+		match (matching, method) {
+			(Some(&idx), _) => Resolution::Route(idx),
+			(None, "OPTIONS") => Resolution::Options(self.allow(candidates.into_iter())),
+			(None, _) => Resolution::MethodNotAllowed(self.allow(candidates.into_iter()))
+		}
+	}
+
+	// Return the value of the Allow header for the given routes.
+	fn allow(&self, routes: impl Iterator<Item = usize>) -> String {
+		let mut methods = Vec::new();
+		for idx in routes {
+			match self.routes[idx].method {
+				Some(ref method) => methods.push(method.as_str()),
+				None => methods.extend_from_slice(&ALL_METHODS)
+			}
+		}
+		methods.push("OPTIONS");
+
+		let mut unique = Vec::new();
+		for method in methods {
+			if !unique.contains(&method) {
+				unique.push(method);
+			}
+		}
+		unique.join(", ")
+	}
+
+	// Find all routes for the given request path, or the redirect / 404
+	// if there are none.
+	fn resolve_path(&self, path: &str) -> Result<Vec<usize>, Resolution> {
+		let path = collapse_slashes(path);
+		let find = |p: &str| self.routes.iter()
+			.enumerate()
+			.filter(|(_, route)| route.path == p)
+			.map(|(idx, _)| idx)
+			.collect::<Vec<usize>>();
+
+		let exact = find(&path);
+		if !exact.is_empty() {
+			return Ok(exact);
+		}
+
+		let stripped = strip_slash(&path);
+		let with_slash = format!("{}/", stripped);
+		match self.trailing_slash {
+			TrailingSlash::Strict => Err(Resolution::NotFound),
+			TrailingSlash::Merge => {
+				let merged = self.routes.iter()
+					.enumerate()
+					.filter(|(_, route)| strip_slash(&route.path) == stripped)
+					.map(|(idx, _)| idx)
+					.collect::<Vec<usize>>();
+				match merged.is_empty() {
+					true => Err(Resolution::NotFound),
+					false => Ok(merged)
+				}
+			},
+			TrailingSlash::RedirectToNoSlash if stripped != path && !find(stripped).is_empty() =>
+				Err(Resolution::Redirect(String::from(stripped))),
+			TrailingSlash::RedirectToSlash if stripped == path && !find(&with_slash).is_empty() =>
+				Err(Resolution::Redirect(with_slash)),
+			_ => Err(Resolution::NotFound)
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{Resolution, Router, TrailingSlash};
+
+	fn router(policy: TrailingSlash) -> Router {
+		let mut router = Router::new();
+		router.set_trailing_slash(policy);
+		router.add("/users", |client| client.respond_ok(&[]));
+		router.add("/files/", |client| client.respond_ok(&[]));
+		router
+	}
+
+	#[test]
+	fn strict() {
+		let router = router(TrailingSlash::Strict);
+		assert_eq!(Resolution::Route(0), router.resolve("GET", "/users"));
+		assert_eq!(Resolution::NotFound, router.resolve("GET", "/users/"));
+		assert_eq!(Resolution::Route(1), router.resolve("GET", "/files/"));
+		assert_eq!(Resolution::NotFound, router.resolve("GET", "/files"));
+	}
+
+	#[test]
+	fn merge() {
+		let router = router(TrailingSlash::Merge);
+		assert_eq!(Resolution::Route(0), router.resolve("GET", "/users/"));
+		assert_eq!(Resolution::Route(1), router.resolve("GET", "/files"));
+		assert_eq!(Resolution::NotFound, router.resolve("GET", "/other/"));
+	}
+
+	#[test]
+	fn redirect_to_no_slash() {
+		let router = router(TrailingSlash::RedirectToNoSlash);
+		assert_eq!(Resolution::Redirect("/users".into()), router.resolve("GET", "/users/"));
+		assert_eq!(Resolution::NotFound, router.resolve("GET", "/files"));
+	}
+
+	#[test]
+	fn redirect_to_slash() {
+		let router = router(TrailingSlash::RedirectToSlash);
+		assert_eq!(Resolution::Redirect("/files/".into()), router.resolve("GET", "/files"));
+		assert_eq!(Resolution::NotFound, router.resolve("GET", "/users/"));
+	}
+
+	#[test]
+	fn repeated_slashes() {
+		let router = router(TrailingSlash::Strict);
+		assert_eq!(Resolution::Route(0), router.resolve("GET", "//users"));
+		assert_eq!(Resolution::Route(1), router.resolve("GET", "/files//"));
+	}
+
+	#[test]
+	fn methods() {
+		let mut router = Router::new();
+		router.add_method("GET", "/users", |client| client.respond_ok(&[]));
+		router.add_method("POST", "/users", |client| client.respond_ok(&[]));
+		router.add_method("DELETE", "/files", |client| client.respond_ok(&[]));
+
+		assert_eq!(Resolution::Route(1), router.resolve("POST", "/users"));
+		assert_eq!(Resolution::MethodNotAllowed("GET, POST, OPTIONS".into()), router.resolve("PUT", "/users"));
+		assert_eq!(Resolution::Options("GET, POST, OPTIONS".into()), router.resolve("OPTIONS", "/users"));
+		assert_eq!(Resolution::Options("GET, POST, DELETE, OPTIONS".into()), router.resolve("OPTIONS", "*"));
+		assert_eq!(Resolution::NotFound, router.resolve("OPTIONS", "/other"));
+	}
+}
@@ -0,0 +1,206 @@
+// Mapping of request paths to files below a served root directory.
+
+use std::{fs, io};
+use std::path::{Path, PathBuf};
+
+use crate::urlencoded::{percent_decode, percent_encode};
+
+// Outcome of looking up a request path below the served root
+#[derive(Debug, PartialEq)]
+pub(crate) enum Lookup {
+	Found(PathBuf),
+	Forbidden,
+	NotFound,
+}
+
+// Normalize the request target to a path below root, returning the
+// canonicalized root along with it.
+//
+// The path is percent-decoded first, so encoded traversal like "%2e%2e%2f"
+// is caught as well. "." and ".." segments are resolved without touching the
+// file system; a path which would leave the root is forbidden.
+fn normalize(root: &Path, target: &str) -> Result<(PathBuf, PathBuf), Lookup> {
+	let path = target.split(['?', '#']).next().unwrap_or("");
+	let path = percent_decode(path, false);
+
+	if path.contains('\0') || path.contains('\\') {
+		return Err(Lookup::Forbidden);
+	}
+
+	let mut segments = Vec::new();
+	for segment in path.split('/') {
+		match segment {
+			"" | "." => continue,
+			".." => if segments.pop().is_none() {
+				return Err(Lookup::Forbidden);
+			},
+			s => segments.push(s)
+		}
+	}
+
+	let root = root.canonicalize().map_err(|_| Lookup::NotFound)?;
+	let path = segments.iter().fold(root.clone(), |p, s| p.join(s));
+	Ok((root, path))
+}
+
+// Canonicalize the path to catch symbolic links pointing outside of the root.
+fn check(root: &Path, path: &Path, is_wanted: impl Fn(&Path) -> bool) -> Lookup {
+	match path.canonicalize() {
+		Ok(path) if !path.starts_with(root) => Lookup::Forbidden,
+		Ok(path) if is_wanted(&path) => Lookup::Found(path),
+		_ => Lookup::NotFound
+	}
+}
+
+// Resolve the request target to a file below root. For a directory,
+// its index.html is used.
+pub(crate) fn resolve(root: &Path, target: &str) -> Lookup {
+	let (root, mut file) = match normalize(root, target) {
+		Ok(paths) => paths,
+		Err(lookup) => return lookup
+	};
+	if file.is_dir() {
+		file.push("index.html");
+	}
+
+	check(&root, &file, Path::is_file)
+}
+
+// Resolve the request target to a directory below root.
+pub(crate) fn resolve_dir(root: &Path, target: &str) -> Lookup {
+	match normalize(root, target) {
+		Ok((root, dir)) => check(&root, &dir, Path::is_dir),
+		Err(lookup) => lookup
+	}
+}
+
+// Escape text for use in HTML content and attribute values.
+fn escape_html(s: &str) -> String {
+	let mut result = String::with_capacity(s.len());
+	for c in s.chars() {
+		match c {
+			'&' => result += "&amp;",
+			'<' => result += "&lt;",
+			'>' => result += "&gt;",
+			'"' => result += "&quot;",
+			'\'' => result += "&#39;",
+			c => result.push(c)
+		}
+	}
+	result
+}
+
+// Render an HTML page listing the entries of the directory, with links
+// relative to the directory's URL, which has to end with a slash.
+pub(crate) fn listing(dir: &Path, url_path: &str) -> io::Result<String> {
+	let mut entries = fs::read_dir(dir)?
+		.map(|entry| {
+			let entry = entry?;
+			let is_dir = entry.file_type()?.is_dir();
+			Ok((entry.file_name().to_string_lossy().into_owned(), is_dir))
+		})
+		.collect::<io::Result<Vec<(String, bool)>>>()?;
+	// Directories first, then by name.
+	entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+	let title = escape_html(&format!("Index of {}", url_path));
+	let mut html = format!("<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{0}</title></head>\n<body>\n<h1>{0}</h1>\n<ul>\n", title);
+	if url_path != "/" {
+		html += "<li><a href=\"../\">../</a></li>\n";
+	}
+	for (name, is_dir) in entries {
+		let slash = if is_dir { "/" } else { "" };
+		html += &format!("<li><a href=\"./{}{}\">{}{}</a></li>\n",
+			percent_encode(&name), slash, escape_html(&name), slash);
+	}
+	html += "</ul>\n</body>\n</html>\n";
+
+	Ok(html)
+}
+
+// Return the gzip-compressed copy of the file, i.e. the same path plus ".gz",
+// if there is one. It has to be in the same directory after resolving symbolic
+// links, so it can't lead outside of the served root.
+pub(crate) fn gzip_sibling(path: &Path) -> Option<PathBuf> {
+	let mut gz = path.as_os_str().to_owned();
+	gz.push(".gz");
+	let gz = PathBuf::from(gz).canonicalize().ok()?;
+	let dir = path.canonicalize().ok()?.parent()?.to_path_buf();
+
+	match gz.is_file() && gz.parent() == Some(dir.as_path()) {
+		true => Some(gz),
+		false => None
+	}
+}
+
+// Guess the content type from the file extension.
+pub(crate) fn content_type(path: &Path) -> &'static str {
+	let extension = path.extension()
+		.and_then(|e| e.to_str())
+		.map(|e| e.to_ascii_lowercase());
+
+	match extension.as_deref() {
+		Some("html") | Some("htm") => "text/html; charset=utf-8",
+		Some("css") => "text/css",
+		Some("js") => "text/javascript",
+		Some("json") => "application/json",
+		Some("txt") => "text/plain; charset=utf-8",
+		Some("png") => "image/png",
+		Some("jpg") | Some("jpeg") => "image/jpeg",
+		Some("gif") => "image/gif",
+		Some("svg") => "image/svg+xml",
+		Some("ico") => "image/x-icon",
+		Some("pdf") => "application/pdf",
+		_ => "application/octet-stream"
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{listing, resolve, resolve_dir, Lookup};
+	use crate::testing::TempDir;
+	use std::fs;
+
+	#[test]
+	fn traversal() {
+		let dir = TempDir::new("traversal");
+		let root = dir.join("root");
+		fs::create_dir_all(root.join("sub")).unwrap();
+		fs::write(root.join("sub").join("file.txt"), "data").unwrap();
+		fs::write(dir.join("secret.txt"), "secret").unwrap();
+		let file = root.join("sub").join("file.txt").canonicalize().unwrap();
+
+		assert_eq!(Lookup::Found(file.clone()), resolve(&root, "/sub/file.txt"));
+		assert_eq!(Lookup::Found(file.clone()), resolve(&root, "/sub/../sub/./file.txt?x=1"));
+		assert_eq!(Lookup::Found(file), resolve(&root, "//sub//file.txt"));
+		assert_eq!(Lookup::NotFound, resolve(&root, "/missing.txt"));
+		assert_eq!(Lookup::NotFound, resolve(&root, "/sub"));
+
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/../secret.txt"));
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/sub/../../secret.txt"));
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/..%2fsecret.txt"));
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/%2e%2e%2fsecret.txt"));
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/sub/file.txt%00.png"));
+		assert_eq!(Lookup::Forbidden, resolve(&root, "/..\\secret.txt"));
+		assert_eq!(Lookup::NotFound, resolve(&root, "/etc/passwd"));
+	}
+
+	#[test]
+	fn directories() {
+		let dir = TempDir::new("directories");
+		let root = dir.join("root");
+		fs::create_dir_all(root.join("sub dir")).unwrap();
+		fs::write(root.join("a&b <c>.txt"), "data").unwrap();
+		fs::write(root.join("sub dir").join("file.txt"), "data").unwrap();
+
+		let sub = root.join("sub dir").canonicalize().unwrap();
+		assert_eq!(Lookup::Found(sub.clone()), resolve_dir(&root, "/sub%20dir/"));
+		assert_eq!(Lookup::NotFound, resolve_dir(&root, "/a&b%20<c>.txt"));
+		assert_eq!(Lookup::Forbidden, resolve_dir(&root, "/../"));
+
+		let html = listing(&root, "/").unwrap();
+		assert!(!html.contains("../"));
+		assert!(html.contains("<li><a href=\"./sub%20dir/\">sub dir/</a></li>\n<li><a href=\"./a%26b%20%3Cc%3E.txt\">a&amp;b &lt;c&gt;.txt</a></li>"));
+		assert!(listing(&sub, "/sub dir/").unwrap().contains("<a href=\"../\">../</a>"));
+	}
+}
@@ -0,0 +1,75 @@
+// Canonical reason phrases for HTTP status codes.
+
+// Return the reason phrase registered for the given status code,
+// or an empty string for unknown codes.
+pub(crate) fn reason_phrase(status: u16) -> &'static str {
+	match status {
+		100 => "Continue",
+		101 => "Switching Protocols",
+		200 => "OK",
+		201 => "Created",
+		202 => "Accepted",
+		203 => "Non-Authoritative Information",
+		204 => "No Content",
+		205 => "Reset Content",
+		206 => "Partial Content",
+		300 => "Multiple Choices",
+		301 => "Moved Permanently",
+		302 => "Found",
+		303 => "See Other",
+		304 => "Not Modified",
+		307 => "Temporary Redirect",
+		308 => "Permanent Redirect",
+		400 => "Bad Request",
+		401 => "Unauthorized",
+		403 => "Forbidden",
+		404 => "Not Found",
+		405 => "Method Not Allowed",
+		406 => "Not Acceptable",
+		408 => "Request Timeout",
+		409 => "Conflict",
+		410 => "Gone",
+		411 => "Length Required",
+		412 => "Precondition Failed",
+		413 => "Payload Too Large",
+		414 => "URI Too Long",
+		415 => "Unsupported Media Type",
+		416 => "Range Not Satisfiable",
+		417 => "Expectation Failed",
+		426 => "Upgrade Required",
+		428 => "Precondition Required",
+		429 => "Too Many Requests",
+		431 => "Request Header Fields Too Large",
+		500 => "Internal Server Error",
+		501 => "Not Implemented",
+		502 => "Bad Gateway",
+		503 => "Service Unavailable",
+		504 => "Gateway Timeout",
+		505 => "HTTP Version Not Supported",
+		_ => ""
+	}
+}
+
+// Return whether a response with the given status, e.g. "204 No Content", must
+// not have a body and therefore no Content-Length either (RFC 9110, section 6.4.1).
+pub(crate) fn forbids_body(status: &str) -> bool {
+	match status.split(' ').next().and_then(|code| code.parse::<u16>().ok()) {
+		Some(code) => (100..200).contains(&code) || code == 204 || code == 304,
+		None => false
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::forbids_body;
+
+	#[test]
+	fn bodiless() {
+		for status in ["100 Continue", "101 Switching Protocols", "204 No Content", "304 Not Modified", "204"].iter() {
+			assert!(forbids_body(status), "{}", status);
+		}
+		for status in ["200 OK", "205 Reset Content", "404 Not Found", "OK", ""].iter() {
+			assert!(!forbids_body(status), "{}", status);
+		}
+	}
+}
@@ -0,0 +1,187 @@
+// Harness for tests which do full round trips: a server answering requests in
+// a background thread, and a minimal blocking HTTP client to talk to it. Also
+// temporary directories for tests which need files.
+
+use std::{
+	fs,
+	io::{self, Read, Write},
+	net::{SocketAddr, TcpStream},
+	ops::Deref,
+	path::{Path, PathBuf},
+	process,
+	sync::{Arc, atomic::{AtomicBool, AtomicUsize, Ordering}},
+	thread,
+	time::Duration
+};
+
+use crate::{Client, MicroHTTP};
+
+// A server on a free port of 127.0.0.1 which passes every client to the handler.
+// It is stopped when dropped.
+pub(crate) struct TestServer {
+	addr: SocketAddr,
+	stop: Arc<AtomicBool>,
+	thread: Option<thread::JoinHandle<()>>,
+}
+
+impl TestServer {
+	// Start a server with default settings.
+	pub(crate) fn start(handler: impl FnMut(Client) -> io::Result<()> + Send + 'static) -> TestServer {
+		TestServer::start_with(|_| {}, handler)
+	}
+
+	// Start a server after passing it to setup, e.g. to change its settings.
+	pub(crate) fn start_with(
+		setup: impl FnOnce(&mut MicroHTTP),
+		mut handler: impl FnMut(Client) -> io::Result<()> + Send + 'static) -> TestServer
+	{
+		let (mut server, addr) = MicroHTTP::new_ephemeral("127.0.0.1").expect("Could not create server");
+		setup(&mut server);
+
+		let stop = Arc::new(AtomicBool::new(false));
+		let stopped = stop.clone();
+		let thread = thread::spawn(move || {
+			while !stopped.load(Ordering::Relaxed) {
+				match server.next_client_timeout(Duration::from_millis(10)) {
+					Ok(Some(client)) => handler(client).expect("Handler failed"),
+					Ok(None) => {},
+					Err(e) => panic!("Could not accept client: {}", e)
+				}
+			}
+		});
+
+		TestServer { addr, stop, thread: Some(thread) }
+	}
+
+	// Return the address the server listens on.
+	pub(crate) fn addr(&self) -> SocketAddr {
+		self.addr
+	}
+
+	// Return the URL of the given path on this server, e.g. "http://127.0.0.1:1234/path".
+	pub(crate) fn url(&self, path: &str) -> String {
+		format!("http://{}{}", self.addr, path)
+	}
+}
+
+impl Drop for TestServer {
+	fn drop(&mut self) {
+		self.stop.store(true, Ordering::Relaxed);
+		// Don't hide the panic of a failed handler behind a second one.
+		if let Some(Err(e)) = self.thread.take().map(thread::JoinHandle::join) {
+			if !thread::panicking() {
+				std::panic::resume_unwind(e);
+			}
+		}
+	}
+}
+
+// A response as received by the client.
+#[derive(Debug)]
+pub(crate) struct Response {
+	pub(crate) status: u16,
+	pub(crate) headers: Vec<(String, String)>,
+	pub(crate) body: Vec<u8>,
+}
+
+impl Response {
+	// Return the value of the first header with the given name.
+	pub(crate) fn header(&self, name: &str) -> Option<&str> {
+		self.headers.iter()
+			.find(|(n, _)| n.eq_ignore_ascii_case(name))
+			.map(|(_, v)| v.as_str())
+	}
+
+	pub(crate) fn text(&self) -> String {
+		String::from_utf8_lossy(&self.body).into_owned()
+	}
+}
+
+pub(crate) fn get(url: &str) -> io::Result<Response> {
+	request("GET", url, &[], &[])
+}
+
+pub(crate) fn post(url: &str, body: &[u8]) -> io::Result<Response> {
+	request("POST", url, &[], body)
+}
+
+// Send a request to an "http://host:port/path" URL and read the whole response.
+// Content-Length is added if there is a body.
+pub(crate) fn request(method: &str, url: &str, headers: &[&str], body: &[u8]) -> io::Result<Response> {
+	let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {}", msg, url));
+
+	let rest = url.strip_prefix("http://").ok_or_else(|| invalid("Not an http URL"))?;
+	let (host, path) = match rest.find('/') {
+		Some(pos) => rest.split_at(pos),
+		None => (rest, "/")
+	};
+
+	let mut head = format!("{} {} HTTP/1.1\r\nHost: {}\r\n", method, path, host);
+	for h in headers {
+		head += &format!("{}\r\n", h);
+	}
+	if !body.is_empty() {
+		head += &format!("Content-Length: {}\r\n", body.len());
+	}
+	head += "\r\n";
+
+	let mut request = head.into_bytes();
+	request.extend_from_slice(body);
+	let mut connection = TcpStream::connect(host)?;
+	connection.write_all(&request)?;
+
+	let mut data = Vec::new();
+	connection.read_to_end(&mut data)?;
+	parse_response(&data).ok_or_else(|| invalid("Invalid response from"))
+}
+
+fn parse_response(data: &[u8]) -> Option<Response> {
+	let head_end = data.windows(4).position(|w| w == b"\r\n\r\n")?;
+	let head = std::str::from_utf8(&data[..head_end]).ok()?;
+	let mut lines = head.split("\r\n");
+
+	let status = lines.next()?.split(' ').nth(1)?.parse().ok()?;
+	let headers = lines
+		.map(|line| line.split_once(':').map(|(n, v)| (n.to_string(), v.trim().to_string())))
+		.collect::<Option<Vec<_>>>()?;
+
+	Some(Response { status, headers, body: data[head_end + 4..].to_vec() })
+}
+
+// Number of temporary directories created so far, to keep their names unique.
+static TEMP_DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+// An empty directory below the system's temporary directory, which is removed
+// along with its content when dropped. The name includes the process id and
+// a counter, so tests running in parallel, even in several processes, never
+// share a directory.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+	pub(crate) fn new(name: &str) -> TempDir {
+		let count = TEMP_DIR_COUNTER.fetch_add(1, Ordering::Relaxed);
+		let path = std::env::temp_dir().join(format!("micro_http_server_{}_{}_{}", name, process::id(), count));
+		fs::create_dir_all(&path).expect("Could not create temporary directory");
+		TempDir(path)
+	}
+}
+
+impl Deref for TempDir {
+	type Target = Path;
+
+	fn deref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl AsRef<Path> for TempDir {
+	fn as_ref(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
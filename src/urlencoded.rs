@@ -0,0 +1,101 @@
+// Helpers for decoding application/x-www-form-urlencoded data, as used
+// in query strings and HTML form submissions.
+
+// Return the value of a single hex digit.
+fn hex_value(c: u8) -> Option<u8> {
+	match c {
+		b'0'..=b'9' => Some(c - b'0'),
+		b'a'..=b'f' => Some(c - b'a' + 10),
+		b'A'..=b'F' => Some(c - b'A' + 10),
+		_ => None
+	}
+}
+
+// Decode %XX escapes; if plus_as_space is set, '+' is decoded as a space.
+// Invalid escapes are kept as they are, invalid UTF-8 is replaced.
+pub(crate) fn percent_decode(s: &str, plus_as_space: bool) -> String {
+	let bytes = s.as_bytes();
+	let mut result = Vec::with_capacity(bytes.len());
+
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'%' => {
+				let hi = bytes.get(i + 1).cloned().and_then(hex_value);
+				let lo = bytes.get(i + 2).cloned().and_then(hex_value);
+				match (hi, lo) {
+					(Some(hi), Some(lo)) => {
+						result.push(hi << 4 | lo);
+						i += 3;
+						continue;
+					},
+					_ => result.push(b'%')
+				}
+			},
+			b'+' if plus_as_space => result.push(b' '),
+			c => result.push(c)
+		}
+		i += 1;
+	}
+
+	String::from_utf8_lossy(&result).into_owned()
+}
+
+// Encode everything except unreserved characters (RFC 3986) as %XX,
+// e.g. for use in a path segment.
+pub(crate) fn percent_encode(s: &str) -> String {
+	let mut result = String::with_capacity(s.len());
+	for &c in s.as_bytes() {
+		match c {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => result.push(c as char),
+			c => result += &format!("%{:02X}", c)
+		}
+	}
+	result
+}
+
+// Split "a=1&b=&c" into decoded key/value pairs, keeping their order.
+// A key without '=' gets an empty value; empty segments are skipped.
+pub(crate) fn parse_urlencoded(s: &str) -> Vec<(String, String)> {
+	s.split('&')
+		.filter(|pair| !pair.is_empty())
+		.map(|pair| match pair.find('=') {
+			Some(pos) => (percent_decode(&pair[..pos], true), percent_decode(&pair[pos + 1..], true)),
+			None => (percent_decode(pair, true), String::new())
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{parse_urlencoded, percent_decode, percent_encode};
+
+	#[test]
+	fn decode() {
+		assert_eq!("a b", percent_decode("a+b", true));
+		assert_eq!("a+b", percent_decode("a+b", false));
+		assert_eq!("ä/?", percent_decode("%C3%a4%2F%3F", true));
+		assert_eq!("100%", percent_decode("100%", true));
+		assert_eq!("%zz", percent_decode("%zz", true));
+	}
+
+	#[test]
+	fn encode() {
+		assert_eq!("a-b_c.d~e", percent_encode("a-b_c.d~e"));
+		assert_eq!("a%20b%2F%3F%25", percent_encode("a b/?%"));
+		assert_eq!("%C3%A4", percent_encode("ä"));
+	}
+
+	#[test]
+	fn pairs() {
+		let pairs = parse_urlencoded("name=John+Doe&tag=a&tag=b&empty=&flag&&x%3Dy=1");
+		assert_eq!(vec!(
+			("name".to_string(), "John Doe".to_string()),
+			("tag".to_string(), "a".to_string()),
+			("tag".to_string(), "b".to_string()),
+			("empty".to_string(), String::new()),
+			("flag".to_string(), String::new()),
+			("x=y".to_string(), "1".to_string())
+		), pairs);
+	}
+}